@@ -0,0 +1,200 @@
+use std::f64;
+use geojson::{Feature, Value};
+
+/// Signed area and centroid of a ring.
+fn ring_area_centroid(ring: &[Vec<f64>]) -> (f64, (f64, f64)) {
+    let (mut area, mut cx, mut cy) = (0.0, 0.0, 0.0);
+    for i in 0..ring.len() {
+        let (a, b) = (&ring[i], &ring[(i + 1) % ring.len()]);
+        let cross = a[0] * b[1] - b[0] * a[1];
+        area += cross;
+        cx += (a[0] + b[0]) * cross;
+        cy += (a[1] + b[1]) * cross;
+    }
+    if area == 0.0 {
+        return (0.0, (0.0, 0.0));
+    }
+    (area / 2.0, (cx / (3.0 * area), cy / (3.0 * area)))
+}
+
+/// Adds the (planar) area of a polygon, weighted by its centroid, to `total`:
+/// (area, sum of x * area, sum of y * area).
+fn accumulate_polygon(rings: &[Vec<Vec<f64>>], total: &mut (f64, f64, f64)) {
+    for (ix, ring) in rings.iter().enumerate() {
+        let (area, (x, y)) = ring_area_centroid(ring);
+        // The holes are subtracted, whatever the orientation of the rings:
+        let area = if ix == 0 { area.abs() } else { -area.abs() };
+        total.0 += area;
+        total.1 += x * area;
+        total.2 += y * area;
+    }
+}
+
+fn accumulate_area(value: &Value, total: &mut (f64, f64, f64)) {
+    match *value {
+        Value::Polygon(ref rings) => accumulate_polygon(rings, total),
+        Value::MultiPolygon(ref polygons) => {
+            for rings in polygons {
+                accumulate_polygon(rings, total);
+            }
+        }
+        Value::GeometryCollection(ref geometries) => {
+            for geom in geometries {
+                accumulate_area(&geom.value, total);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Planar area and centroid of a feature (a null area for the features
+/// without polygons).
+fn area_centroid(feature: &Feature) -> (f64, (f64, f64)) {
+    let mut total = (0.0, 0.0, 0.0);
+    if let Some(ref geom) = feature.geometry {
+        accumulate_area(&geom.value, &mut total);
+    }
+    if total.0 <= 0.0 {
+        return (0.0, (0.0, 0.0));
+    }
+    (total.0, (total.1 / total.0, total.2 / total.0))
+}
+
+fn move_positions<F: FnMut(&mut Vec<f64>)>(value: &mut Value, f: &mut F) {
+    match *value {
+        Value::Point(ref mut point) => f(point),
+        Value::MultiPoint(ref mut points) |
+        Value::LineString(ref mut points) => {
+            for point in points {
+                f(point);
+            }
+        }
+        Value::MultiLineString(ref mut rings) |
+        Value::Polygon(ref mut rings) => {
+            for ring in rings {
+                for point in ring {
+                    f(point);
+                }
+            }
+        }
+        Value::MultiPolygon(ref mut polygons) => {
+            for polygon in polygons {
+                for ring in polygon {
+                    for point in ring {
+                        f(point);
+                    }
+                }
+            }
+        }
+        Value::GeometryCollection(ref mut geometries) => {
+            for geom in geometries {
+                move_positions(&mut geom.value, f);
+            }
+        }
+    }
+}
+
+/// Force exerted by a polygon (given its centroid, radius and mass) during
+/// an iteration of the cartogram.
+struct Source {
+    centroid: (f64, f64),
+    radius: f64,
+    mass: f64,
+}
+
+/// Desired area of each feature, proportional to its value (the features without
+/// a valid value keeping their current area), the total area being unchanged.
+fn desired_areas(areas: &[f64], values: &[Option<f64>]) -> Vec<f64> {
+    let (total_area, total_value) = areas.iter()
+        .zip(values)
+        .filter(|&(&area, _)| area > 0.0)
+        .filter_map(|(&area, value)| value.map(|v| (area, v)))
+        .fold((0.0, 0.0), |acc, (area, v)| (acc.0 + area, acc.1 + v));
+    areas.iter()
+        .zip(values)
+        .map(|(&area, value)| match *value {
+                 Some(v) if total_value > 0.0 => v * total_area / total_value,
+                 _ => area,
+             })
+        .collect()
+}
+
+/// Mean relative difference between the areas of the features and their desired
+/// areas (only counting the features having an area).
+fn mean_area_error(areas: &[f64], desired: &[f64]) -> f64 {
+    let errors = areas.iter()
+        .zip(desired)
+        .filter(|&(_, &d)| d > 0.0)
+        .map(|(&a, &d)| (a - d).abs() / d)
+        .collect::<Vec<f64>>();
+    if errors.is_empty() {
+        0.0
+    } else {
+        errors.iter().sum::<f64>() / errors.len() as f64
+    }
+}
+
+/// Distorts the (projected) features so that their areas become proportional to
+/// their values, with the algorithm of Dougenik, Chrisman and Niemeyer (1985):
+/// at each iteration, every polygon pushes away (or pulls) all the positions of
+/// the map according to the difference between its area and its desired area.
+/// The positions shared by several features move identically, so the features
+/// stay contiguous. Returns the mean area error (relative to the desired areas)
+/// after the last iteration.
+pub fn cartogram(features: &mut [Feature], values: &[Option<f64>], iterations: u32) -> f64 {
+    for _ in 0..iterations {
+        let geometry = features.iter().map(area_centroid).collect::<Vec<(f64, (f64, f64))>>();
+        let areas = geometry.iter().map(|g| g.0).collect::<Vec<f64>>();
+        let desired = desired_areas(&areas, values);
+        let size_errors = areas.iter()
+            .zip(&desired)
+            .filter(|&(&a, &d)| a > 0.0 && d > 0.0)
+            .map(|(&a, &d)| a.max(d) / a.min(d))
+            .collect::<Vec<f64>>();
+        if size_errors.is_empty() {
+            break;
+        }
+        let mean_size_error = size_errors.iter().sum::<f64>() / size_errors.len() as f64;
+        let force_reduction = 1.0 / (1.0 + mean_size_error);
+        let sources = geometry.iter()
+            .zip(&desired)
+            .filter(|&(&(area, _), _)| area > 0.0)
+            .map(|(&(area, centroid), &desired)| {
+                     let radius = (area / f64::consts::PI).sqrt();
+                     Source {
+                         centroid: centroid,
+                         radius: radius,
+                         mass: (desired / f64::consts::PI).sqrt() - radius,
+                     }
+                 })
+            .collect::<Vec<Source>>();
+        let mut displace = |point: &mut Vec<f64>| {
+            let (mut dx, mut dy) = (0.0, 0.0);
+            for source in &sources {
+                let (vx, vy) = (point[0] - source.centroid.0, point[1] - source.centroid.1);
+                let distance = (vx * vx + vy * vy).sqrt();
+                if distance == 0.0 {
+                    continue;
+                }
+                let force = if distance > source.radius {
+                    source.mass * source.radius / distance
+                } else {
+                    let ratio = distance / source.radius;
+                    source.mass * ratio * ratio * (4.0 - 3.0 * ratio)
+                };
+                dx += force * force_reduction * vx / distance;
+                dy += force * force_reduction * vy / distance;
+            }
+            point[0] += dx;
+            point[1] += dy;
+        };
+        for feature in features.iter_mut() {
+            if let Some(ref mut geom) = feature.geometry {
+                move_positions(&mut geom.value, &mut displace);
+            }
+        }
+    }
+    let areas = features.iter().map(|f| area_centroid(f).0).collect::<Vec<f64>>();
+    let desired = desired_areas(&areas, values);
+    mean_area_error(&areas, &desired)
+}
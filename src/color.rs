@@ -0,0 +1,187 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// Parses a `#rgb` or `#rrggbb` color into its red, green and blue channels.
+pub fn parse_hex(color: &str) -> Option<[f64; 3]> {
+    if !color.starts_with('#') {
+        return None;
+    }
+    let text = &color[1..];
+    let channel = |hex: &str| u8::from_str_radix(hex, 16).ok().map(|v| v as f64);
+    match text.len() {
+        3 => {
+            let digit = |i: usize| channel(&text[i..i + 1]).map(|v| v * 17.0);
+            Some([digit(0)?, digit(1)?, digit(2)?])
+        }
+        6 => Some([channel(&text[0..2])?, channel(&text[2..4])?, channel(&text[4..6])?]),
+        _ => None,
+    }
+}
+
+/// Formats the channels of a color as `#rrggbb`.
+pub fn to_hex(rgb: [f64; 3]) -> String {
+    let c = |i: usize| rgb[i].round().max(0.0).min(255.0) as u8;
+    format!("#{:02x}{:02x}{:02x}", c(0), c(1), c(2))
+}
+
+/// Linear interpolation between two colors.
+pub fn mix(a: [f64; 3], b: [f64; 3], t: f64) -> [f64; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+/// Corners of the bivariate palettes (low / low, high x / low y, low x / high y
+/// and high / high), adapted from the schemes of Joshua Stevens.
+const BIVARIATE_PALETTES: [(&'static str, [&'static str; 4]); 3] =
+    [("TealPink", ["#e8e8e8", "#5ac8c8", "#be64ac", "#3b4994"]),
+     ("GreenBlue", ["#e8e8e8", "#73ae80", "#6c83b5", "#2a5a5b"]),
+     ("RedBlue", ["#e8e8e8", "#c85a5a", "#64acbe", "#574249"])];
+
+/// Colors of a `nb_class` × `nb_class` bivariate palette, row by row from the
+/// lowest class of y, each row going from the lowest to the highest class of x.
+pub fn bivariate_palette(name: &str, nb_class: usize) -> Option<Vec<String>> {
+    let corners = BIVARIATE_PALETTES.iter().find(|p| p.0 == name)?.1;
+    let corners = [parse_hex(corners[0])?,
+                   parse_hex(corners[1])?,
+                   parse_hex(corners[2])?,
+                   parse_hex(corners[3])?];
+    let step = |ix: usize| if nb_class > 1 { ix as f64 / (nb_class - 1) as f64 } else { 0.0 };
+    let mut colors = Vec::with_capacity(nb_class * nb_class);
+    for iy in 0..nb_class {
+        let low_x = mix(corners[0], corners[2], step(iy));
+        let high_x = mix(corners[1], corners[3], step(iy));
+        for ix in 0..nb_class {
+            colors.push(to_hex(mix(low_x, high_x, step(ix))));
+        }
+    }
+    Some(colors)
+}
+
+/// How the values are mapped on a continuous color scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+    Linear,
+    /// Linear on the logarithm of the values (which should be positive).
+    Log,
+    /// Linear on the rank of the values (each color being used by as many features).
+    Quantile,
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Interpolation::Linear),
+            "log" => Ok(Interpolation::Log),
+            "quantile" => Ok(Interpolation::Quantile),
+            _ => {
+                Err(format!("Invalid interpolation: {} (expected \"linear\", \"log\" or \"quantile\")",
+                            s))
+            }
+        }
+    }
+}
+
+/// Continuous color scale, interpolating between evenly spaced color stops.
+pub struct ColorScale {
+    interpolation: Interpolation,
+    stops: Vec<[f64; 3]>,
+    /// Sorted values (to compute the ranks of the quantile interpolation).
+    sorted: Vec<f64>,
+}
+
+impl ColorScale {
+    /// Creates the scale of the given values (not empty, and positive for a
+    /// logarithmic interpolation) and colors (at least two `#rrggbb` colors).
+    pub fn new(values: &[f64],
+               colors: &[String],
+               interpolation: Interpolation)
+               -> Result<ColorScale, String> {
+        if colors.len() < 2 {
+            return Err("At least two colors are needed by a continuous color scale!".to_string());
+        }
+        let stops = colors.iter()
+            .map(|c| {
+                     parse_hex(c).ok_or_else(|| format!("Invalid color: {} (expected \"#rrggbb\")", c))
+                 })
+            .collect::<Result<Vec<[f64; 3]>, String>>()?;
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Ok(ColorScale {
+               interpolation: interpolation,
+               stops: stops,
+               sorted: sorted,
+           })
+    }
+
+    fn min(&self) -> f64 {
+        self.sorted[0]
+    }
+
+    fn max(&self) -> f64 {
+        self.sorted[self.sorted.len() - 1]
+    }
+
+    /// Number of sorted values for which the predicate holds, the predicate
+    /// being true for the lowest values and false for the others.
+    fn count_while<F: Fn(f64) -> bool>(&self, predicate: F) -> usize {
+        self.sorted
+            .binary_search_by(|&v| if predicate(v) {
+                                  Ordering::Less
+                              } else {
+                                  Ordering::Greater
+                              })
+            .unwrap_err()
+    }
+
+    /// Position (between 0 and 1) of a value on the scale.
+    pub fn position(&self, value: f64) -> f64 {
+        let (min, max) = (self.min(), self.max());
+        let t = match self.interpolation {
+            _ if min == max => 0.0,
+            Interpolation::Linear => (value - min) / (max - min),
+            Interpolation::Log => (value.ln() - min.ln()) / (max.ln() - min.ln()),
+            Interpolation::Quantile => {
+                // Mean rank of the values equal to `value`:
+                let below = self.count_while(|v| v < value) as f64;
+                let not_above = self.count_while(|v| v <= value) as f64;
+                ((below + not_above - 1.0) / 2.0).max(0.0) / (self.sorted.len() - 1) as f64
+            }
+        };
+        t.max(0.0).min(1.0)
+    }
+
+    /// Value at a position of the scale (the inverse of `position`).
+    pub fn value_at(&self, t: f64) -> f64 {
+        let (min, max) = (self.min(), self.max());
+        match self.interpolation {
+            Interpolation::Linear => min + (max - min) * t,
+            Interpolation::Log => (min.ln() + (max.ln() - min.ln()) * t).exp(),
+            Interpolation::Quantile => {
+                let rank = t * (self.sorted.len() - 1) as f64;
+                let (ix, frac) = (rank.floor() as usize, rank.fract());
+                match self.sorted.get(ix + 1) {
+                    Some(next) => self.sorted[ix] + (next - self.sorted[ix]) * frac,
+                    None => self.sorted[ix],
+                }
+            }
+        }
+    }
+
+    /// Color of a position of the scale.
+    pub fn color_at(&self, t: f64) -> String {
+        let last = self.stops.len() - 1;
+        let position = t * last as f64;
+        let ix = (position.floor() as usize).min(last - 1);
+        to_hex(mix(self.stops[ix], self.stops[ix + 1], position - ix as f64))
+    }
+
+    pub fn color(&self, value: f64) -> String {
+        self.color_at(self.position(value))
+    }
+
+    /// Colors of the stops, as `#rrggbb`.
+    pub fn stops(&self) -> Vec<String> {
+        self.stops.iter().map(|&c| to_hex(c)).collect()
+    }
+}
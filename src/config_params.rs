@@ -1,3 +1,12 @@
+use std::collections::BTreeMap;
+use std::f64;
+use std::path::Path;
+use std::str::FromStr;
+use serde_json::{Map, Value as JsonValue};
+use toml;
+
+use expression::Expr;
+
 #[derive(Debug, Default, Clone)]
 pub struct MapExtent {
     pub left: f64,
@@ -5,3 +14,423 @@ pub struct MapExtent {
     pub bottom: f64,
     pub top: f64,
 }
+
+impl MapExtent {
+    /// Returns an "inverted" extent, to be grown with `expand` or `union`.
+    pub fn empty() -> Self {
+        MapExtent {
+            left: f64::MAX,
+            right: f64::MIN,
+            bottom: f64::MAX,
+            top: f64::MIN,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left > self.right || self.bottom > self.top
+    }
+
+    pub fn width(&self) -> f64 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> f64 {
+        self.top - self.bottom
+    }
+
+    pub fn expand(&mut self, x: f64, y: f64) {
+        if x > self.right {
+            self.right = x;
+        }
+        if x < self.left {
+            self.left = x;
+        }
+        if y > self.top {
+            self.top = y;
+        }
+        if y < self.bottom {
+            self.bottom = y;
+        }
+    }
+
+    pub fn union(&self, other: &MapExtent) -> MapExtent {
+        MapExtent {
+            left: self.left.min(other.left),
+            right: self.right.max(other.right),
+            bottom: self.bottom.min(other.bottom),
+            top: self.top.max(other.top),
+        }
+    }
+
+    pub fn intersects(&self, other: &MapExtent) -> bool {
+        self.left <= other.right && other.left <= self.right && self.bottom <= other.top &&
+        other.bottom <= self.top
+    }
+
+    /// Gives a size to the dimensions of a degenerate extent (like the extent of a
+    /// single point, or of points on a vertical line), keeping it centered: an empty
+    /// dimension takes the size of the other one, or `span` if both are empty.
+    pub fn with_minimum_size(&self, span: f64) -> MapExtent {
+        let (width, height) = (self.width(), self.height());
+        let width = if width > 0.0 {
+            width
+        } else if height > 0.0 {
+            height
+        } else {
+            span
+        };
+        let height = if height > 0.0 { height } else { width };
+        let (cx, cy) = ((self.left + self.right) / 2.0, (self.bottom + self.top) / 2.0);
+        MapExtent {
+            left: cx - width / 2.0,
+            right: cx + width / 2.0,
+            bottom: cy - height / 2.0,
+            top: cy + height / 2.0,
+        }
+    }
+
+    /// Height (in pixels) of a viewport of the given width matching the aspect
+    /// ratio of the extent, once padded.
+    pub fn fitting_height(&self, width: u32, padding: Option<&Padding>) -> u32 {
+        let (w, h, px) = match padding {
+            Some(&Padding::Percent(pct)) => {
+                (self.width() * (1.0 + pct / 50.0), self.height() * (1.0 + pct / 50.0), 0.0)
+            }
+            Some(&Padding::Pixels(px)) => (self.width(), self.height(), px),
+            None => (self.width(), self.height(), 0.0),
+        };
+        let avail_width = (width as f64 - 2.0 * px).max(1.0);
+        (h * avail_width / w + 2.0 * px).round().max(1.0) as u32
+    }
+
+    /// Grows the extent by the given padding, `viewport` being the size
+    /// (in pixels) of the map in which the extent will be drawn.
+    pub fn padded(&self, padding: &Padding, viewport: (u32, u32)) -> MapExtent {
+        let (dx, dy) = match *padding {
+            Padding::Percent(pct) => (self.width() * pct / 100.0, self.height() * pct / 100.0),
+            Padding::Pixels(px) => {
+                let avail_width = (viewport.0 as f64 - 2.0 * px).max(1.0);
+                let avail_height = (viewport.1 as f64 - 2.0 * px).max(1.0);
+                let res = (self.width() / avail_width).max(self.height() / avail_height);
+                (px * res, px * res)
+            }
+        };
+        MapExtent {
+            left: self.left - dx,
+            right: self.right + dx,
+            bottom: self.bottom - dy,
+            top: self.top + dy,
+        }
+    }
+}
+
+/// Space to keep around the features used to compute the extent.
+#[derive(Debug, Clone)]
+pub enum Padding {
+    Percent(f64),
+    Pixels(f64),
+}
+
+impl Padding {
+    /// Reads a padding value, either a number (a percentage of the extent)
+    /// or a string like `"10%"` or `"25px"`.
+    pub fn from_config(value: &toml::Value) -> Option<Self> {
+        match *value {
+            toml::Value::Integer(v) => Some(Padding::Percent(v as f64)),
+            toml::Value::Float(v) => Some(Padding::Percent(v)),
+            toml::Value::String(ref s) => {
+                let s = s.trim();
+                if s.ends_with("px") {
+                    s[..s.len() - 2].trim().parse::<f64>().ok().map(Padding::Pixels)
+                } else if s.ends_with('%') {
+                    s[..s.len() - 1].trim().parse::<f64>().ok().map(Padding::Percent)
+                } else {
+                    s.parse::<f64>().ok().map(Padding::Percent)
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// How the extent is fitted into the viewport.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FitMode {
+    /// The whole extent is visible, the map is centered on the smallest dimension.
+    Contain,
+    /// The whole viewport is filled, the extent is cropped on the largest dimension.
+    Cover,
+    /// The extent is stretched to the viewport, without preserving the aspect ratio.
+    Stretch,
+}
+
+impl FromStr for FitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "contain" => Ok(FitMode::Contain),
+            "cover" => Ok(FitMode::Cover),
+            "stretch" => Ok(FitMode::Stretch),
+            _ => Err(format!("Invalid fit mode: {}", s)),
+        }
+    }
+}
+
+/// Format of the output file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Svg,
+    Png,
+    Pdf,
+}
+
+impl OutputFormat {
+    /// Guesses the format from the extension of the output file (SVG by default).
+    pub fn from_path(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .unwrap_or_default();
+        extension.parse::<OutputFormat>().unwrap_or(OutputFormat::Svg)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "svg" => Ok(OutputFormat::Svg),
+            "png" => Ok(OutputFormat::Png),
+            "pdf" => Ok(OutputFormat::Pdf),
+            _ => Err(format!("Invalid output format: {}", s)),
+        }
+    }
+}
+
+/// Reads the zoom factor to apply when rasterizing the map, either from a
+/// `png-scale` key or from a `png-dpi` key (the SVG being drawn at 96 dpi).
+pub fn raster_zoom(c: &BTreeMap<String, toml::Value>) -> Result<f64, String> {
+    let zoom = match (c.get("png-scale"), c.get("png-dpi")) {
+        (Some(_), Some(_)) => {
+            return Err("Only one of \"png-scale\" and \"png-dpi\" can be provided!".to_string())
+        }
+        (Some(scale), None) => {
+            as_number(scale).ok_or_else(|| "\"png-scale\" should be a number!".to_string())?
+        }
+        (None, Some(dpi)) => {
+            as_number(dpi).ok_or_else(|| "\"png-dpi\" should be a number!".to_string())? / 96.0
+        }
+        (None, None) => 1.0,
+    };
+    if zoom > 0.0 {
+        Ok(zoom)
+    } else {
+        Err("The raster scale should be a positive number!".to_string())
+    }
+}
+
+/// Resolution (in dots per inch) at which the map is printed, so that its
+/// physical size is the `physical-width` of the map (if any), or its size
+/// in pixels at `dpi` (96 by default).
+pub fn print_dpi(c: &BTreeMap<String, toml::Value>) -> Result<f64, String> {
+    match (c.get("dpi"), c.get("physical-width")) {
+        (Some(_), Some(_)) => Err("Only one of \"dpi\" and \"physical-width\" can be provided!".to_string()),
+        (Some(dpi), None) => {
+            match as_number(dpi) {
+                Some(dpi) if dpi > 0.0 && dpi.is_finite() => Ok(dpi),
+                _ => Err("\"dpi\" should be a positive number!".to_string()),
+            }
+        }
+        (None, Some(&toml::Value::String(ref length))) => {
+            let width = c.get("width").and_then(|w| w.as_integer()).unwrap_or(0);
+            if width <= 0 {
+                return Err("\"width\" is required to use \"physical-width\"!".to_string());
+            }
+            let meters = parse_length(length)
+                .ok_or_else(|| format!("Invalid \"physical-width\" value: {}", length))?;
+            Ok(width as f64 * 0.0254 / meters)
+        }
+        (None, Some(_)) => Err("\"physical-width\" should be a string like \"20cm\"!".to_string()),
+        (None, None) => Ok(96.0),
+    }
+}
+
+/// Scale of a map framed by its center.
+#[derive(Debug, Clone)]
+pub enum MapScale {
+    /// Scale denominator and size (in meters) of a pixel on the output medium.
+    Denominator(f64, f64),
+    /// Zoom level, as used by web-mercator tiles: the resolution is the one of
+    /// the tiles at the equator, whatever the projection of the map.
+    Zoom(f64),
+}
+
+impl MapScale {
+    /// Reads the scale from the `[map]` section, either from a `zoom` key or from
+    /// a `scale` key (with `dpi` or `physical-width` to know the size of a pixel).
+    pub fn from_config(c: &BTreeMap<String, toml::Value>) -> Result<Self, String> {
+        match (c.get("scale"), c.get("zoom")) {
+            (Some(_), Some(_)) => Err("Only one of \"scale\" and \"zoom\" can be provided!".to_string()),
+            (None, Some(zoom)) => {
+                as_number(zoom)
+                    .map(MapScale::Zoom)
+                    .ok_or_else(|| "\"zoom\" should be a number!".to_string())
+            }
+            (Some(scale), None) => {
+                let scale = as_number(scale)
+                    .ok_or_else(|| "\"scale\" should be a number (the scale denominator)!".to_string())?;
+                let pixel_size = 0.0254 / print_dpi(c)?;
+                Ok(MapScale::Denominator(scale, pixel_size))
+            }
+            (None, None) => Err("\"scale\" or \"zoom\" is required when using \"center\"!".to_string()),
+        }
+    }
+
+    /// Size of a pixel, in map units (meters). For a zoom level, this is the size
+    /// of a pixel at the equator in Web Mercator.
+    pub fn resolution(&self) -> f64 {
+        match *self {
+            MapScale::Denominator(scale, pixel_size) => scale * pixel_size,
+            MapScale::Zoom(zoom) => 2.0 * f64::consts::PI * 6378137.0 / (256.0 * 2f64.powf(zoom)),
+        }
+    }
+}
+
+pub fn as_number(value: &toml::Value) -> Option<f64> {
+    match *value {
+        toml::Value::Integer(v) => Some(v as f64),
+        toml::Value::Float(v) => Some(v),
+        _ => None,
+    }
+}
+
+/// Reads an optional list of numbers, like `breaks = [0, 10, 100]`.
+pub fn number_list(value: Option<&toml::Value>, name: &str) -> Result<Option<Vec<f64>>, String> {
+    match value {
+        Some(&toml::Value::Array(ref values)) => {
+            values.iter()
+                .map(as_number)
+                .collect::<Option<Vec<f64>>>()
+                .map(Some)
+                .ok_or_else(|| format!("\"{}\" should be a list of numbers!", name))
+        }
+        Some(_) => Err(format!("\"{}\" should be a list of numbers!", name)),
+        None => Ok(None),
+    }
+}
+
+/// Reads an optional list of strings, like `colors = ["#fee5d9", "#a50f15"]`.
+pub fn string_list(value: Option<&toml::Value>, name: &str) -> Result<Option<Vec<String>>, String> {
+    match value {
+        Some(&toml::Value::Array(ref values)) => {
+            values.iter()
+                .map(|v| v.as_str().map(|v| v.to_string()))
+                .collect::<Option<Vec<String>>>()
+                .map(Some)
+                .ok_or_else(|| format!("\"{}\" should be a list of strings!", name))
+        }
+        Some(_) => Err(format!("\"{}\" should be a list of strings!", name)),
+        None => Ok(None),
+    }
+}
+
+/// Parses a physical length (like `"20cm"`, `"8.5in"` or `"210mm"`) into meters,
+/// which should be positive.
+pub fn parse_length(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let units = [("mm", 0.001), ("cm", 0.01), ("in", 0.0254), ("pt", 0.0254 / 72.0), ("m", 1.0)];
+    for &(suffix, factor) in &units {
+        if value.ends_with(suffix) {
+            return value[..value.len() - suffix.len()]
+                       .trim()
+                       .parse::<f64>()
+                       .ok()
+                       .map(|v| v * factor)
+                       .filter(|v| *v > 0.0 && v.is_finite());
+        }
+    }
+    None
+}
+
+/// Value of a style property: either a constant, or an expression (a string
+/// starting with `=`, like `"= 0.5 + lanes * 0.3"`) evaluated for each feature.
+#[derive(Debug, Clone)]
+pub enum StyleValue {
+    Constant(String),
+    /// Expression, with the value to use when it evaluates to null.
+    Expression(Expr, String),
+}
+
+impl StyleValue {
+    pub fn from_config(value: Option<&toml::Value>, default: &str) -> Result<Self, String> {
+        match value {
+            Some(&toml::Value::String(ref v)) if v.trim_start().starts_with('=') => {
+                let expr = v.trim_start()[1..].parse::<Expr>()?;
+                Ok(StyleValue::Expression(expr, default.to_string()))
+            }
+            Some(&toml::Value::String(ref v)) => Ok(StyleValue::Constant(v.clone())),
+            Some(&toml::Value::Integer(v)) => Ok(StyleValue::Constant(v.to_string())),
+            Some(&toml::Value::Float(v)) => Ok(StyleValue::Constant(v.to_string())),
+            Some(_) => Err("Style values should be strings or numbers!".to_string()),
+            None => Ok(StyleValue::Constant(default.to_string())),
+        }
+    }
+
+    /// Value of the property, if it doesn't depend on the features.
+    pub fn constant(&self) -> Option<&str> {
+        match *self {
+            StyleValue::Constant(ref v) => Some(v),
+            StyleValue::Expression(..) => None,
+        }
+    }
+
+    /// Value of the property for a feature.
+    pub fn evaluate(&self, properties: &Map<String, JsonValue>) -> String {
+        match *self {
+            StyleValue::Constant(ref v) => v.clone(),
+            StyleValue::Expression(ref expr, ref default) => {
+                match expr.evaluate(properties) {
+                    JsonValue::String(v) => v,
+                    JsonValue::Number(v) => v.to_string(),
+                    JsonValue::Bool(v) => v.to_string(),
+                    _ => default.clone(),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_options(entries: &[(&str, toml::Value)]) -> BTreeMap<String, toml::Value> {
+        entries.iter().map(|&(k, ref v)| (k.to_string(), v.clone())).collect()
+    }
+
+    #[test]
+    fn parses_positive_lengths() {
+        assert_eq!(parse_length("20cm"), Some(0.2));
+        assert_eq!(parse_length(" 2 m"), Some(2.0));
+        assert_eq!(parse_length("0mm"), None);
+        assert_eq!(parse_length("-5in"), None);
+        assert_eq!(parse_length("20"), None);
+    }
+
+    #[test]
+    fn computes_the_print_resolution() {
+        assert_eq!(print_dpi(&map_options(&[])), Ok(96.0));
+        assert_eq!(print_dpi(&map_options(&[("dpi", toml::Value::Integer(300))])), Ok(300.0));
+        assert!(print_dpi(&map_options(&[("dpi", toml::Value::Integer(0))])).is_err());
+        assert!(print_dpi(&map_options(&[("dpi", toml::Value::Float(-72.0))])).is_err());
+        let options = map_options(&[("width", toml::Value::Integer(1000)),
+                                    ("physical-width", toml::Value::String("10in".to_string()))]);
+        assert_eq!(print_dpi(&options), Ok(100.0));
+        let options = map_options(&[("width", toml::Value::Integer(1000)),
+                                    ("physical-width", toml::Value::String("0cm".to_string()))]);
+        assert!(print_dpi(&options).is_err());
+    }
+}
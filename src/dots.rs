@@ -0,0 +1,178 @@
+use geojson::Value;
+
+/// Pseudo-random number generator (SplitMix64), so that the same seed always
+/// gives the same dots.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform number in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// Maximum number of random positions tried to place a dot in a polygon.
+const MAX_ATTEMPTS: usize = 1000;
+
+/// Maximum number of dots drawn for a field of a feature.
+pub const MAX_DOTS: usize = 1_000_000;
+
+fn ring_area(ring: &[Vec<f64>]) -> f64 {
+    let mut sum = 0.0;
+    for i in 0..ring.len() {
+        let (a, b) = (&ring[i], &ring[(i + 1) % ring.len()]);
+        sum += a[0] * b[1] - b[0] * a[1];
+    }
+    (sum / 2.0).abs()
+}
+
+fn polygon_area(rings: &[Vec<Vec<f64>>]) -> f64 {
+    rings.iter()
+        .enumerate()
+        .map(|(ix, ring)| if ix == 0 { ring_area(ring) } else { -ring_area(ring) })
+        .sum::<f64>()
+        .max(0.0)
+}
+
+/// Whether a point is inside a polygon (inside its exterior ring and outside
+/// its holes), using the even-odd rule.
+fn contains(rings: &[Vec<Vec<f64>>], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    for ring in rings {
+        let n = ring.len();
+        for i in 0..n {
+            let (a, b) = (&ring[i], &ring[(i + n - 1) % n]);
+            if (a[1] > y) != (b[1] > y) && x < (b[0] - a[0]) * (y - a[1]) / (b[1] - a[1]) + a[0] {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+fn add_polygon<'a>(rings: &'a [Vec<Vec<f64>>], polygons: &mut Vec<(&'a [Vec<Vec<f64>>], f64)>) {
+    let area = polygon_area(rings);
+    if area > 0.0 {
+        polygons.push((rings, area));
+    }
+}
+
+/// Collects the polygons of a geometry with their area, skipping the empty or flat
+/// ones (in which no dot can be placed).
+fn collect_polygons<'a>(value: &'a Value, polygons: &mut Vec<(&'a [Vec<Vec<f64>>], f64)>) {
+    match *value {
+        Value::Polygon(ref rings) => add_polygon(rings, polygons),
+        Value::MultiPolygon(ref parts) => {
+            for part in parts {
+                add_polygon(part, polygons);
+            }
+        }
+        Value::GeometryCollection(ref geometries) => {
+            for geom in geometries {
+                collect_polygons(&geom.value, polygons);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Draws `nb_points` random points inside the polygons of a geometry (and outside
+/// their holes), each polygon getting a share of the points proportional to its
+/// area. Fewer points are returned if some of them couldn't be placed.
+pub fn random_points(value: &Value, nb_points: usize, rng: &mut Rng) -> Vec<[f64; 2]> {
+    let mut polygons = Vec::new();
+    collect_polygons(value, &mut polygons);
+    let total_area = polygons.iter().map(|p| p.1).sum::<f64>();
+    // The number of points comes from the data, so it isn't used to reserve memory:
+    let mut points = Vec::new();
+    if polygons.is_empty() {
+        return points;
+    }
+    for _ in 0..nb_points {
+        // Pick a polygon according to its area, then a position in its bounding box:
+        let mut target = rng.next_f64() * total_area;
+        let ix = polygons.iter()
+            .position(|&(_, area)| {
+                          target -= area;
+                          target < 0.0
+                      })
+            .unwrap_or(polygons.len() - 1);
+        let rings = polygons[ix].0;
+        let (mut left, mut right, mut bottom, mut top) = (f64::MAX, f64::MIN, f64::MAX, f64::MIN);
+        for point in &rings[0] {
+            left = left.min(point[0]);
+            right = right.max(point[0]);
+            bottom = bottom.min(point[1]);
+            top = top.max(point[1]);
+        }
+        for _ in 0..MAX_ATTEMPTS {
+            let x = left + rng.next_f64() * (right - left);
+            let y = bottom + rng.next_f64() * (top - bottom);
+            if contains(rings, x, y) {
+                points.push([x, y]);
+                break;
+            }
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use geojson::Geometry;
+    use super::*;
+
+    fn square(left: f64, bottom: f64, size: f64) -> Vec<Vec<f64>> {
+        vec![vec![left, bottom],
+             vec![left + size, bottom],
+             vec![left + size, bottom + size],
+             vec![left, bottom + size],
+             vec![left, bottom]]
+    }
+
+    #[test]
+    fn places_the_points_inside_the_polygons() {
+        let value = Value::Polygon(vec![square(0.0, 0.0, 4.0), square(1.0, 1.0, 2.0)]);
+        let points = random_points(&value, 50, &mut Rng::new(1));
+        assert_eq!(points.len(), 50);
+        for p in points {
+            assert!(p[0] >= 0.0 && p[0] <= 4.0 && p[1] >= 0.0 && p[1] <= 4.0);
+            assert!(!(p[0] > 1.0 && p[0] < 3.0 && p[1] > 1.0 && p[1] < 3.0));
+        }
+    }
+
+    #[test]
+    fn skips_the_empty_and_flat_polygons() {
+        let flat = vec![vec![0.0, 0.0], vec![1.0, 1.0], vec![2.0, 2.0], vec![0.0, 0.0]];
+        let value = Value::GeometryCollection(vec![Geometry::new(Value::Polygon(vec![])),
+                                                   Geometry::new(Value::MultiPolygon(vec![vec![vec![]],
+                                                                                          vec![flat]]))]);
+        assert!(random_points(&value, 10, &mut Rng::new(1)).is_empty());
+
+        let value = Value::MultiPolygon(vec![vec![], vec![square(10.0, 10.0, 1.0)]]);
+        let points = random_points(&value, 10, &mut Rng::new(1));
+        assert_eq!(points.len(), 10);
+        assert!(points.iter().all(|p| p[0] >= 10.0 && p[1] >= 10.0));
+    }
+}
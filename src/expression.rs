@@ -0,0 +1,850 @@
+use std::cmp::Ordering;
+use std::f64;
+use std::str::FromStr;
+use serde_json::{Map, Value as JsonValue};
+
+use color::{mix, parse_hex, to_hex};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Expression over the properties of a feature, like
+/// `continent = 'Africa' and pop_est > 1000000`.
+///
+/// The supported syntax is:
+///
+/// - literals: numbers, `'strings'`, `true`, `false` and `null`,
+/// - properties: bare names (`pop_est`) or double-quoted names (`"Pop. 2017"`),
+/// - comparisons: `=` (or `==`), `!=` (or `<>`), `<`, `<=`, `>` and `>=`,
+/// - `value in (a, b, ...)` and `value not in (a, b, ...)`,
+/// - `value is null` and `value is not null`,
+/// - string matching with `like` (case sensitive) and `ilike` (case insensitive),
+///   where `%` matches any sequence of characters and `_` any single character,
+/// - `and`, `or`, `not` and parentheses,
+/// - arithmetic: `+`, `-`, `*`, `/` and `%` (`+` concatenates strings),
+/// - functions:
+///   - `get('name')`: value of a property,
+///   - `match(input, label_1, output_1, label_2, output_2, ..., default)`,
+///   - `case(condition_1, output_1, condition_2, output_2, ..., default)`,
+///   - `interpolate(input, stop_1, output_1, stop_2, output_2, ...)`: linear
+///     interpolation between numbers or colors (like `'#ff0000'`),
+///   - `step(input, output_0, stop_1, output_1, stop_2, output_2, ...)`: output of
+///     the last stop lower than or equal to the input (`output_0` below `stop_1`),
+///   - `sqrt`, `abs`, `round`, `min` and `max`.
+///
+/// Comparisons with a null (or missing) value are always false, and arithmetic
+/// with a null (or non numeric) value gives null.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Literal(JsonValue),
+    Field(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+    In(Box<Expr>, Vec<Expr>),
+    IsNull(Box<Expr>),
+    /// Pattern matching, the boolean being `true` for a case insensitive match.
+    Like(Box<Expr>, Box<Expr>, bool),
+    Arithmetic(ArithmeticOp, Box<Expr>, Box<Expr>),
+    Negate(Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArithmeticOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    Get,
+    Match,
+    Case,
+    Interpolate,
+    Step,
+    Sqrt,
+    Abs,
+    Round,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Function> {
+        match name.to_lowercase().as_str() {
+            "get" => Some(Function::Get),
+            "match" => Some(Function::Match),
+            "case" => Some(Function::Case),
+            "interpolate" => Some(Function::Interpolate),
+            "step" => Some(Function::Step),
+            "sqrt" => Some(Function::Sqrt),
+            "abs" => Some(Function::Abs),
+            "round" => Some(Function::Round),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            _ => None,
+        }
+    }
+
+    /// Checks the number of arguments of a call.
+    fn check_arity(&self, nb_args: usize) -> Result<(), String> {
+        let valid = match *self {
+            Function::Get | Function::Sqrt | Function::Abs | Function::Round => nb_args == 1,
+            Function::Min | Function::Max => nb_args >= 1,
+            Function::Match => nb_args >= 4 && nb_args % 2 == 0,
+            Function::Case => nb_args >= 3 && nb_args % 2 == 1,
+            Function::Interpolate => nb_args >= 3 && nb_args % 2 == 1,
+            Function::Step => nb_args >= 2 && nb_args % 2 == 0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("invalid number of arguments for {:?}", self).to_lowercase())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    /// Bare word: a keyword or the name of a property.
+    Word(String),
+    /// Double-quoted name of a property.
+    Field(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&'static str; 16] = ["==", "!=", "<>", "<=", ">=", "=", "<", ">", "(", ")", ",", "+",
+                                     "-", "*", "/", "%"];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars = source.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            // Quoted string or property name, the quote being escaped by doubling it:
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    Some(&q) if q == c => {
+                        if chars.get(i + 1) == Some(&c) {
+                            text.push(c);
+                            i += 2;
+                        } else {
+                            i += 1;
+                            break;
+                        }
+                    }
+                    Some(&other) => {
+                        text.push(other);
+                        i += 1;
+                    }
+                    None => return Err("unterminated quoted text".to_string()),
+                }
+            }
+            tokens.push(if c == '\'' { Token::Str(text) } else { Token::Field(text) });
+        } else if c.is_ascii_digit() ||
+                  c == '.' && chars.get(i + 1).map_or(false, |d| d.is_ascii_digit()) {
+            let start = i;
+            i += 1;
+            while i < chars.len() &&
+                  (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == 'e' || chars[i] == 'E' ||
+                   (chars[i] == '-' || chars[i] == '+') && (chars[i - 1] == 'e' || chars[i - 1] == 'E')) {
+                i += 1;
+            }
+            let text = chars[start..i].iter().collect::<String>();
+            tokens.push(Token::Number(text.parse::<f64>()
+                                          .map_err(|_| format!("invalid number {}", text))?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect()));
+        } else {
+            let rest = chars[i..].iter().take(2).collect::<String>();
+            match SYMBOLS.iter().find(|s| rest.starts_with(*s)) {
+                Some(&symbol) => {
+                    tokens.push(Token::Symbol(symbol));
+                    i += symbol.len();
+                }
+                None => return Err(format!("unexpected character '{}'", c)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// Consumes the next token if it's the given keyword (case insensitive).
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = match self.peek() {
+            Some(&Token::Word(ref w)) => w.eq_ignore_ascii_case(keyword),
+            _ => false,
+        };
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// Consumes the next token if it's the given symbol.
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let found = match self.peek() {
+            Some(&Token::Symbol(s)) => s == symbol,
+            _ => false,
+        };
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), String> {
+        if self.symbol(symbol) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}'", symbol))
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            Ok(Expr::Not(Box::new(self.not()?)))
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let left = self.additive()?;
+        let ops = [("==", CompareOp::Eq),
+                   ("=", CompareOp::Eq),
+                   ("!=", CompareOp::Ne),
+                   ("<>", CompareOp::Ne),
+                   ("<=", CompareOp::Le),
+                   ("<", CompareOp::Lt),
+                   (">=", CompareOp::Ge),
+                   (">", CompareOp::Gt)];
+        for &(symbol, op) in &ops {
+            if self.symbol(symbol) {
+                let right = self.additive()?;
+                return Ok(Expr::Compare(op, Box::new(left), Box::new(right)));
+            }
+        }
+        if self.keyword("is") {
+            let negated = self.keyword("not");
+            if !self.keyword("null") {
+                return Err("expected 'null' after 'is'".to_string());
+            }
+            let expr = Expr::IsNull(Box::new(left));
+            return Ok(if negated { Expr::Not(Box::new(expr)) } else { expr });
+        }
+        let negated = self.keyword("not");
+        let expr = if self.keyword("in") {
+            self.expect_symbol("(")?;
+            let mut values = vec![self.additive()?];
+            while self.symbol(",") {
+                values.push(self.additive()?);
+            }
+            self.expect_symbol(")")?;
+            Expr::In(Box::new(left), values)
+        } else if self.keyword("like") {
+            Expr::Like(Box::new(left), Box::new(self.additive()?), false)
+        } else if self.keyword("ilike") {
+            Expr::Like(Box::new(left), Box::new(self.additive()?), true)
+        } else if negated {
+            return Err("expected 'in', 'like' or 'ilike' after 'not'".to_string());
+        } else {
+            return Ok(left);
+        };
+        Ok(if negated { Expr::Not(Box::new(expr)) } else { expr })
+    }
+
+    fn additive(&mut self) -> Result<Expr, String> {
+        let mut expr = self.multiplicative()?;
+        loop {
+            let op = if self.symbol("+") {
+                ArithmeticOp::Add
+            } else if self.symbol("-") {
+                ArithmeticOp::Sub
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Arithmetic(op, Box::new(expr), Box::new(self.multiplicative()?));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = if self.symbol("*") {
+                ArithmeticOp::Mul
+            } else if self.symbol("/") {
+                ArithmeticOp::Div
+            } else if self.symbol("%") {
+                ArithmeticOp::Rem
+            } else {
+                return Ok(expr);
+            };
+            expr = Expr::Arithmetic(op, Box::new(expr), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.symbol("-") {
+            Ok(Expr::Negate(Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Literal(json_number(n))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(JsonValue::String(s))),
+            Some(Token::Field(name)) => Ok(Expr::Field(name)),
+            Some(Token::Symbol("(")) => {
+                let expr = self.or()?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            Some(Token::Word(word)) => {
+                match word.to_lowercase().as_str() {
+                    "true" => Ok(Expr::Literal(JsonValue::Bool(true))),
+                    "false" => Ok(Expr::Literal(JsonValue::Bool(false))),
+                    "null" => Ok(Expr::Literal(JsonValue::Null)),
+                    "and" | "or" | "not" | "in" | "is" | "like" | "ilike" => {
+                        Err(format!("unexpected keyword '{}'", word))
+                    }
+                    _ if self.symbol("(") => {
+                        let function = Function::from_name(&word)
+                            .ok_or_else(|| format!("unknown function '{}'", word))?;
+                        let mut args = Vec::new();
+                        if !self.symbol(")") {
+                            args.push(self.or()?);
+                            while self.symbol(",") {
+                                args.push(self.or()?);
+                            }
+                            self.expect_symbol(")")?;
+                        }
+                        function.check_arity(args.len())?;
+                        Ok(Expr::Call(function, args))
+                    }
+                    _ => Ok(Expr::Field(word)),
+                }
+            }
+            Some(Token::Symbol(s)) => Err(format!("unexpected '{}'", s)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn json_number(n: f64) -> JsonValue {
+    ::serde_json::Number::from_f64(n)
+        .map(JsonValue::Number)
+        .unwrap_or(JsonValue::Null)
+}
+
+impl FromStr for Expr {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(source).map_err(|err| format!("Invalid expression \"{}\": {}", source, err))?,
+            pos: 0,
+        };
+        let expr = parser.or()
+            .map_err(|err| format!("Invalid expression \"{}\": {}", source, err))?;
+        if parser.pos < parser.tokens.len() {
+            return Err(format!("Invalid expression \"{}\": unexpected {:?}",
+                               source,
+                               parser.tokens[parser.pos]));
+        }
+        Ok(expr)
+    }
+}
+
+/// Compares two values: numbers with numbers (or with numeric strings),
+/// strings with strings and booleans with booleans.
+fn compare(a: &JsonValue, b: &JsonValue) -> Option<Ordering> {
+    match (a, b) {
+        (&JsonValue::Number(ref a), &JsonValue::Number(ref b)) => {
+            a.as_f64().and_then(|a| b.as_f64().and_then(|b| a.partial_cmp(&b)))
+        }
+        (&JsonValue::Number(ref a), &JsonValue::String(ref b)) => {
+            a.as_f64().and_then(|a| b.trim().parse::<f64>().ok().and_then(|b| a.partial_cmp(&b)))
+        }
+        (&JsonValue::String(ref a), &JsonValue::Number(ref b)) => {
+            a.trim().parse::<f64>().ok().and_then(|a| b.as_f64().and_then(|b| a.partial_cmp(&b)))
+        }
+        (&JsonValue::String(ref a), &JsonValue::String(ref b)) => Some(a.cmp(b)),
+        (&JsonValue::Bool(a), &JsonValue::Bool(b)) => Some(a.cmp(&b)),
+        _ => None,
+    }
+}
+
+/// Text of a value, as used by `like`.
+fn as_text(value: &JsonValue) -> Option<String> {
+    match *value {
+        JsonValue::String(ref s) => Some(s.clone()),
+        JsonValue::Number(ref n) => Some(n.to_string()),
+        JsonValue::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Matches a text against a `like` pattern.
+fn like(text: &[char], pattern: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(&'%') => (0..text.len() + 1).any(|i| like(&text[i..], &pattern[1..])),
+        Some(&'_') => !text.is_empty() && like(&text[1..], &pattern[1..]),
+        Some(c) => text.first() == Some(c) && like(&text[1..], &pattern[1..]),
+    }
+}
+
+fn as_number(value: &JsonValue) -> Option<f64> {
+    match *value {
+        JsonValue::Number(ref n) => n.as_f64(),
+        JsonValue::String(ref s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+/// Parses a `#rgb` or `#rrggbb` color.
+fn parse_color(value: &JsonValue) -> Option<[f64; 3]> {
+    value.as_str().and_then(parse_hex)
+}
+
+/// Linear interpolation between two numbers or two colors.
+fn interpolate(a: &JsonValue, b: &JsonValue, t: f64) -> JsonValue {
+    if let (Some(a), Some(b)) = (as_number(a), as_number(b)) {
+        return json_number(a + (b - a) * t);
+    }
+    match (parse_color(a), parse_color(b)) {
+        (Some(a), Some(b)) => JsonValue::String(to_hex(mix(a, b, t))),
+        _ => JsonValue::Null,
+    }
+}
+
+/// Truthiness of a value (null, false, 0 and empty strings are false).
+pub fn is_truthy(value: &JsonValue) -> bool {
+    match *value {
+        JsonValue::Null => false,
+        JsonValue::Bool(b) => b,
+        JsonValue::Number(ref n) => n.as_f64().map_or(false, |n| n != 0.0),
+        JsonValue::String(ref s) => !s.is_empty(),
+        _ => true,
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression over the properties of a feature.
+    pub fn evaluate(&self, properties: &Map<String, JsonValue>) -> JsonValue {
+        match *self {
+            Expr::Literal(ref value) => value.clone(),
+            Expr::Field(ref name) => properties.get(name).cloned().unwrap_or(JsonValue::Null),
+            Expr::Not(ref expr) => JsonValue::Bool(!expr.matches(properties)),
+            Expr::And(ref a, ref b) => JsonValue::Bool(a.matches(properties) && b.matches(properties)),
+            Expr::Or(ref a, ref b) => JsonValue::Bool(a.matches(properties) || b.matches(properties)),
+            Expr::Compare(op, ref a, ref b) => {
+                let (a, b) = (a.evaluate(properties), b.evaluate(properties));
+                if a.is_null() || b.is_null() {
+                    return JsonValue::Bool(false);
+                }
+                let res = match (op, compare(&a, &b)) {
+                    (CompareOp::Eq, ordering) => ordering == Some(Ordering::Equal),
+                    (CompareOp::Ne, ordering) => ordering != Some(Ordering::Equal),
+                    (CompareOp::Lt, Some(ordering)) => ordering == Ordering::Less,
+                    (CompareOp::Le, Some(ordering)) => ordering != Ordering::Greater,
+                    (CompareOp::Gt, Some(ordering)) => ordering == Ordering::Greater,
+                    (CompareOp::Ge, Some(ordering)) => ordering != Ordering::Less,
+                    (_, None) => false,
+                };
+                JsonValue::Bool(res)
+            }
+            Expr::In(ref expr, ref values) => {
+                let value = expr.evaluate(properties);
+                let found = !value.is_null() &&
+                            values.iter().any(|v| {
+                                                  compare(&value, &v.evaluate(properties)) ==
+                                                  Some(Ordering::Equal)
+                                              });
+                JsonValue::Bool(found)
+            }
+            Expr::IsNull(ref expr) => JsonValue::Bool(expr.evaluate(properties).is_null()),
+            Expr::Like(ref expr, ref pattern, case_insensitive) => {
+                let res = match (as_text(&expr.evaluate(properties)),
+                                 as_text(&pattern.evaluate(properties))) {
+                    (Some(text), Some(pattern)) => {
+                        let (text, pattern) = if case_insensitive {
+                            (text.to_lowercase(), pattern.to_lowercase())
+                        } else {
+                            (text, pattern)
+                        };
+                        like(&text.chars().collect::<Vec<char>>(),
+                             &pattern.chars().collect::<Vec<char>>())
+                    }
+                    _ => false,
+                };
+                JsonValue::Bool(res)
+            }
+            Expr::Arithmetic(op, ref a, ref b) => {
+                let (a, b) = (a.evaluate(properties), b.evaluate(properties));
+                match (op, &a, &b) {
+                    (ArithmeticOp::Add, &JsonValue::String(_), _) |
+                    (ArithmeticOp::Add, _, &JsonValue::String(_)) if as_number(&a).is_none() ||
+                                                                     as_number(&b).is_none() => {
+                        match (as_text(&a), as_text(&b)) {
+                            (Some(a), Some(b)) => JsonValue::String(a + &b),
+                            _ => JsonValue::Null,
+                        }
+                    }
+                    _ => {
+                        match (as_number(&a), as_number(&b)) {
+                            (Some(a), Some(b)) => {
+                                match op {
+                                    ArithmeticOp::Add => json_number(a + b),
+                                    ArithmeticOp::Sub => json_number(a - b),
+                                    ArithmeticOp::Mul => json_number(a * b),
+                                    ArithmeticOp::Div if b != 0.0 => json_number(a / b),
+                                    ArithmeticOp::Rem if b != 0.0 => json_number(a % b),
+                                    ArithmeticOp::Div | ArithmeticOp::Rem => JsonValue::Null,
+                                }
+                            }
+                            _ => JsonValue::Null,
+                        }
+                    }
+                }
+            }
+            Expr::Negate(ref expr) => {
+                as_number(&expr.evaluate(properties))
+                    .map(|v| json_number(-v))
+                    .unwrap_or(JsonValue::Null)
+            }
+            Expr::Call(function, ref args) => Expr::call(function, args, properties),
+        }
+    }
+
+    fn call(function: Function, args: &[Expr], properties: &Map<String, JsonValue>) -> JsonValue {
+        let number_arg = |ix: usize| as_number(&args[ix].evaluate(properties));
+        match function {
+            Function::Get => {
+                match args[0].evaluate(properties) {
+                    JsonValue::String(ref name) => {
+                        properties.get(name).cloned().unwrap_or(JsonValue::Null)
+                    }
+                    _ => JsonValue::Null,
+                }
+            }
+            Function::Match => {
+                let input = args[0].evaluate(properties);
+                let pairs = &args[1..args.len() - 1];
+                for pair in pairs.chunks(2) {
+                    if compare(&input, &pair[0].evaluate(properties)) == Some(Ordering::Equal) {
+                        return pair[1].evaluate(properties);
+                    }
+                }
+                args[args.len() - 1].evaluate(properties)
+            }
+            Function::Case => {
+                for pair in args[..args.len() - 1].chunks(2) {
+                    if pair[0].matches(properties) {
+                        return pair[1].evaluate(properties);
+                    }
+                }
+                args[args.len() - 1].evaluate(properties)
+            }
+            Function::Interpolate => {
+                let input = match number_arg(0) {
+                    Some(input) => input,
+                    None => return JsonValue::Null,
+                };
+                let stops = args[1..]
+                    .chunks(2)
+                    .map(|pair| (as_number(&pair[0].evaluate(properties)), &pair[1]))
+                    .collect::<Vec<_>>();
+                match stops[0] {
+                    (Some(stop), output) if input <= stop => return output.evaluate(properties),
+                    (None, _) => return JsonValue::Null,
+                    _ => {}
+                }
+                for window in stops.windows(2) {
+                    match (window[0], window[1]) {
+                        ((Some(a), out_a), (Some(b), out_b)) if input <= b => {
+                            let t = if b > a { (input - a) / (b - a) } else { 1.0 };
+                            return interpolate(&out_a.evaluate(properties),
+                                               &out_b.evaluate(properties),
+                                               t);
+                        }
+                        ((Some(_), _), (Some(_), _)) => {}
+                        _ => return JsonValue::Null,
+                    }
+                }
+                stops[stops.len() - 1].1.evaluate(properties)
+            }
+            Function::Step => {
+                let input = match number_arg(0) {
+                    Some(input) => input,
+                    None => return JsonValue::Null,
+                };
+                let mut output = &args[1];
+                for pair in args[2..].chunks(2) {
+                    match as_number(&pair[0].evaluate(properties)) {
+                        Some(stop) if input >= stop => output = &pair[1],
+                        _ => break,
+                    }
+                }
+                output.evaluate(properties)
+            }
+            Function::Sqrt | Function::Abs | Function::Round => {
+                match number_arg(0) {
+                    Some(v) => {
+                        json_number(match function {
+                                        Function::Sqrt => v.sqrt(),
+                                        Function::Abs => v.abs(),
+                                        _ => v.round(),
+                                    })
+                    }
+                    None => JsonValue::Null,
+                }
+            }
+            Function::Min | Function::Max => {
+                let values = (0..args.len()).map(|ix| number_arg(ix)).collect::<Option<Vec<f64>>>();
+                match values {
+                    Some(ref values) if function == Function::Min => {
+                        json_number(values.iter().cloned().fold(f64::MAX, f64::min))
+                    }
+                    Some(ref values) => json_number(values.iter().cloned().fold(f64::MIN, f64::max)),
+                    None => JsonValue::Null,
+                }
+            }
+        }
+    }
+
+    /// Returns `true` if the expression is true for the given properties.
+    pub fn matches(&self, properties: &Map<String, JsonValue>) -> bool {
+        is_truthy(&self.evaluate(properties))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(json: &str) -> Map<String, JsonValue> {
+        ::serde_json::from_str(json).unwrap()
+    }
+
+    fn eval(source: &str, props: &Map<String, JsonValue>) -> JsonValue {
+        source.parse::<Expr>().unwrap().evaluate(props)
+    }
+
+    fn matches(source: &str, props: &Map<String, JsonValue>) -> bool {
+        source.parse::<Expr>().unwrap().matches(props)
+    }
+
+    #[test]
+    fn respects_the_precedence_of_boolean_operators() {
+        let props = Map::new();
+        assert!(matches("true or false and false", &props));
+        assert!(matches("false and true or true", &props));
+        assert!(!matches("not false and false", &props));
+        assert!(matches("not (false and false)", &props));
+        assert!(matches("not not true", &props));
+        assert!(!matches("(true or false) and false", &props));
+    }
+
+    #[test]
+    fn unescapes_doubled_quotes() {
+        let props = properties(r#"{"name": "L'Haÿ", "Pop \"2017\"": 12}"#);
+        assert!(matches("name = 'L''Haÿ'", &props));
+        assert!(matches(r#""Pop ""2017""" = 12"#, &props));
+        assert_eq!(eval("''''", &props), JsonValue::String("'".to_string()));
+    }
+
+    #[test]
+    fn tests_membership() {
+        let props = properties(r#"{"code": "AR", "pop": "2"}"#);
+        assert!(matches("code in ('AR', 'BR')", &props));
+        assert!(!matches("code in ('CL')", &props));
+        assert!(matches("code not in ('CL', 'UY')", &props));
+        assert!(!matches("code NOT IN ('AR')", &props));
+        assert!(matches("pop in (1, 2)", &props));
+        assert!(!matches("missing in ('AR')", &props));
+    }
+
+    #[test]
+    fn tests_null_values() {
+        let props = properties(r#"{"name": "Salta", "empty": null}"#);
+        assert!(matches("name is not null", &props));
+        assert!(matches("empty is null", &props));
+        assert!(matches("missing is null", &props));
+        assert!(!matches("missing is not null", &props));
+        assert!(!matches("empty = null", &props));
+    }
+
+    #[test]
+    fn matches_like_patterns() {
+        let props = properties(r#"{"name": "Argentina", "pop": 1234}"#);
+        assert!(matches("name like 'Arg%'", &props));
+        assert!(matches("name like '%tin%'", &props));
+        assert!(matches("name like '_rgentin_'", &props));
+        assert!(!matches("name like '_rgentin'", &props));
+        assert!(!matches("name like 'arg%'", &props));
+        assert!(matches("name ilike 'arg%'", &props));
+        assert!(matches("name not like 'Br%'", &props));
+        assert!(matches("pop like '12%'", &props));
+        assert!(!matches("missing like '%'", &props));
+    }
+
+    #[test]
+    fn compares_numbers_with_numeric_strings() {
+        let props = properties(r#"{"pop": "1000", "area": 1000, "name": "abc"}"#);
+        assert!(matches("pop > 100", &props));
+        assert!(matches("area = '1000'", &props));
+        assert!(matches("area >= pop", &props));
+        assert!(matches("'10' < '9'", &props));
+        assert!(!matches("name > 3", &props));
+        assert!(!matches("name < 3", &props));
+        assert!(matches("name != 3", &props));
+    }
+
+    #[test]
+    fn falls_back_to_the_default_of_match_and_case() {
+        let props = properties(r#"{"code": "CL", "pop": 7}"#);
+        assert_eq!(eval("match(code, 'AR', 1, 'CL', 2, 0)", &props), json_number(2.0));
+        assert_eq!(eval("match(code, 'AR', 1, 'BR', 2, 0)", &props), json_number(0.0));
+        assert_eq!(eval("match(missing, 'AR', 1, 'none')", &props),
+                   JsonValue::String("none".to_string()));
+        let case = "case(pop > 10, 'big', pop > 5, 'medium', 'small')";
+        assert_eq!(eval(case, &props), JsonValue::String("medium".to_string()));
+        assert_eq!(eval(case, &properties(r#"{"pop": 1}"#)),
+                   JsonValue::String("small".to_string()));
+        assert_eq!(eval(case, &Map::new()), JsonValue::String("small".to_string()));
+    }
+
+    #[test]
+    fn interpolates_numbers_and_colors() {
+        let numbers = "interpolate(x, 0, 0, 10, 100, 20, 0)";
+        assert_eq!(eval(numbers, &properties(r#"{"x": 2.5}"#)), json_number(25.0));
+        assert_eq!(eval(numbers, &properties(r#"{"x": 15}"#)), json_number(50.0));
+        assert_eq!(eval(numbers, &properties(r#"{"x": -1}"#)), json_number(0.0));
+        assert_eq!(eval(numbers, &properties(r#"{"x": 30}"#)), json_number(0.0));
+        assert_eq!(eval(numbers, &Map::new()), JsonValue::Null);
+
+        let colors = "interpolate(x, 0, '#000000', 10, '#0000ff')";
+        assert_eq!(eval(colors, &properties(r#"{"x": 2}"#)),
+                   JsonValue::String("#000033".to_string()));
+        assert_eq!(eval(colors, &properties(r#"{"x": 10}"#)),
+                   JsonValue::String("#0000ff".to_string()));
+        assert_eq!(eval("interpolate(1, 0, 'red', 2, '#0000ff')", &Map::new()),
+                   JsonValue::Null);
+    }
+
+    #[test]
+    fn selects_the_output_of_the_last_step() {
+        let step = "step(x, 'low', 10, 'mid', 100, 'high')";
+        let output = |x: &str| eval(step, &properties(&format!(r#"{{"x": {}}}"#, x)));
+        assert_eq!(output("5"), JsonValue::String("low".to_string()));
+        assert_eq!(output("10"), JsonValue::String("mid".to_string()));
+        assert_eq!(output("99.9"), JsonValue::String("mid".to_string()));
+        assert_eq!(output("1e3"), JsonValue::String("high".to_string()));
+        assert_eq!(output("null"), JsonValue::Null);
+    }
+
+    #[test]
+    fn gives_null_on_division_by_zero() {
+        let props = properties(r#"{"x": 4, "zero": 0}"#);
+        assert_eq!(eval("x / 2", &props), json_number(2.0));
+        assert_eq!(eval("x / zero", &props), JsonValue::Null);
+        assert_eq!(eval("x % 0", &props), JsonValue::Null);
+        assert!(matches("x / 0 is null", &props));
+        assert!(!matches("x / 0 > 1", &props));
+    }
+
+    #[test]
+    fn parses_unary_minus() {
+        let props = properties(r#"{"x": 3}"#);
+        assert_eq!(eval("-x", &props), json_number(-3.0));
+        assert_eq!(eval("x-1", &props), json_number(2.0));
+        assert_eq!(eval("2-1", &props), json_number(1.0));
+        assert_eq!(eval("-2 * -x", &props), json_number(6.0));
+        assert_eq!(eval("- -1", &props), json_number(1.0));
+        assert_eq!(eval("1e-2", &props), json_number(0.01));
+        assert!(matches("x > -1", &props));
+        assert_eq!(eval("-missing", &props), JsonValue::Null);
+    }
+
+    #[test]
+    fn reports_parse_errors() {
+        for source in &["a = ",
+                        "a = 'unterminated",
+                        "(a = 1",
+                        "a = 1)",
+                        "a = 1 b",
+                        "a not b",
+                        "a is 1",
+                        "a in 1",
+                        "a # 1",
+                        "and = 1",
+                        "unknown(a)",
+                        "sqrt(1, 2)",
+                        ""] {
+            match source.parse::<Expr>() {
+                Err(err) => assert!(err.starts_with("Invalid expression"), "{}", err),
+                Ok(expr) => panic!("\"{}\" was parsed as {:?}", source, expr),
+            }
+        }
+    }
+}
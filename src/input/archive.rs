@@ -0,0 +1,90 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use flate2::read::GzDecoder;
+use zip::ZipArchive;
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Opens a file for reading, transparently decompressing it if its name ends with `.gz`.
+pub fn open_input(path: &str) -> Result<Box<dyn Read>, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    if path.to_lowercase().ends_with(".gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(BufReader::new(file)))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Creates a new (empty) temporary directory.
+pub fn temp_dir() -> Result<PathBuf, String> {
+    let dir = env::temp_dir().join(format!("mapsvg-{}-{}",
+                                           process::id(),
+                                           TEMP_COUNTER.fetch_add(1, Ordering::SeqCst)));
+    fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+    Ok(dir)
+}
+
+/// Decompresses a `.gz` file in `dir`, returning the path of the decompressed file.
+pub fn gunzip(path: &str, dir: &Path) -> Result<PathBuf, String> {
+    let file_name = Path::new(&path[..path.len() - 3])
+        .file_name()
+        .ok_or_else(|| format!("Invalid path: {}", path))?
+        .to_owned();
+    let target = dir.join(file_name);
+    let mut input = open_input(path)?;
+    let mut output = File::create(&target).map_err(|err| err.to_string())?;
+    io::copy(&mut input, &mut output).map_err(|err| err.to_string())?;
+    Ok(target)
+}
+
+/// Relative path of an entry of an archive, without the components which could lead
+/// outside of the extraction directory (like ".." or a root).
+fn entry_path(name: &str) -> PathBuf {
+    Path::new(name)
+        .components()
+        .filter_map(|c| match c {
+                        Component::Normal(part) => Some(part),
+                        _ => None,
+                    })
+        .collect()
+}
+
+/// Extracts every file of a `.zip` archive in `dir` (keeping the directories of the
+/// archive), returning their paths.
+pub fn unzip(path: &str, dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let mut archive = ZipArchive::new(BufReader::new(file)).map_err(|err| err.to_string())?;
+    let mut paths = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|err| err.to_string())?;
+        let relative_path = entry_path(entry.name());
+        if entry.name().ends_with('/') || relative_path.as_os_str().is_empty() {
+            continue;
+        }
+        let target = dir.join(relative_path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        let mut output = File::create(&target).map_err(|err| err.to_string())?;
+        io::copy(&mut entry, &mut output).map_err(|err| err.to_string())?;
+        paths.push(target);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_entries_inside_the_directory() {
+        assert_eq!(entry_path("data/2017/communes.shp"), Path::new("data/2017/communes.shp"));
+        assert_eq!(entry_path("../../etc/passwd"), Path::new("etc/passwd"));
+        assert_eq!(entry_path("/tmp/./layer.geojson"), Path::new("tmp/layer.geojson"));
+        assert_eq!(entry_path(".."), PathBuf::new());
+    }
+}
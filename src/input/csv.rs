@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use geojson::{Feature, Geometry, Value};
+use serde_json::{Map, Number, Value as JsonValue};
+use toml;
+
+use super::LayerSource;
+use super::archive::open_input;
+use super::wkt::parse_wkt;
+
+/// Content of a delimited text file.
+pub struct Table {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    /// Line of the file (starting at 1) on which each row starts, the blank lines
+    /// being skipped and the quoted fields being able to span several lines.
+    pub lines: Vec<usize>,
+}
+
+/// Reads a delimited text file, returning its header and its rows.
+pub fn read_table(path: &str, delimiter: char) -> Result<Table, String> {
+    let mut file = open_input(path)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content).map_err(|err| err.to_string())?;
+    let content = content.trim_start_matches('\u{feff}');
+
+    let mut rows = Vec::new();
+    let mut lines = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let (mut line, mut row_line) = (1, 1);
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                if c == '\n' || c == '\r' && chars.peek() != Some(&'\n') {
+                    line += 1;
+                }
+                field.push(c);
+            }
+        } else if c == '"' {
+            in_quotes = true;
+        } else if c == delimiter {
+            row.push(field.clone());
+            field.clear();
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.peek() == Some(&'\n') {
+                chars.next();
+            }
+            line += 1;
+            row.push(field.clone());
+            field.clear();
+            if !(row.len() == 1 && row[0].is_empty()) {
+                rows.push(row);
+                lines.push(row_line);
+            }
+            row = Vec::new();
+            row_line = line;
+        } else {
+            field.push(c);
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+        lines.push(row_line);
+    }
+    if rows.is_empty() {
+        return Err("Empty CSV file!".to_string());
+    }
+    let header = rows.remove(0).into_iter().map(|h| h.trim().to_string()).collect();
+    lines.remove(0);
+    Ok(Table {
+           header: header,
+           rows: rows,
+           lines: lines,
+       })
+}
+
+/// Returns `true` for the numeric-looking values which are rather codes, like "01"
+/// (a leading zero followed by another digit).
+pub fn has_leading_zero(value: &str) -> bool {
+    let digits = value.trim_start_matches(|c| c == '-' || c == '+');
+    digits.len() > 1 && digits.starts_with('0') && digits[1..].starts_with(|c: char| c.is_digit(10))
+}
+
+/// Converts the rows of a table into properties, using numbers for the columns
+/// where every non-empty value is numeric (and isn't a code with a leading zero,
+/// like "01"). The columns in `skip` are ignored.
+pub fn typed_records(header: &[String], rows: &[Vec<String>], skip: &[usize]) -> Vec<Map<String, JsonValue>> {
+    let column_types = (0..header.len())
+        .map(|ix| {
+            let values = rows.iter()
+                .filter_map(|row| row.get(ix))
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty());
+            let mut is_integer = true;
+            let mut is_float = true;
+            for v in values {
+                let is_code = has_leading_zero(v);
+                is_integer = is_integer && !is_code && v.parse::<i64>().is_ok();
+                is_float = is_float && !is_code && v.parse::<f64>().is_ok();
+            }
+            (is_integer, is_float)
+        })
+        .collect::<Vec<_>>();
+
+    rows.iter()
+        .map(|row| {
+            let mut properties = Map::new();
+            for (ix, name) in header.iter().enumerate() {
+                if skip.contains(&ix) {
+                    continue;
+                }
+                let raw = row.get(ix).map(|v| v.trim()).unwrap_or("");
+                let value = if raw.is_empty() {
+                    JsonValue::Null
+                } else if column_types[ix].0 {
+                    JsonValue::Number(Number::from(raw.parse::<i64>().unwrap()))
+                } else if column_types[ix].1 {
+                    Number::from_f64(raw.parse::<f64>().unwrap())
+                        .map(JsonValue::Number)
+                        .unwrap_or(JsonValue::Null)
+                } else {
+                    JsonValue::String(raw.to_string())
+                };
+                properties.insert(name.clone(), value);
+            }
+            properties
+        })
+        .collect()
+}
+
+/// Reads the delimiter to use from the options of a layer (or of a join).
+pub fn delimiter_option(options: Option<&BTreeMap<String, toml::Value>>) -> Result<char, String> {
+    match options.and_then(|o| o.get("delimiter")) {
+        Some(&toml::Value::String(ref d)) if d == "\\t" || d == "tab" => Ok('\t'),
+        Some(&toml::Value::String(ref d)) if d.chars().count() == 1 => Ok(d.chars().next().unwrap()),
+        Some(_) => Err("\"delimiter\" should be a single character!".to_string()),
+        None => Ok(','),
+    }
+}
+
+fn find_column(header: &[String], name: &str) -> Result<usize, String> {
+    header.iter()
+        .position(|h| h == name)
+        .ok_or_else(|| format!("No column named \"{}\" in the CSV file!", name))
+}
+
+/// Reads a CSV layer, with the location of each feature given by a longitude and a
+/// latitude columns (`lon` and `lat` options, guessed from the header by default)
+/// or by a WKT column (`wkt` option).
+pub fn read(path: &str, options: Option<&BTreeMap<String, toml::Value>>) -> Result<LayerSource, String> {
+    let delimiter = delimiter_option(options)?;
+    let Table { header, rows, lines } = read_table(path, delimiter)?;
+    let option = |name: &str| options.and_then(|o| o.get(name)).and_then(|v| v.as_str());
+
+    let geometry_columns = if let Some(wkt_column) = option("wkt") {
+        vec![find_column(&header, wkt_column)?]
+    } else {
+        let guess = |candidates: &[&str]| {
+            header.iter()
+                .position(|h| candidates.contains(&h.to_lowercase().as_str()))
+        };
+        let lon = match option("lon") {
+            Some(name) => find_column(&header, name)?,
+            None => {
+                guess(&["lon", "lng", "long", "longitude", "x"])
+                    .ok_or_else(|| "Unable to find the longitude column, use the \"lon\" option!".to_string())?
+            }
+        };
+        let lat = match option("lat") {
+            Some(name) => find_column(&header, name)?,
+            None => {
+                guess(&["lat", "latitude", "y"])
+                    .ok_or_else(|| "Unable to find the latitude column, use the \"lat\" option!".to_string())?
+            }
+        };
+        vec![lon, lat]
+    };
+
+    let records = typed_records(&header, &rows, &geometry_columns);
+    let mut features = Vec::with_capacity(rows.len());
+    for ((row, properties), line) in rows.iter().zip(records.into_iter()).zip(lines) {
+        let cell = |col: usize| row.get(col).map(|v| v.trim()).unwrap_or("");
+        let geometry = if geometry_columns.len() == 1 {
+            let wkt = cell(geometry_columns[0]);
+            if wkt.is_empty() {
+                None
+            } else {
+                parse_wkt(wkt).map_err(|err| format!("Line {}: {}", line, err))?
+            }
+        } else {
+            match (cell(geometry_columns[0]).parse::<f64>(), cell(geometry_columns[1]).parse::<f64>()) {
+                (Ok(x), Ok(y)) => Some(Value::Point(vec![x, y])),
+                _ => {
+                    eprintln!("Warning: invalid coordinates on line {} of {}, the feature has no geometry.",
+                              line,
+                              path);
+                    None
+                }
+            }
+        };
+        features.push(Feature {
+                          geometry: geometry.map(Geometry::new),
+                          properties: Some(properties),
+                          bbox: None,
+                          id: None,
+                          foreign_members: None,
+                      });
+    }
+    Ok(LayerSource::from_features(features))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_utils::{TempDir, features};
+
+    #[test]
+    fn reads_quoted_fields() {
+        let dir = TempDir::new("csv-quoted-test");
+        let path = dir.write("quoted.csv",
+                             "\u{feff}name;comment\r\n\"Saint-Denis; Réunion\";\"said \"\"hi\"\"\"\r\n\r\nParis;\n"
+                                 .as_bytes());
+        let table = read_table(path.to_str().unwrap(), ';').unwrap();
+        assert_eq!(table.header, vec!["name", "comment"]);
+        assert_eq!(table.lines, vec![2, 4]);
+        assert_eq!(table.rows,
+                   vec![vec!["Saint-Denis; Réunion".to_string(), "said \"hi\"".to_string()],
+                        vec!["Paris".to_string(), "".to_string()]]);
+    }
+
+    #[test]
+    fn infers_the_column_types() {
+        let header = vec!["code".to_string(), "pop".to_string(), "ratio".to_string(), "name".to_string()];
+        let rows = vec![vec!["01".to_string(), "12".to_string(), "0.5".to_string(), "Ain".to_string()],
+                        vec!["13".to_string(), "".to_string(), "2".to_string(), "42".to_string()]];
+        let records = typed_records(&header, &rows, &[]);
+        assert_eq!(records[0]["code"], JsonValue::String("01".to_string()));
+        assert_eq!(records[1]["code"], JsonValue::String("13".to_string()));
+        assert_eq!(records[0]["pop"], JsonValue::Number(Number::from(12)));
+        assert_eq!(records[1]["pop"], JsonValue::Null);
+        assert_eq!(records[0]["ratio"], JsonValue::Number(Number::from_f64(0.5).unwrap()));
+        assert_eq!(records[1]["name"], JsonValue::String("42".to_string()));
+        assert!(!typed_records(&header, &rows, &[3])[0].contains_key("name"));
+        assert!(has_leading_zero("-007"));
+        assert!(!has_leading_zero("0"));
+        assert!(!has_leading_zero("0.25"));
+    }
+
+    #[test]
+    fn reads_points_from_lon_lat_columns() {
+        let dir = TempDir::new("csv-points-test");
+        let path = dir.write("points.csv", b"Name,Longitude,Latitude\nA,2.35,48.85\nB,,\n");
+        let features = features(read(path.to_str().unwrap(), None).unwrap());
+        assert_eq!(features[0].geometry.as_ref().unwrap().value,
+                   Value::Point(vec![2.35, 48.85]));
+        assert!(features[1].geometry.is_none());
+        assert!(!features[0].properties.as_ref().unwrap().contains_key("Longitude"));
+    }
+
+    #[test]
+    fn reads_geometries_from_a_wkt_column() {
+        let dir = TempDir::new("csv-wkt-test");
+        let path = dir.write("wkt.csv", b"id\tgeom\n1\tPOINT (1 2)\n2\tPOINT EMPTY\n3\tPOINT (1\n");
+        let mut options = BTreeMap::new();
+        options.insert("wkt".to_string(), toml::Value::String("geom".to_string()));
+        options.insert("delimiter".to_string(), toml::Value::String("tab".to_string()));
+        assert_eq!(read(path.to_str().unwrap(), Some(&options)).err(),
+                   Some("Line 4: Expected at least 2 coordinates in WKT geometry!".to_string()));
+
+        let path = dir.write("wkt2.csv", b"id\tgeom\n1\tPOINT (1 2)\n2\tPOINT EMPTY\n");
+        let features = features(read(path.to_str().unwrap(), Some(&options)).unwrap());
+        assert_eq!(features[0].geometry.as_ref().unwrap().value,
+                   Value::Point(vec![1.0, 2.0]));
+        assert!(features[1].geometry.is_none());
+
+        // The blank lines and the line breaks of the quoted fields are counted:
+        let path = dir.write("wkt3.csv",
+                             b"id\tgeom\tnote\n\n1\tPOINT (1 2)\t\"two\nlines\"\n2\tPOINT (1\t\n");
+        assert_eq!(read(path.to_str().unwrap(), Some(&options)).err(),
+                   Some("Line 5: Expected at least 2 coordinates in WKT geometry!".to_string()));
+    }
+}
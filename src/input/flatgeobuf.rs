@@ -0,0 +1,722 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use byteorder::{ByteOrder, LittleEndian};
+use geojson::{Feature, Geometry, Value};
+use serde_json;
+use serde_json::{Map, Number, Value as JsonValue};
+use toml;
+
+use config_params::MapExtent;
+use layer::extend_extent;
+use super::{BboxFilter, LayerSource, filter_in_layer_crs};
+
+const MAGIC_BYTES: &'static [u8] = b"fgb";
+const NODE_ITEM_SIZE: usize = 40;
+
+/// Minimal reader for the tables of a FlatBuffers buffer.
+#[derive(Clone, Copy)]
+struct FbTable<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+fn read_u32(buf: &[u8], pos: usize) -> Option<u32> {
+    buf.get(pos..pos + 4).map(LittleEndian::read_u32)
+}
+
+impl<'a> FbTable<'a> {
+    /// Returns the root table of a buffer.
+    fn root(buf: &'a [u8]) -> Option<FbTable<'a>> {
+        read_u32(buf, 0).map(|offset| {
+                                 FbTable {
+                                     buf: buf,
+                                     pos: offset as usize,
+                                 }
+                             })
+    }
+
+    /// Returns the position of a field, if present.
+    fn field(&self, index: usize) -> Option<usize> {
+        let soffset = read_u32(self.buf, self.pos)? as i32;
+        let vtable = (self.pos as i64 - soffset as i64) as usize;
+        let vtable_size = self.buf.get(vtable..vtable + 2).map(LittleEndian::read_u16)? as usize;
+        let entry = 4 + 2 * index;
+        if entry + 2 > vtable_size {
+            return None;
+        }
+        match self.buf.get(vtable + entry..vtable + entry + 2).map(LittleEndian::read_u16)? {
+            0 => None,
+            offset => Some(self.pos + offset as usize),
+        }
+    }
+
+    fn get_u8(&self, index: usize, default: u8) -> u8 {
+        self.field(index).and_then(|p| self.buf.get(p).cloned()).unwrap_or(default)
+    }
+
+    fn get_u16(&self, index: usize, default: u16) -> u16 {
+        self.field(index)
+            .and_then(|p| self.buf.get(p..p + 2))
+            .map(LittleEndian::read_u16)
+            .unwrap_or(default)
+    }
+
+    fn get_i32(&self, index: usize, default: i32) -> i32 {
+        self.field(index)
+            .and_then(|p| self.buf.get(p..p + 4))
+            .map(LittleEndian::read_i32)
+            .unwrap_or(default)
+    }
+
+    fn get_u64(&self, index: usize, default: u64) -> u64 {
+        self.field(index)
+            .and_then(|p| self.buf.get(p..p + 8))
+            .map(LittleEndian::read_u64)
+            .unwrap_or(default)
+    }
+
+    /// Follows the offset stored in a field (for strings, vectors and tables).
+    fn indirect(&self, index: usize) -> Option<usize> {
+        let p = self.field(index)?;
+        Some(p + read_u32(self.buf, p)? as usize)
+    }
+
+    /// Returns the position of the first element and the length of a vector.
+    fn vector(&self, index: usize) -> Option<(usize, usize)> {
+        let p = self.indirect(index)?;
+        Some((p + 4, read_u32(self.buf, p)? as usize))
+    }
+
+    fn get_bytes(&self, index: usize) -> Option<&'a [u8]> {
+        let (start, len) = self.vector(index)?;
+        self.buf.get(start..start + len)
+    }
+
+    fn get_str(&self, index: usize) -> Option<&'a str> {
+        self.get_bytes(index).and_then(|b| ::std::str::from_utf8(b).ok())
+    }
+
+    fn get_f64_vec(&self, index: usize) -> Vec<f64> {
+        match self.vector(index).and_then(|(start, len)| self.buf.get(start..start + len * 8)) {
+            Some(bytes) => bytes.chunks(8).map(LittleEndian::read_f64).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn get_u32_vec(&self, index: usize) -> Vec<u32> {
+        match self.vector(index).and_then(|(start, len)| self.buf.get(start..start + len * 4)) {
+            Some(bytes) => bytes.chunks(4).map(LittleEndian::read_u32).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn get_table(&self, index: usize) -> Option<FbTable<'a>> {
+        self.indirect(index).map(|p| {
+                                     FbTable {
+                                         buf: self.buf,
+                                         pos: p,
+                                     }
+                                 })
+    }
+
+    fn get_tables(&self, index: usize) -> Vec<FbTable<'a>> {
+        match self.vector(index) {
+            Some((start, len)) => {
+                (0..len)
+                    .filter_map(|i| {
+                                    let p = start + 4 * i;
+                                    read_u32(self.buf, p).map(|offset| {
+                                                                  FbTable {
+                                                                      buf: self.buf,
+                                                                      pos: p + offset as usize,
+                                                                  }
+                                                              })
+                                })
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+struct Column {
+    name: String,
+    column_type: u8,
+}
+
+/// Reads a FlatGeobuf layer. When a filter is provided and the file has a spatial
+/// index, only the features intersecting the filter are read from the file.
+pub fn read(path: &str,
+            _options: Option<&BTreeMap<String, toml::Value>>,
+            filter: Option<&BboxFilter>)
+            -> Result<LayerSource, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let file_size = file.metadata().map_err(|err| err.to_string())?.len();
+    let mut reader = BufReader::new(file);
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic).map_err(|err| err.to_string())?;
+    if &magic[..3] != MAGIC_BYTES {
+        return Err("Invalid FlatGeobuf file (wrong magic bytes)!".to_string());
+    }
+    let header_buf = read_size_prefixed(&mut reader, file_size)?.ok_or_else(truncated)?;
+    let header = FbTable::root(&header_buf).ok_or_else(|| "Invalid FlatGeobuf header!".to_string())?;
+    let geometry_type = header.get_u8(2, 0);
+    let columns = header.get_tables(7)
+        .iter()
+        .map(|c| {
+                 Column {
+                     name: c.get_str(0).unwrap_or("").to_string(),
+                     column_type: c.get_u8(1, 0),
+                 }
+             })
+        .collect::<Vec<Column>>();
+    let features_count = header.get_u64(8, 0);
+    // Each feature takes at least the 4 bytes of its size:
+    if features_count > file_size / 4 {
+        return Err("Invalid FlatGeobuf header (wrong number of features)!".to_string());
+    }
+    let features_count = features_count as usize;
+    let index_node_size = header.get_u16(9, 16);
+    let crs = header.get_table(10).and_then(|crs| {
+        let code = crs.get_i32(1, 0);
+        match crs.get_str(0) {
+            Some(org) if org.to_uppercase() != "EPSG" => None,
+            _ if code == 0 || code == 4326 => None,
+            _ => Some(format!("+init=epsg:{}", code)),
+        }
+    });
+    let filter = match filter {
+        Some(f) => Some(filter_in_layer_crs(f, crs.as_ref().map(|c| c.as_str()))?),
+        None => None,
+    };
+
+    let index_size = if index_node_size > 0 && features_count > 0 {
+        // The first level holds the leaves, whose end is the number of nodes:
+        level_bounds(features_count, index_node_size as usize)[0].1 * NODE_ITEM_SIZE
+    } else {
+        0
+    };
+    let features_start = 12 + header_buf.len() as u64 + index_size as u64;
+    if features_start > file_size {
+        return Err(truncated());
+    }
+
+    let mut features = Vec::new();
+    match filter {
+        Some(ref extent) if index_size > 0 => {
+            let mut index = vec![0u8; index_size];
+            reader.read_exact(&mut index).map_err(|err| err.to_string())?;
+            let mut offsets = search_index(&index, features_count, index_node_size as usize, extent);
+            offsets.sort();
+            for offset in offsets {
+                reader.seek(SeekFrom::Start(features_start + offset)).map_err(|err| err.to_string())?;
+                let feature_buf = read_size_prefixed(&mut reader, file_size)?.ok_or_else(truncated)?;
+                features.push(decode_feature(&feature_buf, &columns, geometry_type)?);
+            }
+        }
+        _ => {
+            reader.seek(SeekFrom::Start(features_start)).map_err(|err| err.to_string())?;
+            loop {
+                // The end of the file is only expected between two features:
+                let feature_buf = match read_size_prefixed(&mut reader, file_size)? {
+                    Some(buf) => buf,
+                    None => break,
+                };
+                let feature = decode_feature(&feature_buf, &columns, geometry_type)?;
+                let keep = match (filter.as_ref(), feature.geometry.as_ref()) {
+                    (Some(extent), Some(geom)) => {
+                        let mut feature_extent = MapExtent::empty();
+                        extend_extent(&geom.value, &mut feature_extent);
+                        extent.intersects(&feature_extent)
+                    }
+                    _ => true,
+                };
+                if keep {
+                    features.push(feature);
+                }
+            }
+        }
+    }
+    Ok(LayerSource { crs: crs, ..LayerSource::from_features(features) })
+}
+
+fn truncated() -> String {
+    "Truncated FlatGeobuf file!".to_string()
+}
+
+/// Reads a buffer prefixed by its size, or returns `None` if the reader is at the
+/// end of the file (a file ending in the middle of a buffer being an error).
+fn read_size_prefixed<R: Read>(reader: &mut R, file_size: u64) -> Result<Option<Vec<u8>>, String> {
+    let mut size = [0u8; 4];
+    let mut filled = 0;
+    while filled < size.len() {
+        match reader.read(&mut size[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(truncated()),
+            Ok(n) => filled += n,
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.to_string()),
+        }
+    }
+    let size = LittleEndian::read_u32(&size) as u64;
+    if size > file_size {
+        return Err("Invalid FlatGeobuf file (wrong size of a buffer)!".to_string());
+    }
+    let mut buf = vec![0u8; size as usize];
+    reader.read_exact(&mut buf)
+        .map_err(|err| if err.kind() == ErrorKind::UnexpectedEof {
+                     truncated()
+                 } else {
+                     err.to_string()
+                 })?;
+    Ok(Some(buf))
+}
+
+/// Ranges of node indexes of each level of the packed R-tree, from the leaves to the root.
+fn level_bounds(nb_items: usize, node_size: usize) -> Vec<(usize, usize)> {
+    let node_size = node_size.max(2);
+    let mut level_nb_nodes = vec![nb_items];
+    let mut n = nb_items;
+    let mut nb_nodes = n;
+    loop {
+        n = (n + node_size - 1) / node_size;
+        nb_nodes += n;
+        level_nb_nodes.push(n);
+        if n == 1 {
+            break;
+        }
+    }
+    // The root is stored first and the leaves last:
+    let mut bounds = Vec::with_capacity(level_nb_nodes.len());
+    let mut end = nb_nodes;
+    for size in level_nb_nodes {
+        bounds.push((end - size, end));
+        end -= size;
+    }
+    bounds
+}
+
+/// Returns the offsets (relative to the first feature) of the features whose
+/// bounding box intersects the given extent.
+fn search_index(index: &[u8], nb_items: usize, node_size: usize, extent: &MapExtent) -> Vec<u64> {
+    let node_size = node_size.max(2);
+    let bounds = level_bounds(nb_items, node_size);
+    let mut res = Vec::new();
+    let mut queue = VecDeque::new();
+    queue.push_back((0, bounds.len() - 1));
+    while let Some((node_index, level)) = queue.pop_front() {
+        let end = (node_index + node_size).min(bounds[level].1);
+        for pos in node_index..end {
+            let item = match index.get(pos * NODE_ITEM_SIZE..(pos + 1) * NODE_ITEM_SIZE) {
+                Some(item) => item,
+                None => break,
+            };
+            let node_extent = MapExtent {
+                left: LittleEndian::read_f64(&item[0..8]),
+                bottom: LittleEndian::read_f64(&item[8..16]),
+                right: LittleEndian::read_f64(&item[16..24]),
+                top: LittleEndian::read_f64(&item[24..32]),
+            };
+            if !extent.intersects(&node_extent) {
+                continue;
+            }
+            let offset = LittleEndian::read_u64(&item[32..40]);
+            if level == 0 {
+                res.push(offset);
+            } else {
+                queue.push_back((offset as usize, level - 1));
+            }
+        }
+    }
+    res
+}
+
+fn decode_feature(buf: &[u8], columns: &[Column], geometry_type: u8) -> Result<Feature, String> {
+    let feature = FbTable::root(buf).ok_or_else(|| "Invalid FlatGeobuf feature!".to_string())?;
+    let geometry = match feature.get_table(0) {
+        Some(geom) => decode_geometry(&geom, geometry_type)?,
+        None => None,
+    };
+    let properties = match feature.get_bytes(1) {
+        Some(bytes) => Some(decode_properties(bytes, columns)?),
+        None => None,
+    };
+    Ok(Feature {
+           geometry: geometry.map(Geometry::new),
+           properties: properties,
+           bbox: None,
+           id: None,
+           foreign_members: None,
+       })
+}
+
+/// Splits the flat coordinates of a geometry into lines, using the `ends` of each part.
+fn lines(xy: &[f64], ends: &[u32]) -> Vec<Vec<Vec<f64>>> {
+    let points = xy.chunks(2).map(|c| vec![c[0], c[1]]).collect::<Vec<Vec<f64>>>();
+    if ends.is_empty() {
+        return vec![points];
+    }
+    let mut res = Vec::with_capacity(ends.len());
+    let mut start = 0;
+    for &end in ends {
+        let end = (end as usize).min(points.len());
+        res.push(points[start..end].to_vec());
+        start = end;
+    }
+    res
+}
+
+fn decode_geometry(geom: &FbTable, header_type: u8) -> Result<Option<Value>, String> {
+    let geom_type = match geom.get_u8(6, 0) {
+        0 => header_type,
+        t => t,
+    };
+    let xy = geom.get_f64_vec(1);
+    let ends = geom.get_u32_vec(0);
+    let value = match geom_type {
+        1 => {
+            if xy.len() < 2 {
+                return Ok(None);
+            }
+            Value::Point(vec![xy[0], xy[1]])
+        }
+        2 => Value::LineString(lines(&xy, &[]).pop().unwrap()),
+        3 => Value::Polygon(lines(&xy, &ends)),
+        4 => Value::MultiPoint(xy.chunks(2).map(|c| vec![c[0], c[1]]).collect()),
+        5 => Value::MultiLineString(lines(&xy, &ends)),
+        6 => {
+            let mut polygons = Vec::new();
+            for part in geom.get_tables(7) {
+                if let Some(Value::Polygon(rings)) = decode_geometry(&part, 3)? {
+                    polygons.push(rings);
+                }
+            }
+            Value::MultiPolygon(polygons)
+        }
+        7 => {
+            let mut geometries = Vec::new();
+            for part in geom.get_tables(7) {
+                if let Some(value) = decode_geometry(&part, 0)? {
+                    geometries.push(Geometry::new(value));
+                }
+            }
+            Value::GeometryCollection(geometries)
+        }
+        _ => return Err(format!("Unsupported FlatGeobuf geometry type: {}", geom_type)),
+    };
+    Ok(Some(value))
+}
+
+fn decode_properties(bytes: &[u8], columns: &[Column]) -> Result<Map<String, JsonValue>, String> {
+    let invalid = || "Invalid FlatGeobuf properties!".to_string();
+    let mut properties = Map::new();
+    for column in columns {
+        properties.insert(column.name.clone(), JsonValue::Null);
+    }
+    let mut pos = 0;
+    while pos + 2 <= bytes.len() {
+        let column = columns.get(LittleEndian::read_u16(&bytes[pos..pos + 2]) as usize)
+            .ok_or_else(&invalid)?;
+        pos += 2;
+        let size = match column.column_type {
+            0 | 1 | 2 => 1,
+            3 | 4 => 2,
+            5 | 6 | 9 => 4,
+            7 | 8 | 10 => 8,
+            _ => {
+                let len = bytes.get(pos..pos + 4).map(LittleEndian::read_u32).ok_or_else(&invalid)?;
+                pos += 4;
+                len as usize
+            }
+        };
+        let raw = bytes.get(pos..pos + size).ok_or_else(&invalid)?;
+        pos += size;
+        let value = match column.column_type {
+            0 => JsonValue::from(raw[0] as i8),
+            1 => JsonValue::from(raw[0]),
+            2 => JsonValue::Bool(raw[0] != 0),
+            3 => JsonValue::from(LittleEndian::read_i16(raw)),
+            4 => JsonValue::from(LittleEndian::read_u16(raw)),
+            5 => JsonValue::from(LittleEndian::read_i32(raw)),
+            6 => JsonValue::from(LittleEndian::read_u32(raw)),
+            7 => JsonValue::from(LittleEndian::read_i64(raw)),
+            8 => JsonValue::from(LittleEndian::read_u64(raw)),
+            9 => {
+                Number::from_f64(LittleEndian::read_f32(raw) as f64)
+                    .map(JsonValue::Number)
+                    .unwrap_or(JsonValue::Null)
+            }
+            10 => {
+                Number::from_f64(LittleEndian::read_f64(raw))
+                    .map(JsonValue::Number)
+                    .unwrap_or(JsonValue::Null)
+            }
+            11 | 13 => JsonValue::String(String::from_utf8_lossy(raw).into_owned()),
+            12 => {
+                let text = String::from_utf8_lossy(raw).into_owned();
+                serde_json::from_str(&text).unwrap_or(JsonValue::String(text))
+            }
+            _ => JsonValue::Null,
+        };
+        properties.insert(column.name.clone(), value);
+    }
+    Ok(properties)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use byteorder::WriteBytesExt;
+    use super::*;
+    use super::super::test_utils::{TempDir, features};
+
+    /// Field of a table built by `Builder` (the index of the field being its
+    /// position in the list).
+    enum Field {
+        Absent,
+        U8(u8),
+        U16(u16),
+        I32(i32),
+        U64(u64),
+        Bytes(Vec<u8>),
+        F64s(Vec<f64>),
+        U32s(Vec<u32>),
+        Table(Vec<Field>),
+        Tables(Vec<Vec<Field>>),
+    }
+
+    /// Writes a FlatBuffers table (preceded by its vtable) followed by the
+    /// content of its offset fields, and returns the position of the table.
+    fn write_table(buf: &mut Vec<u8>, fields: &[Field]) -> usize {
+        let inline_size = |f: &Field| match *f {
+            Field::Absent => 0,
+            Field::U8(_) => 1,
+            Field::U16(_) => 2,
+            Field::I32(_) => 4,
+            Field::U64(_) => 8,
+            _ => 4,
+        };
+        let vtable_pos = buf.len();
+        buf.write_u16::<LittleEndian>(4 + 2 * fields.len() as u16).unwrap();
+        buf.write_u16::<LittleEndian>(4 + fields.iter().map(&inline_size).sum::<usize>() as u16)
+            .unwrap();
+        let mut offset = 4;
+        for field in fields {
+            let size = inline_size(field);
+            buf.write_u16::<LittleEndian>(if size == 0 { 0 } else { offset }).unwrap();
+            offset += size as u16;
+        }
+        let table_pos = buf.len();
+        buf.write_i32::<LittleEndian>((table_pos - vtable_pos) as i32).unwrap();
+        let mut pending = Vec::new();
+        for field in fields {
+            match *field {
+                Field::Absent => {}
+                Field::U8(v) => buf.push(v),
+                Field::U16(v) => buf.write_u16::<LittleEndian>(v).unwrap(),
+                Field::I32(v) => buf.write_i32::<LittleEndian>(v).unwrap(),
+                Field::U64(v) => buf.write_u64::<LittleEndian>(v).unwrap(),
+                _ => {
+                    pending.push((buf.len(), field));
+                    buf.write_u32::<LittleEndian>(0).unwrap();
+                }
+            }
+        }
+        for (field_pos, field) in pending {
+            let target = buf.len();
+            match *field {
+                Field::Bytes(ref bytes) => {
+                    buf.write_u32::<LittleEndian>(bytes.len() as u32).unwrap();
+                    buf.extend_from_slice(bytes);
+                }
+                Field::F64s(ref values) => {
+                    buf.write_u32::<LittleEndian>(values.len() as u32).unwrap();
+                    for &v in values {
+                        buf.write_f64::<LittleEndian>(v).unwrap();
+                    }
+                }
+                Field::U32s(ref values) => {
+                    buf.write_u32::<LittleEndian>(values.len() as u32).unwrap();
+                    for &v in values {
+                        buf.write_u32::<LittleEndian>(v).unwrap();
+                    }
+                }
+                Field::Tables(ref tables) => {
+                    buf.write_u32::<LittleEndian>(tables.len() as u32).unwrap();
+                    let start = buf.len();
+                    buf.extend(vec![0u8; 4 * tables.len()]);
+                    for (ix, table) in tables.iter().enumerate() {
+                        let pos = write_table(buf, table);
+                        let element = start + 4 * ix;
+                        LittleEndian::write_u32(&mut buf[element..element + 4], (pos - element) as u32);
+                    }
+                }
+                Field::Table(ref table) => {
+                    let pos = write_table(buf, table);
+                    LittleEndian::write_u32(&mut buf[field_pos..field_pos + 4],
+                                            (pos - field_pos) as u32);
+                    continue;
+                }
+                _ => unreachable!(),
+            }
+            LittleEndian::write_u32(&mut buf[field_pos..field_pos + 4], (target - field_pos) as u32);
+        }
+        table_pos
+    }
+
+    /// FlatBuffers buffer whose root is the given table.
+    fn root(fields: &[Field]) -> Vec<u8> {
+        let mut buf = vec![0u8; 4];
+        let pos = write_table(&mut buf, fields);
+        LittleEndian::write_u32(&mut buf[0..4], pos as u32);
+        buf
+    }
+
+    fn size_prefixed(buf: &[u8]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.write_u32::<LittleEndian>(buf.len() as u32).unwrap();
+        data.extend_from_slice(buf);
+        data
+    }
+
+    fn header(index_node_size: u16, crs: Option<i32>) -> Vec<u8> {
+        root(&[Field::Absent,
+               Field::Absent,
+               // Points:
+               Field::U8(1),
+               Field::Absent,
+               Field::Absent,
+               Field::Absent,
+               Field::Absent,
+               Field::Tables(vec![vec![Field::Bytes(b"name".to_vec()), Field::U8(11)],
+                                  vec![Field::Bytes(b"pop".to_vec()), Field::U8(5)]]),
+               Field::U64(2),
+               Field::U16(index_node_size),
+               match crs {
+                   Some(code) => Field::Table(vec![Field::Bytes(b"EPSG".to_vec()), Field::I32(code)]),
+                   None => Field::Absent,
+               }])
+    }
+
+    fn feature(x: f64, y: f64, name: &str, pop: i32) -> Vec<u8> {
+        let mut properties = Vec::new();
+        properties.write_u16::<LittleEndian>(0).unwrap();
+        properties.write_u32::<LittleEndian>(name.len() as u32).unwrap();
+        properties.extend_from_slice(name.as_bytes());
+        properties.write_u16::<LittleEndian>(1).unwrap();
+        properties.write_i32::<LittleEndian>(pop).unwrap();
+        root(&[Field::Table(vec![Field::Absent, Field::F64s(vec![x, y])]),
+               Field::Bytes(properties)])
+    }
+
+    /// Index node: its bounding box and the offset of its first child (or of its feature).
+    fn node(left: f64, bottom: f64, right: f64, top: f64, offset: u64) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &v in &[left, bottom, right, top] {
+            data.write_f64::<LittleEndian>(v).unwrap();
+        }
+        data.write_u64::<LittleEndian>(offset).unwrap();
+        data
+    }
+
+    /// A layer of two points, (0, 0) and (10, 10), with a spatial index if `indexed`.
+    fn layer_data(indexed: bool, crs: Option<i32>) -> Vec<u8> {
+        let mut data = b"fgb\x03fgb\x00".to_vec();
+        data.extend(size_prefixed(&header(if indexed { 16 } else { 0 }, crs)));
+        let features = vec![size_prefixed(&feature(0.0, 0.0, "Origin", 12)),
+                            size_prefixed(&feature(10.0, 10.0, "Far", -3))];
+        if indexed {
+            data.extend(node(0.0, 0.0, 10.0, 10.0, 1));
+            data.extend(node(0.0, 0.0, 0.0, 0.0, 0));
+            data.extend(node(10.0, 10.0, 10.0, 10.0, features[0].len() as u64));
+        }
+        for f in features {
+            data.extend(f);
+        }
+        data
+    }
+
+    fn write_layer(dir: &TempDir, name: &str, indexed: bool, crs: Option<i32>) -> PathBuf {
+        dir.write(name, &layer_data(indexed, crs))
+    }
+
+    fn read_layer(path: &PathBuf, filter: Option<&BboxFilter>) -> Result<LayerSource, String> {
+        read(path.to_str().unwrap(), None, filter)
+    }
+
+    fn around_far_point() -> BboxFilter {
+        BboxFilter::LonLat(MapExtent {
+                               left: 5.0,
+                               right: 15.0,
+                               bottom: 5.0,
+                               top: 15.0,
+                           })
+    }
+
+    #[test]
+    fn computes_the_levels_of_the_index() {
+        assert_eq!(level_bounds(2, 16), vec![(1, 3), (0, 1)]);
+        assert_eq!(level_bounds(5, 2), vec![(6, 11), (3, 6), (1, 3), (0, 1)]);
+    }
+
+    #[test]
+    fn reads_every_feature_and_its_properties() {
+        let dir = TempDir::new("fgb-all-test");
+        let layer = read_layer(&write_layer(&dir, "all.fgb", true, None), None).unwrap();
+        assert_eq!(layer.crs, None);
+        let features = features(layer);
+        assert_eq!(features.len(), 2);
+        assert_eq!(features[1].geometry.as_ref().unwrap().value,
+                   Value::Point(vec![10.0, 10.0]));
+        let properties = features[0].properties.as_ref().unwrap();
+        assert_eq!(properties["name"], JsonValue::String("Origin".to_string()));
+        assert_eq!(properties["pop"], JsonValue::from(12));
+    }
+
+    #[test]
+    fn only_reads_the_features_in_the_filter() {
+        let dir = TempDir::new("fgb-filter-test");
+        let filter = around_far_point();
+        for &(name, indexed) in &[("indexed.fgb", true), ("unindexed.fgb", false)] {
+            let path = write_layer(&dir, name, indexed, None);
+            let features = features(read_layer(&path, Some(&filter)).unwrap());
+            assert_eq!(features.len(), 1);
+            assert_eq!(features[0].properties.as_ref().unwrap()["pop"], JsonValue::from(-3));
+        }
+    }
+
+    #[test]
+    fn reads_the_coordinate_system() {
+        let dir = TempDir::new("fgb-crs-test");
+        let layer = read_layer(&write_layer(&dir, "lambert.fgb", false, Some(2154)), None).unwrap();
+        assert_eq!(layer.crs, Some("+init=epsg:2154".to_string()));
+        let layer = read_layer(&write_layer(&dir, "wgs84.fgb", false, Some(4326)), None).unwrap();
+        assert_eq!(layer.crs, None);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let dir = TempDir::new("fgb-invalid-test");
+        assert!(read_layer(&dir.write("invalid.fgb", b"GeoJSON!"), None).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let dir = TempDir::new("fgb-truncated-test");
+        for &indexed in &[true, false] {
+            let mut data = layer_data(indexed, None);
+            let len = data.len();
+            data.truncate(len - 3);
+            let path = dir.write("truncated.fgb", &data);
+            assert_eq!(read_layer(&path, None).err(),
+                       Some("Truncated FlatGeobuf file!".to_string()));
+            assert!(read_layer(&path, Some(&around_far_point())).is_err());
+        }
+        // A size larger than the file:
+        let mut data = b"fgb\x03fgb\x00".to_vec();
+        data.write_u32::<LittleEndian>(u32::max_value()).unwrap();
+        assert_eq!(read_layer(&dir.write("huge.fgb", &data), None).err(),
+                   Some("Invalid FlatGeobuf file (wrong size of a buffer)!".to_string()));
+    }
+}
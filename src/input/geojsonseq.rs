@@ -0,0 +1,150 @@
+use std::io::{BufRead, BufReader, Read};
+use geojson::{Feature, GeoJson};
+
+use super::LayerSource;
+use super::archive::open_input;
+
+/// Record separator, starting each text of a GeoJSON text sequence (RFC 8142).
+const RECORD_SEPARATOR: u8 = 0x1E;
+
+/// Iterator over the texts of a GeoJSON sequence, with their location (for the
+/// error messages). When the file starts with a record separator, the texts are
+/// delimited by record separators (RFC 8142) and can span several lines;
+/// otherwise there is one text per line (newline-delimited GeoJSON).
+pub struct SeqTexts {
+    reader: BufReader<Box<dyn Read>>,
+    delimiter: u8,
+    count: usize,
+}
+
+impl SeqTexts {
+    pub fn new(path: &str) -> Result<SeqTexts, String> {
+        let mut reader = BufReader::new(open_input(path)?);
+        let starts_with_separator = reader.fill_buf()
+            .map_err(|err| err.to_string())?
+            .first() == Some(&RECORD_SEPARATOR);
+        let delimiter = if starts_with_separator {
+            reader.consume(1);
+            RECORD_SEPARATOR
+        } else {
+            b'\n'
+        };
+        Ok(SeqTexts {
+               reader: reader,
+               delimiter: delimiter,
+               count: 0,
+           })
+    }
+
+    fn location(&self) -> String {
+        if self.delimiter == RECORD_SEPARATOR {
+            format!("Text {}", self.count)
+        } else {
+            format!("Line {}", self.count)
+        }
+    }
+}
+
+impl Iterator for SeqTexts {
+    type Item = Result<(String, String), String>;
+
+    fn next(&mut self) -> Option<Result<(String, String), String>> {
+        loop {
+            let mut buf = Vec::new();
+            match self.reader.read_until(self.delimiter, &mut buf) {
+                Ok(0) => return None,
+                Ok(_) => self.count += 1,
+                Err(err) => return Some(Err(err.to_string())),
+            }
+            let text = match String::from_utf8(buf) {
+                Ok(text) => text,
+                Err(err) => return Some(Err(format!("{}: {}", self.location(), err))),
+            };
+            // The newline-delimited files can also prefix each line by a record separator:
+            let text = text.trim_matches(|c: char| c == '\u{1e}' || c.is_whitespace());
+            if !text.is_empty() {
+                return Some(Ok((self.location(), text.to_string())));
+            }
+        }
+    }
+}
+
+/// Decodes a text of a sequence, which can be a Feature or a FeatureCollection.
+pub fn decode_text(location: &str, text: &str) -> Result<Vec<Feature>, String> {
+    match text.parse::<GeoJson>() {
+        Ok(GeoJson::Feature(feature)) => Ok(vec![feature]),
+        Ok(GeoJson::FeatureCollection(collection)) => Ok(collection.features),
+        Ok(GeoJson::Geometry(_)) => Err(format!("{}: expected a Feature, found a Geometry!", location)),
+        Err(err) => Err(format!("{}: {:?}", location, err)),
+    }
+}
+
+/// Reads a GeoJSON text sequence (see `SeqTexts`).
+pub fn read(path: &str) -> Result<LayerSource, String> {
+    let mut features = Vec::new();
+    for text in SeqTexts::new(path)? {
+        let (location, text) = text?;
+        features.extend(decode_text(&location, &text)?);
+    }
+    Ok(LayerSource::from_features(features))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::stream::stream_seq;
+    use super::super::test_utils::{TempDir, features};
+
+    const POINT: &'static str = r#"{"type": "Feature", "properties": {"n": 1},
+        "geometry": {"type": "Point", "coordinates": [1, 2]}}"#;
+    const COLLECTION: &'static str = r#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {"n": 2}, "geometry": null},
+        {"type": "Feature", "properties": {"n": 3}, "geometry": null}]}"#;
+
+    /// Reads a sequence with both readers, returning the `n` property of the features.
+    fn read_both(name: &str, content: &str) -> (Result<Vec<i64>, String>, Result<Vec<i64>, String>) {
+        let dir = TempDir::new(&format!("geojsonseq-{}", name));
+        let path = dir.write(name, content.as_bytes());
+        let numbers = |features: Vec<Feature>| {
+            features.iter()
+                .map(|f| f.properties.as_ref().unwrap()["n"].as_i64().unwrap())
+                .collect::<Vec<i64>>()
+        };
+        let read_all = read(path.to_str().unwrap()).map(|layer| numbers(features(layer)));
+        let streamed = stream_seq(path.to_str().unwrap())
+            .and_then(|features| features.collect::<Result<Vec<Feature>, String>>())
+            .map(&numbers);
+        (read_all, streamed)
+    }
+
+    #[test]
+    fn reads_record_separated_sequences() {
+        let content = format!("\u{1e}{}\n\u{1e}{}\n", POINT, COLLECTION);
+        let (read_all, streamed) = read_both("pretty.geojsons", &content);
+        assert_eq!(read_all, Ok(vec![1, 2, 3]));
+        assert_eq!(streamed, Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn reads_newline_delimited_sequences() {
+        let content = format!("{}\n\n{}\n",
+                              POINT.replace("\n", ""),
+                              COLLECTION.replace("\n", ""));
+        let (read_all, streamed) = read_both("lines.geojsonl", &content);
+        assert_eq!(read_all, Ok(vec![1, 2, 3]));
+        assert_eq!(streamed, Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn reports_the_location_of_the_errors() {
+        let content = format!("{}\n{{\"type\": \"Point\", \"coordinates\": [0, 0]}}\n",
+                              POINT.replace("\n", ""));
+        let (read_all, streamed) = read_both("errors.geojsonl", &content);
+        assert_eq!(read_all, Err("Line 2: expected a Feature, found a Geometry!".to_string()));
+        assert_eq!(streamed, read_all);
+
+        let content = format!("\u{1e}{}\n\u{1e}{{\n", POINT);
+        let (read_all, _) = read_both("errors.geojsons", &content);
+        assert!(read_all.unwrap_err().starts_with("Text 2: "));
+    }
+}
@@ -0,0 +1,149 @@
+use geojson::{Feature, Geometry, Value};
+use serde_json::{Map, Number, Value as JsonValue};
+
+use super::LayerSource;
+use super::archive::open_input;
+use super::xml_tree::Element;
+
+/// Reads the waypoints, routes and tracks of a GPX file. The elevations and
+/// times of the points of routes and tracks are kept as arrays of values.
+pub fn read(path: &str) -> Result<LayerSource, String> {
+    let root = Element::parse(open_input(path)?)?;
+    if root.name != "gpx" {
+        return Err("Expected a GPX document!".to_string());
+    }
+    let mut features = Vec::new();
+    for wpt in root.children_named("wpt") {
+        let mut properties = metadata(wpt, "waypoint");
+        let (position, ele, time) = read_point(wpt)?;
+        properties.insert("ele".to_string(), ele);
+        properties.insert("time".to_string(), time);
+        features.push(make_feature(Value::Point(position), properties));
+    }
+    for rte in root.children_named("rte") {
+        let mut properties = metadata(rte, "route");
+        let (line, elevations, times) = read_points(&rte.children_named("rtept"))?;
+        // A route without any point has no geometry to draw:
+        if line.is_empty() {
+            continue;
+        }
+        properties.insert("ele".to_string(), JsonValue::Array(elevations));
+        properties.insert("time".to_string(), JsonValue::Array(times));
+        features.push(make_feature(Value::LineString(line), properties));
+    }
+    for trk in root.children_named("trk") {
+        let mut properties = metadata(trk, "track");
+        let mut lines = Vec::new();
+        let mut all_elevations = Vec::new();
+        let mut all_times = Vec::new();
+        for trkseg in trk.children_named("trkseg") {
+            let (line, elevations, times) = read_points(&trkseg.children_named("trkpt"))?;
+            if line.is_empty() {
+                continue;
+            }
+            lines.push(line);
+            all_elevations.extend(elevations);
+            all_times.extend(times);
+        }
+        if lines.is_empty() {
+            continue;
+        }
+        properties.insert("ele".to_string(), JsonValue::Array(all_elevations));
+        properties.insert("time".to_string(), JsonValue::Array(all_times));
+        let value = if lines.len() == 1 {
+            Value::LineString(lines.pop().unwrap())
+        } else {
+            Value::MultiLineString(lines)
+        };
+        features.push(make_feature(value, properties));
+    }
+    Ok(LayerSource::from_features(features))
+}
+
+fn make_feature(value: Value, properties: Map<String, JsonValue>) -> Feature {
+    Feature {
+        geometry: Some(Geometry::new(value)),
+        properties: Some(properties),
+        bbox: None,
+        id: None,
+        foreign_members: None,
+    }
+}
+
+/// Descriptive properties of a waypoint, a route or a track.
+fn metadata(element: &Element, gpx_type: &str) -> Map<String, JsonValue> {
+    let mut properties = Map::new();
+    properties.insert("gpx-type".to_string(), JsonValue::String(gpx_type.to_string()));
+    for name in &["name", "cmt", "desc", "src", "sym", "type", "number"] {
+        if let Some(text) = element.child_text(name) {
+            properties.insert(name.to_string(), JsonValue::String(text.to_string()));
+        }
+    }
+    properties
+}
+
+/// Reads the position, the elevation and the time of a point.
+fn read_point(point: &Element) -> Result<(Vec<f64>, JsonValue, JsonValue), String> {
+    let coord = |name: &str| {
+        point.attr(name)
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .ok_or_else(|| format!("Invalid or missing \"{}\" attribute in GPX point!", name))
+    };
+    let position = vec![coord("lon")?, coord("lat")?];
+    let ele = point.child_text("ele")
+        .and_then(|e| e.parse::<f64>().ok())
+        .and_then(Number::from_f64)
+        .map(JsonValue::Number)
+        .unwrap_or(JsonValue::Null);
+    let time = point.child_text("time")
+        .map(|t| JsonValue::String(t.to_string()))
+        .unwrap_or(JsonValue::Null);
+    Ok((position, ele, time))
+}
+
+fn read_points(points: &[&Element]) -> Result<(Vec<Vec<f64>>, Vec<JsonValue>, Vec<JsonValue>), String> {
+    let mut line = Vec::with_capacity(points.len());
+    let mut elevations = Vec::with_capacity(points.len());
+    let mut times = Vec::with_capacity(points.len());
+    for point in points {
+        let (position, ele, time) = read_point(point)?;
+        line.push(position);
+        elevations.push(ele);
+        times.push(time);
+    }
+    Ok((line, elevations, times))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_utils::{TempDir, features};
+
+    #[test]
+    fn skips_the_routes_and_segments_without_points() {
+        let dir = TempDir::new("gpx-test");
+        let path = dir.write("walk.gpx",
+                             br#"<?xml version="1.0"?>
+<gpx version="1.1" xmlns="http://www.topografix.com/GPX/1/1">
+  <rte><name>Empty route</name></rte>
+  <trk><name>Empty track</name><trkseg></trkseg></trk>
+  <trk>
+    <name>Walk</name>
+    <trkseg></trkseg>
+    <trkseg>
+      <trkpt lat="45.0" lon="5.0"><ele>210.5</ele></trkpt>
+      <trkpt lat="45.1" lon="5.1"></trkpt>
+    </trkseg>
+  </trk>
+</gpx>"#);
+        let features = features(read(path.to_str().unwrap()).unwrap());
+        assert_eq!(features.len(), 1);
+        let properties = features[0].properties.as_ref().unwrap();
+        assert_eq!(properties["name"], JsonValue::String("Walk".to_string()));
+        assert_eq!(properties["ele"],
+                   JsonValue::Array(vec![JsonValue::Number(Number::from_f64(210.5).unwrap()),
+                                         JsonValue::Null]));
+        assert_eq!(features[0].geometry.as_ref().unwrap().value,
+                   Value::LineString(vec![vec![5.0, 45.0], vec![5.1, 45.1]]));
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use geojson::Feature;
+use serde_json::{Map, Value as JsonValue};
+use toml;
+
+use super::csv::{Table, delimiter_option, has_leading_zero, read_table, typed_records};
+
+/// Table of attributes joined to the features of a layer, configured by the
+/// `join` section of the layer:
+///
+/// ```toml
+/// [layer.join]
+/// table = "stats.csv"
+/// table-key = "code"  # column of the table
+/// layer-key = "iso"   # property of the features
+/// ```
+pub struct JoinTable {
+    path: String,
+    layer_key: String,
+    records: HashMap<String, Map<String, JsonValue>>,
+    /// Keys of the table matched by at least one feature.
+    matched: BTreeSet<String>,
+    /// Keys of the features without a matching record (or `None` if a feature
+    /// has no key at all).
+    unmatched: BTreeSet<Option<String>>,
+}
+
+/// Normalizes a key, so that the integral numbers written as floats (like "1.0")
+/// match the same integers (like "1"). The codes with leading zeros are kept as is.
+fn normalize_key(key: &str) -> String {
+    match key.parse::<f64>() {
+        Ok(v) if v.is_finite() && v.fract() == 0.0 && v.abs() < 1e15 && !has_leading_zero(key) => {
+            (v as i64).to_string()
+        }
+        _ => key.to_string(),
+    }
+}
+
+/// String used to compare the key of a feature with the keys of the table.
+fn key_string(value: &JsonValue) -> Option<String> {
+    match *value {
+        JsonValue::String(ref s) => Some(normalize_key(s.trim())),
+        JsonValue::Number(ref n) => Some(normalize_key(&n.to_string())),
+        JsonValue::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+impl JoinTable {
+    pub fn from_config(options: &BTreeMap<String, toml::Value>) -> Result<JoinTable, String> {
+        let option = |name: &str| {
+            options.get(name)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| format!("\"{}\" is required (as a string) in the \"join\" section!", name))
+        };
+        let path = option("table")?;
+        let table_key = option("table-key")?;
+        let layer_key = option("layer-key")?;
+
+        let Table { header, rows, .. } = read_table(path, delimiter_option(Some(options))?)
+            .map_err(|err| format!("Unable to read table \"{}\": {}", path, err))?;
+        let key_column = header.iter()
+            .position(|h| h == table_key)
+            .ok_or_else(|| format!("No column named \"{}\" in the table \"{}\"!", table_key, path))?;
+        let mut records = HashMap::with_capacity(rows.len());
+        for (row, properties) in rows.iter().zip(typed_records(&header, &rows, &[key_column])) {
+            let key = row.get(key_column).map(|k| k.trim()).unwrap_or("");
+            if key.is_empty() {
+                continue;
+            }
+            if records.insert(normalize_key(key), properties).is_some() {
+                println!("Warning: duplicated key \"{}\" in the table \"{}\", only its last record is used.",
+                         key,
+                         path);
+            }
+        }
+        Ok(JoinTable {
+               path: path.to_string(),
+               layer_key: layer_key.to_string(),
+               records: records,
+               matched: BTreeSet::new(),
+               unmatched: BTreeSet::new(),
+           })
+    }
+
+    /// Adds the columns of the record matching the feature to its properties
+    /// (replacing the properties of the same name).
+    pub fn join(&mut self, feature: &mut Feature) {
+        let key = feature.properties
+            .as_ref()
+            .and_then(|p| p.get(&self.layer_key))
+            .and_then(key_string);
+        match key.as_ref().and_then(|k| self.records.get(k)) {
+            Some(record) => {
+                let properties = feature.properties.get_or_insert_with(Map::new);
+                for (name, value) in record {
+                    properties.insert(name.clone(), value.clone());
+                }
+            }
+            None => {
+                self.unmatched.insert(key);
+                return;
+            }
+        }
+        self.matched.insert(key.unwrap());
+    }
+
+    /// Prints the keys which couldn't be matched, on both sides of the join.
+    pub fn report(&self, layer_name: &str) {
+        let no_key = self.unmatched.contains(&None);
+        let unmatched_features = self.unmatched
+            .iter()
+            .filter_map(|k| k.as_ref().map(|k| k.as_str()))
+            .collect::<Vec<&str>>();
+        if no_key {
+            println!("Warning: some features of layer \"{}\" have no \"{}\" property to join with \"{}\".",
+                     layer_name,
+                     self.layer_key,
+                     self.path);
+        }
+        if !unmatched_features.is_empty() {
+            println!("Warning: {} key(s) of layer \"{}\" not found in \"{}\": {}",
+                     unmatched_features.len(),
+                     layer_name,
+                     self.path,
+                     unmatched_features.join(", "));
+        }
+        let mut unmatched_records = self.records
+            .keys()
+            .filter(|k| !self.matched.contains(*k))
+            .map(|k| k.as_str())
+            .collect::<Vec<&str>>();
+        if !unmatched_records.is_empty() {
+            unmatched_records.sort();
+            println!("Warning: {} key(s) of \"{}\" not found in layer \"{}\": {}",
+                     unmatched_records.len(),
+                     self.path,
+                     layer_name,
+                     unmatched_records.join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Number;
+    use super::*;
+
+    #[test]
+    fn normalizes_the_numeric_keys() {
+        let float = JsonValue::Number(Number::from_f64(1.0).unwrap());
+        assert_eq!(key_string(&float), Some("1".to_string()));
+        assert_eq!(key_string(&JsonValue::Number(Number::from(1))), Some("1".to_string()));
+        assert_eq!(key_string(&JsonValue::String(" 1.0 ".to_string())), Some("1".to_string()));
+        assert_eq!(key_string(&JsonValue::Number(Number::from_f64(1.5).unwrap())),
+                   Some("1.5".to_string()));
+        assert_eq!(key_string(&JsonValue::String("01".to_string())), Some("01".to_string()));
+        assert_eq!(key_string(&JsonValue::String("FR".to_string())), Some("FR".to_string()));
+        assert_eq!(key_string(&JsonValue::Null), None);
+        assert_eq!(normalize_key("2.00"), normalize_key("2"));
+    }
+}
@@ -0,0 +1,255 @@
+use geojson::{Feature, Geometry, Value};
+use serde_json::{Map, Value as JsonValue};
+
+use super::LayerSource;
+use super::archive::open_input;
+use super::xml_tree::Element;
+
+/// Reads the placemarks of a KML file. The name of the folder(s) containing a
+/// placemark is stored in its `folder` property (as a path like "Trips/2017").
+pub fn read(path: &str) -> Result<LayerSource, String> {
+    let root = Element::parse(open_input(path)?)?;
+    if root.name != "kml" {
+        return Err("Expected a KML document!".to_string());
+    }
+    let mut features = Vec::new();
+    collect_placemarks(&root, &mut Vec::new(), &mut features)?;
+    Ok(LayerSource::from_features(features))
+}
+
+fn collect_placemarks(element: &Element,
+                      folders: &mut Vec<String>,
+                      features: &mut Vec<Feature>)
+                      -> Result<(), String> {
+    for child in &element.children {
+        match child.name.as_str() {
+            "Folder" => {
+                folders.push(child.child_text("name").unwrap_or("").to_string());
+                collect_placemarks(child, folders, features)?;
+                folders.pop();
+            }
+            "Document" => collect_placemarks(child, folders, features)?,
+            "Placemark" => features.push(read_placemark(child, folders)?),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn read_placemark(placemark: &Element, folders: &[String]) -> Result<Feature, String> {
+    let mut properties = Map::new();
+    for name in &["name", "description", "address"] {
+        if let Some(text) = placemark.child_text(name) {
+            properties.insert(name.to_string(), JsonValue::String(text.to_string()));
+        }
+    }
+    if !folders.is_empty() {
+        properties.insert("folder".to_string(), JsonValue::String(folders.join("/")));
+    }
+    if let Some(extended_data) = placemark.child("ExtendedData") {
+        for data in extended_data.children_named("Data") {
+            if let (Some(name), Some(value)) = (data.attr("name"), data.child_text("value")) {
+                properties.insert(name.to_string(), JsonValue::String(value.to_string()));
+            }
+        }
+        for schema_data in extended_data.children_named("SchemaData") {
+            for data in schema_data.children_named("SimpleData") {
+                if let Some(name) = data.attr("name") {
+                    properties.insert(name.to_string(), JsonValue::String(data.text.trim().to_string()));
+                }
+            }
+        }
+    }
+
+    let mut geometry = None;
+    for child in &placemark.children {
+        if let Some(value) = read_geometry(child)? {
+            geometry = Some(Geometry::new(value));
+            break;
+        }
+    }
+    Ok(Feature {
+           geometry: geometry,
+           properties: Some(properties),
+           bbox: None,
+           id: placemark.attr("id").map(|id| JsonValue::String(id.to_string())),
+           foreign_members: None,
+       })
+}
+
+/// Parses the content of a `coordinates` element ("lon,lat[,alt]" tuples
+/// separated by whitespaces).
+fn read_coordinates(element: Option<&Element>) -> Result<Vec<Vec<f64>>, String> {
+    let element = element.ok_or_else(|| "Missing KML coordinates!".to_string())?;
+    element.text
+        .split_whitespace()
+        .map(|tuple| {
+            let coords = tuple.split(',')
+                .take(2)
+                .map(|c| c.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| format!("Invalid KML coordinates: {}", tuple))?;
+            if coords.len() < 2 {
+                return Err(format!("Invalid KML coordinates: {}", tuple));
+            }
+            Ok(coords)
+        })
+        .collect()
+}
+
+fn read_ring(boundary: &Element) -> Result<Vec<Vec<f64>>, String> {
+    let ring = boundary.child("LinearRing")
+        .ok_or_else(|| "Missing KML LinearRing!".to_string())?;
+    read_coordinates(ring.child("coordinates"))
+}
+
+/// Reads a KML geometry (`None` if the element isn't a geometry).
+fn read_geometry(element: &Element) -> Result<Option<Value>, String> {
+    let value = match element.name.as_str() {
+        "Point" => {
+            let mut positions = read_coordinates(element.child("coordinates"))?;
+            if positions.is_empty() {
+                return Err("Empty KML Point!".to_string());
+            }
+            Value::Point(positions.remove(0))
+        }
+        "LineString" | "LinearRing" => Value::LineString(read_coordinates(element.child("coordinates"))?),
+        "Polygon" => {
+            let outer = element.child("outerBoundaryIs")
+                .ok_or_else(|| "Missing KML outerBoundaryIs in Polygon!".to_string())?;
+            let mut rings = vec![read_ring(outer)?];
+            for inner in element.children_named("innerBoundaryIs") {
+                rings.push(read_ring(inner)?);
+            }
+            Value::Polygon(rings)
+        }
+        "MultiGeometry" => {
+            let mut members = Vec::new();
+            for child in &element.children {
+                if let Some(value) = read_geometry(child)? {
+                    members.push(value);
+                }
+            }
+            merge_members(members)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(value))
+}
+
+/// Merges the members of a MultiGeometry into a "Multi" geometry when they all have
+/// the same type, or else into a GeometryCollection.
+fn merge_members(members: Vec<Value>) -> Value {
+    let mut points = Vec::new();
+    let mut lines = Vec::new();
+    let mut polygons = Vec::new();
+    let mut geometries = Vec::with_capacity(members.len());
+    for member in members {
+        match member {
+            Value::Point(ref point) => points.push(point.clone()),
+            Value::LineString(ref line) => lines.push(line.clone()),
+            Value::Polygon(ref rings) => polygons.push(rings.clone()),
+            _ => {}
+        }
+        geometries.push(Geometry::new(member));
+    }
+    if points.len() == geometries.len() {
+        Value::MultiPoint(points)
+    } else if lines.len() == geometries.len() {
+        Value::MultiLineString(lines)
+    } else if polygons.len() == geometries.len() {
+        Value::MultiPolygon(polygons)
+    } else {
+        Value::GeometryCollection(geometries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_utils::{TempDir, features};
+
+    fn read_kml(name: &str, placemarks: &str) -> Result<Vec<Feature>, String> {
+        let dir = TempDir::new(name);
+        let content = format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2"><Document>{}</Document></kml>"#,
+                              placemarks);
+        let path = dir.write("layer.kml", content.as_bytes());
+        read(path.to_str().unwrap()).map(features)
+    }
+
+    fn geometry(feature: &Feature) -> &Value {
+        &feature.geometry.as_ref().unwrap().value
+    }
+
+    #[test]
+    fn reads_the_geometries() {
+        let features = read_kml("kml-geometries-test",
+                                r#"
+<Placemark><Point><coordinates> 5.0,45.0,210 </coordinates></Point></Placemark>
+<Placemark><LineString><coordinates>0,0 1,1
+  2,0</coordinates></LineString></Placemark>
+<Placemark><Polygon>
+  <outerBoundaryIs><LinearRing><coordinates>0,0 4,0 4,4 0,4 0,0</coordinates></LinearRing></outerBoundaryIs>
+  <innerBoundaryIs><LinearRing><coordinates>1,1 2,1 2,2 1,1</coordinates></LinearRing></innerBoundaryIs>
+</Polygon></Placemark>
+<Placemark><MultiGeometry>
+  <Point><coordinates>1,2</coordinates></Point>
+  <Point><coordinates>3,4</coordinates></Point>
+</MultiGeometry></Placemark>
+<Placemark><MultiGeometry>
+  <Point><coordinates>1,2</coordinates></Point>
+  <LineString><coordinates>0,0 1,1</coordinates></LineString>
+</MultiGeometry></Placemark>"#)
+            .unwrap();
+        assert_eq!(features.len(), 5);
+        assert_eq!(*geometry(&features[0]), Value::Point(vec![5.0, 45.0]));
+        assert_eq!(*geometry(&features[1]),
+                   Value::LineString(vec![vec![0.0, 0.0], vec![1.0, 1.0], vec![2.0, 0.0]]));
+        match *geometry(&features[2]) {
+            Value::Polygon(ref rings) => {
+                assert_eq!(rings.len(), 2);
+                assert_eq!(rings[0].len(), 5);
+                assert_eq!(rings[1][1], vec![2.0, 1.0]);
+            }
+            ref other => panic!("Expected a polygon, got {:?}", other),
+        }
+        assert_eq!(*geometry(&features[3]),
+                   Value::MultiPoint(vec![vec![1.0, 2.0], vec![3.0, 4.0]]));
+        assert_eq!(*geometry(&features[4]),
+                   Value::GeometryCollection(vec![Geometry::new(Value::Point(vec![1.0, 2.0])),
+                                                  Geometry::new(Value::LineString(vec![vec![0.0, 0.0],
+                                                                                       vec![1.0, 1.0]]))]));
+    }
+
+    #[test]
+    fn reads_the_properties() {
+        let features = read_kml("kml-properties-test",
+                                r#"
+<Folder><name>Trips</name><Folder><name>2017</name>
+  <Placemark id="p1">
+    <name>Lyon</name>
+    <ExtendedData>
+      <Data name="pop"><value>513275</value></Data>
+      <SchemaData><SimpleData name="code"> 69123 </SimpleData></SchemaData>
+    </ExtendedData>
+    <Point><coordinates>4.83,45.76</coordinates></Point>
+  </Placemark>
+</Folder></Folder>"#)
+            .unwrap();
+        let properties = features[0].properties.as_ref().unwrap();
+        assert_eq!(properties["name"], JsonValue::String("Lyon".to_string()));
+        assert_eq!(properties["folder"], JsonValue::String("Trips/2017".to_string()));
+        assert_eq!(properties["pop"], JsonValue::String("513275".to_string()));
+        assert_eq!(properties["code"], JsonValue::String("69123".to_string()));
+        assert_eq!(features[0].id, Some(JsonValue::String("p1".to_string())));
+    }
+
+    #[test]
+    fn rejects_polygons_without_outer_boundary() {
+        let placemark = r#"<Placemark><Polygon><innerBoundaryIs><LinearRing>
+<coordinates>1,1 2,1 2,2 1,1</coordinates></LinearRing></innerBoundaryIs></Polygon></Placemark>"#;
+        assert_eq!(read_kml("kml-polygon-test", placemark).err(),
+                   Some("Missing KML outerBoundaryIs in Polygon!".to_string()));
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use geojson::{Feature, FeatureCollection, GeoJson};
+use proj::Proj;
+use toml;
+
+use config_params::MapExtent;
+use layer::project_extent;
+
+mod archive;
+mod csv;
+mod flatgeobuf;
+mod geojsonseq;
+mod gpx;
+mod join;
+mod kml;
+mod shapefile;
+mod sqlite;
+mod stream;
+mod topojson;
+mod wkb;
+mod wkt;
+mod xml_tree;
+
+pub use self::join::JoinTable;
+
+/// A layer, as read from one of the supported input formats.
+pub struct LayerSource {
+    pub geojson: GeoJson,
+    /// Proj4 definition of the coordinate system of the layer
+    /// (`None` if the layer is in longitude / latitude).
+    pub crs: Option<String>,
+    /// Interior borders of the layer (for topological formats, the arcs
+    /// shared by several polygons).
+    pub borders: Option<GeoJson>,
+}
+
+impl LayerSource {
+    /// Layer made of the given features, in longitude / latitude and without borders.
+    pub fn from_features(features: Vec<Feature>) -> Self {
+        LayerSource {
+            geojson: GeoJson::from(FeatureCollection {
+                                       bbox: None,
+                                       foreign_members: None,
+                                       features: features,
+                                   }),
+            crs: None,
+            borders: None,
+        }
+    }
+}
+
+/// Area of interest of the map, used by the readers backed by a spatial index
+/// to only decode the features intersecting it.
+pub enum BboxFilter {
+    /// Extent in longitude / latitude.
+    LonLat(MapExtent),
+}
+
+/// Expresses a filter in the coordinate system of a layer.
+fn filter_in_layer_crs(filter: &BboxFilter, crs: Option<&str>) -> Result<MapExtent, String> {
+    match (filter, crs) {
+        (&BboxFilter::LonLat(ref extent), Some(crs)) => {
+            let input_proj = Proj::new("+proj=longlat +ellps=WGS84 +datum=WGS84 +no_defs").unwrap();
+            let output_proj = Proj::new(crs)
+                .ok_or_else(|| format!("Invalid coordinate system: {}", crs))?;
+            Ok(project_extent(extent, &input_proj, &output_proj, 20))
+        }
+        (&BboxFilter::LonLat(ref extent), None) => Ok(extent.clone()),
+    }
+}
+
+/// Returns `true` if the layer at `path` is read through a spatial index, so
+/// that it's worth reading it once the extent of the map is known.
+pub fn is_indexed(path: &str) -> bool {
+    format_extension(path) == "fgb"
+}
+
+/// Extensions of the files which can be read as layers.
+const LAYER_FORMATS: [&'static str; 16] = ["csv", "fgb", "gpkg", "sqlite", "db", "geojson", "json",
+                                           "geojsonl", "geojsons", "geojsonseq", "ndjson", "jsonl",
+                                           "gpx", "kml", "shp", "topojson"];
+
+/// Extensions of the formats which are read from a file (and not from a stream),
+/// so that they have to be extracted from an archive before being read.
+const FILE_BASED_FORMATS: [&'static str; 6] = ["fgb", "gpkg", "sqlite", "db", "shp", "zip"];
+
+/// Returns the path without its `.gz` suffix, if any.
+fn strip_gz(path: &str) -> &str {
+    if path.to_lowercase().ends_with(".gz") {
+        &path[..path.len() - 3]
+    } else {
+        path
+    }
+}
+
+/// Returns the (lowercase) extension of the format of a file, ignoring the `.gz` suffix.
+fn format_extension(path: &str) -> String {
+    Path::new(strip_gz(path))
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Returns the name of a layer from its path, i.e. the path without its
+/// extension (and without its `.gz` suffix).
+pub fn layer_name(path: &str) -> &str {
+    let path = strip_gz(path);
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => &path[..path.len() - ext.len() - 1],
+        None => path,
+    }
+}
+
+/// Returns `true` if the layer at `path` can be read feature by feature
+/// (GeoJSON and newline-delimited GeoJSON files, possibly gzipped).
+pub fn is_streamable(path: &str) -> bool {
+    match format_extension(path).as_str() {
+        "geojson" | "json" | "geojsonl" | "geojsons" | "geojsonseq" | "ndjson" | "jsonl" => true,
+        _ => false,
+    }
+}
+
+/// Returns an iterator over the features of the layer at `path`, decoding them one
+/// at a time instead of loading the whole layer (see `is_streamable`).
+pub fn stream_layer(path: &str) -> Result<Box<dyn Iterator<Item = Result<Feature, String>>>, String> {
+    match format_extension(path).as_str() {
+        "geojson" | "json" => Ok(Box::new(stream::FeatureStream::new(path)?)),
+        "geojsonl" | "geojsons" | "geojsonseq" | "ndjson" | "jsonl" => stream::stream_seq(path),
+        _ => Err(format!("Layer {} can't be read in streaming mode!", path)),
+    }
+}
+
+/// Reads the layer located at `path`, choosing the reader from its extension.
+/// `options` is the section of the configuration file dedicated to this layer, if any,
+/// and `filter` the area of interest of the map, if already known.
+pub fn read_layer(path: &str,
+                  options: Option<&BTreeMap<String, toml::Value>>,
+                  filter: Option<&BboxFilter>)
+                  -> Result<LayerSource, String> {
+    let extension = format_extension(path);
+    let compressed = path.to_lowercase().ends_with(".gz");
+    if extension == "zip" || compressed && FILE_BASED_FORMATS.contains(&extension.as_str()) {
+        // Extract the archive in a temporary directory and read the layer from there:
+        let dir = archive::temp_dir()?;
+        let res = if compressed {
+            archive::gunzip(path, &dir).map(|p| vec![p])
+        } else {
+            archive::unzip(path, &dir)
+        }.and_then(|paths| {
+            let candidates = paths.iter()
+                .filter_map(|p| p.to_str())
+                .filter(|p| LAYER_FORMATS.contains(&format_extension(p).as_str()))
+                .collect::<Vec<&str>>();
+            match candidates.len() {
+                0 => Err(format!("No layer found in archive {}", path)),
+                1 => read_layer(candidates[0], options, filter),
+                _ => {
+                    let names = candidates.iter()
+                        .map(|p| Path::new(p).strip_prefix(&dir).unwrap_or_else(|_| Path::new(p)))
+                        .map(|p| p.display().to_string())
+                        .collect::<Vec<String>>();
+                    Err(format!("Several layers found in archive {}: {}", path, names.join(", ")))
+                }
+            }
+        });
+        let _ = fs::remove_dir_all(&dir);
+        return res;
+    }
+    match extension.as_str() {
+        "csv" => csv::read(path, options),
+        "fgb" => flatgeobuf::read(path, options, filter),
+        "gpkg" | "sqlite" | "db" => sqlite::read(path, options),
+        "geojsonl" | "geojsons" | "geojsonseq" | "ndjson" | "jsonl" => geojsonseq::read(path),
+        "gpx" => gpx::read(path),
+        "kml" => kml::read(path),
+        "shp" => shapefile::read(path, options),
+        "topojson" => topojson::read(path, options),
+        _ => read_geojson(path),
+    }
+}
+
+fn read_geojson(path: &str) -> Result<LayerSource, String> {
+    let mut file = archive::open_input(path)?;
+    let mut raw_json = String::new();
+    file.read_to_string(&mut raw_json).map_err(|err| err.to_string())?;
+    let decoded_geojson = raw_json.parse::<GeoJson>().map_err(|err| format!("{:?}", err))?;
+    Ok(LayerSource {
+           geojson: decoded_geojson,
+           crs: None,
+           borders: None,
+       })
+}
+
+#[cfg(test)]
+mod test_utils {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use geojson::{Feature, GeoJson};
+
+    use super::LayerSource;
+
+    /// Temporary directory of a test, removed with its content when dropped
+    /// (even when an assertion of the test fails).
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub fn new(name: &str) -> TempDir {
+            let path = env::temp_dir().join(format!("mapsvg-{}-{}", name, ::std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        pub fn path(&self, file_name: &str) -> PathBuf {
+            self.0.join(file_name)
+        }
+
+        /// Writes a file in the directory, returning its path.
+        pub fn write(&self, file_name: &str, content: &[u8]) -> PathBuf {
+            let path = self.path(file_name);
+            fs::write(&path, content).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Features of a layer, which should be a feature collection.
+    pub fn features(layer: LayerSource) -> Vec<Feature> {
+        match layer.geojson {
+            GeoJson::FeatureCollection(collection) => collection.features,
+            _ => panic!("Expected a feature collection"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+    use zip::{CompressionMethod, ZipWriter};
+    use zip::write::FileOptions;
+    use super::*;
+    use super::test_utils::{TempDir, features};
+
+    const POINT: &'static [u8] = br#"{"type": "FeatureCollection", "features": [
+        {"type": "Feature", "properties": {}, "geometry": {"type": "Point", "coordinates": [1, 2]}}]}"#;
+
+    fn write_zip(dir: &TempDir, name: &str, entries: &[(&str, &[u8])]) -> String {
+        let path = dir.path(name);
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for &(entry, content) in entries {
+            zip.start_file(entry, FileOptions::default().compression_method(CompressionMethod::Stored))
+                .unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn reads_the_layer_of_an_archive() {
+        let dir = TempDir::new("archive-test");
+        let path = write_zip(&dir,
+                             "single.zip",
+                             &[("data/points.geojson", POINT), ("data/readme.txt", b"Points")]);
+        assert_eq!(features(read_layer(&path, None, None).unwrap()).len(), 1);
+
+        let path = write_zip(&dir,
+                             "several.zip",
+                             &[("2016/points.geojson", POINT), ("2017/points.geojson", POINT)]);
+        assert_eq!(read_layer(&path, None, None).err(),
+                   Some(format!("Several layers found in archive {}: 2016/points.geojson, \
+                                 2017/points.geojson",
+                                path)));
+    }
+}
@@ -0,0 +1,581 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use geojson::{Feature, Geometry, Value};
+use serde_json::{Map, Number, Value as JsonValue};
+use toml;
+
+use super::LayerSource;
+
+pub fn read(path: &str, options: Option<&BTreeMap<String, toml::Value>>) -> Result<LayerSource, String> {
+    let shp_path = Path::new(path);
+    let geometries = read_shp(shp_path)?;
+
+    // Attributes are optional, as is the file describing their encoding
+    // (which can be overridden in the layer section of the configuration):
+    let encoding = match options.and_then(|o| o.get("encoding")).and_then(|e| e.as_str()) {
+        Some(e) => Some(e.to_string()),
+        None => read_sidecar(shp_path, "cpg"),
+    };
+    let records = if shp_path.with_extension("dbf").exists() {
+        Some(read_dbf(&shp_path.with_extension("dbf"), encoding.as_ref().map(|e| e.as_str()))?)
+    } else {
+        None
+    };
+    if let Some(ref r) = records {
+        if r.len() != geometries.len() {
+            return Err(format!("The .shp and .dbf files have a different number of records ({} and {})!",
+                               geometries.len(),
+                               r.len()));
+        }
+    }
+
+    let crs = match read_sidecar(shp_path, "prj") {
+        Some(wkt) => prj_to_proj4(&wkt)?,
+        None => None,
+    };
+
+    let mut features = Vec::with_capacity(geometries.len());
+    for (ix, geometry) in geometries.into_iter().enumerate() {
+        let properties = match records {
+            Some(ref r) => {
+                match r[ix] {
+                    Some(ref props) => Some(props.clone()),
+                    // Deleted record:
+                    None => continue,
+                }
+            }
+            None => None,
+        };
+        features.push(Feature {
+                          geometry: geometry.map(Geometry::new),
+                          properties: properties,
+                          bbox: None,
+                          id: None,
+                          foreign_members: None,
+                      });
+    }
+    Ok(LayerSource { crs: crs, ..LayerSource::from_features(features) })
+}
+
+fn read_sidecar(shp_path: &Path, extension: &str) -> Option<String> {
+    let mut content = String::new();
+    File::open(shp_path.with_extension(extension))
+        .and_then(|mut f| f.read_to_string(&mut content))
+        .ok()
+        .map(|_| content.trim().to_string())
+}
+
+fn read_shp(path: &Path) -> Result<Vec<Option<Value>>, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let file_size = file.metadata().map_err(|err| err.to_string())?.len();
+    let mut reader = BufReader::new(file);
+    let file_code = reader.read_i32::<BigEndian>().map_err(|err| err.to_string())?;
+    if file_code != 9994 {
+        return Err("Invalid shapefile (wrong file code)!".to_string());
+    }
+    reader.seek(SeekFrom::Start(24)).map_err(|err| err.to_string())?;
+    let file_length = reader.read_i32::<BigEndian>().map_err(|err| err.to_string())? as u64 * 2;
+    reader.seek(SeekFrom::Start(100)).map_err(|err| err.to_string())?;
+
+    let mut geometries = Vec::new();
+    let mut position = 100;
+    while position + 8 <= file_length {
+        let _record_number = reader.read_i32::<BigEndian>().map_err(|err| err.to_string())?;
+        let content_length = reader.read_i32::<BigEndian>().map_err(|err| err.to_string())?;
+        if content_length < 0 || position + 8 + content_length as u64 * 2 > file_size {
+            return Err("Invalid shapefile (wrong record length)!".to_string());
+        }
+        let content_length = content_length as u64 * 2;
+        let mut content = vec![0u8; content_length as usize];
+        reader.read_exact(&mut content).map_err(|err| err.to_string())?;
+        geometries.push(read_shape(&content)?);
+        position += 8 + content_length;
+    }
+    Ok(geometries)
+}
+
+fn read_shape(mut content: &[u8]) -> Result<Option<Value>, String> {
+    let shape_type = content.read_i32::<LittleEndian>().map_err(|err| err.to_string())?;
+    match shape_type {
+        0 => Ok(None),
+        // Point, PointZ, PointM:
+        1 | 11 | 21 => {
+            let x = content.read_f64::<LittleEndian>().map_err(|err| err.to_string())?;
+            let y = content.read_f64::<LittleEndian>().map_err(|err| err.to_string())?;
+            Ok(Some(Value::Point(vec![x, y])))
+        }
+        // MultiPoint, MultiPointZ, MultiPointM:
+        8 | 18 | 28 => {
+            skip_bbox(&mut content)?;
+            let nb_points = read_count(&mut content, 16)?;
+            let points = read_points(&mut content, nb_points)?;
+            Ok(Some(Value::MultiPoint(points)))
+        }
+        // PolyLine, PolygonLine Z/M and Polygon, Polygon Z/M:
+        3 | 13 | 23 | 5 | 15 | 25 => {
+            skip_bbox(&mut content)?;
+            let nb_parts = read_count(&mut content, 4)?;
+            let nb_points = read_count(&mut content, 16)?;
+            let mut parts = Vec::with_capacity(nb_parts);
+            for _ in 0..nb_parts {
+                parts.push(content.read_i32::<LittleEndian>().map_err(|err| err.to_string())? as usize);
+            }
+            let points = read_points(&mut content, nb_points)?;
+            let mut rings = Vec::with_capacity(nb_parts);
+            for (ix, &start) in parts.iter().enumerate() {
+                let end = if ix + 1 < nb_parts { parts[ix + 1] } else { nb_points };
+                if start >= end || end > nb_points {
+                    return Err("Invalid shapefile (wrong part index)!".to_string());
+                }
+                rings.push(points[start..end].to_vec());
+            }
+            if shape_type == 3 || shape_type == 13 || shape_type == 23 {
+                if rings.len() == 1 {
+                    Ok(Some(Value::LineString(rings.pop().unwrap())))
+                } else {
+                    Ok(Some(Value::MultiLineString(rings)))
+                }
+            } else {
+                Ok(Some(assemble_polygon(rings)))
+            }
+        }
+        _ => Err(format!("Unsupported shape type: {}", shape_type)),
+    }
+}
+
+fn skip_bbox(content: &mut &[u8]) -> Result<(), String> {
+    for _ in 0..4 {
+        content.read_f64::<LittleEndian>().map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
+/// Reads a number of items (parts or points), which can't be negative nor
+/// exceed the number of items of `item_size` bytes left in the record.
+fn read_count(content: &mut &[u8], item_size: usize) -> Result<usize, String> {
+    let count = content.read_i32::<LittleEndian>().map_err(|err| err.to_string())?;
+    if count < 0 || count as usize > content.len() / item_size {
+        return Err("Invalid shapefile (wrong number of parts or points)!".to_string());
+    }
+    Ok(count as usize)
+}
+
+fn read_points(content: &mut &[u8], nb_points: usize) -> Result<Vec<Vec<f64>>, String> {
+    let mut points = Vec::with_capacity(nb_points);
+    for _ in 0..nb_points {
+        let x = content.read_f64::<LittleEndian>().map_err(|err| err.to_string())?;
+        let y = content.read_f64::<LittleEndian>().map_err(|err| err.to_string())?;
+        points.push(vec![x, y]);
+    }
+    Ok(points)
+}
+
+fn signed_area(ring: &[Vec<f64>]) -> f64 {
+    let mut area = 0.0;
+    for i in 0..ring.len() {
+        let j = (i + 1) % ring.len();
+        area += ring[i][0] * ring[j][1] - ring[j][0] * ring[i][1];
+    }
+    area / 2.0
+}
+
+fn contains_point(ring: &[Vec<f64>], point: &[f64]) -> bool {
+    let mut inside = false;
+    let mut j = ring.len() - 1;
+    for i in 0..ring.len() {
+        if ((ring[i][1] > point[1]) != (ring[j][1] > point[1])) &&
+           (point[0] <
+            (ring[j][0] - ring[i][0]) * (point[1] - ring[i][1]) / (ring[j][1] - ring[i][1]) +
+            ring[i][0]) {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Groups the rings of a shapefile polygon: outer rings are clockwise,
+/// holes are counter-clockwise and belong to the outer ring containing them.
+fn assemble_polygon(rings: Vec<Vec<Vec<f64>>>) -> Value {
+    let mut polygons: Vec<Vec<Vec<Vec<f64>>>> = Vec::new();
+    let mut holes = Vec::new();
+    for ring in rings {
+        if signed_area(&ring) <= 0.0 {
+            polygons.push(vec![ring]);
+        } else {
+            holes.push(ring);
+        }
+    }
+    for hole in holes {
+        let owner = polygons
+            .iter()
+            .position(|poly| contains_point(&poly[0], &hole[0]));
+        match owner {
+            Some(ix) => polygons[ix].push(hole),
+            // An orphan hole is most likely a badly oriented outer ring:
+            None => polygons.push(vec![hole]),
+        }
+    }
+    if polygons.len() == 1 {
+        Value::Polygon(polygons.pop().unwrap())
+    } else {
+        Value::MultiPolygon(polygons)
+    }
+}
+
+struct DbfField {
+    name: String,
+    field_type: u8,
+    length: usize,
+    decimals: u8,
+}
+
+/// Reads the records of a .dbf file (`None` for the deleted records).
+fn read_dbf(path: &Path, encoding: Option<&str>) -> Result<Vec<Option<Map<String, JsonValue>>>, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let file_size = file.metadata().map_err(|err| err.to_string())?.len();
+    let mut reader = BufReader::new(file);
+    let mut header = [0u8; 32];
+    reader.read_exact(&mut header).map_err(|err| err.to_string())?;
+    let nb_records = (&header[4..8]).read_u32::<LittleEndian>().map_err(|err| err.to_string())?;
+    let header_length = (&header[8..10]).read_u16::<LittleEndian>().map_err(|err| err.to_string())?;
+    let record_length = (&header[10..12]).read_u16::<LittleEndian>().map_err(|err| err.to_string())?;
+    if record_length == 0 ||
+       header_length as u64 + nb_records as u64 * record_length as u64 > file_size {
+        return Err("Invalid .dbf file (wrong number or length of records)!".to_string());
+    }
+
+    let mut fields = Vec::new();
+    let mut position = 32;
+    while position + 32 <= header_length as usize {
+        let mut descriptor = [0u8; 32];
+        reader.read_exact(&mut descriptor[..1]).map_err(|err| err.to_string())?;
+        position += 1;
+        if descriptor[0] == 0x0D {
+            break;
+        }
+        reader.read_exact(&mut descriptor[1..]).map_err(|err| err.to_string())?;
+        position += 31;
+        let name_end = descriptor[..11].iter().position(|&b| b == 0).unwrap_or(11);
+        fields.push(DbfField {
+                        name: decode(&descriptor[..name_end], encoding).trim().to_string(),
+                        field_type: descriptor[11],
+                        length: descriptor[16] as usize,
+                        decimals: descriptor[17],
+                    });
+    }
+    reader.seek(SeekFrom::Start(header_length as u64)).map_err(|err| err.to_string())?;
+
+    let mut records = Vec::with_capacity(nb_records as usize);
+    let mut record = vec![0u8; record_length as usize];
+    for _ in 0..nb_records {
+        reader.read_exact(&mut record).map_err(|err| err.to_string())?;
+        if record[0] == b'*' {
+            records.push(None);
+            continue;
+        }
+        let mut properties = Map::new();
+        let mut offset = 1;
+        for field in &fields {
+            let raw = record.get(offset..(offset + field.length).min(record.len())).unwrap_or(&[]);
+            offset += field.length;
+            properties.insert(field.name.clone(), decode_field(field, raw, encoding));
+        }
+        records.push(Some(properties));
+    }
+    Ok(records)
+}
+
+fn decode_field(field: &DbfField, raw: &[u8], encoding: Option<&str>) -> JsonValue {
+    let text = decode(raw, encoding);
+    let text = text.trim();
+    match field.field_type {
+        b'N' | b'F' => {
+            if text.is_empty() || text.starts_with('*') {
+                return JsonValue::Null;
+            }
+            if field.decimals == 0 {
+                if let Ok(v) = text.parse::<i64>() {
+                    return JsonValue::Number(Number::from(v));
+                }
+            }
+            text.parse::<f64>()
+                .ok()
+                .and_then(Number::from_f64)
+                .map(JsonValue::Number)
+                .unwrap_or(JsonValue::Null)
+        }
+        b'L' => {
+            match text.chars().next() {
+                Some('T') | Some('t') | Some('Y') | Some('y') => JsonValue::Bool(true),
+                Some('F') | Some('f') | Some('N') | Some('n') => JsonValue::Bool(false),
+                _ => JsonValue::Null,
+            }
+        }
+        b'D' => {
+            if text.len() == 8 && text.bytes().all(|b| b.is_ascii_digit()) {
+                JsonValue::String(format!("{}-{}-{}", &text[..4], &text[4..6], &text[6..]))
+            } else {
+                JsonValue::Null
+            }
+        }
+        _ => JsonValue::String(text.to_string()),
+    }
+}
+
+/// Decodes some text using the encoding from the .cpg file (UTF-8 or Latin-1);
+/// without any encoding, UTF-8 is tried before falling back to Latin-1.
+fn decode(raw: &[u8], encoding: Option<&str>) -> String {
+    let latin1 = |raw: &[u8]| raw.iter().map(|&b| b as char).collect::<String>();
+    let raw = match raw.iter().position(|&b| b == 0) {
+        Some(end) => &raw[..end],
+        None => raw,
+    };
+    match encoding.map(|e| e.to_uppercase().replace("-", "").replace("_", "")) {
+        Some(ref e) if e == "UTF8" => String::from_utf8_lossy(raw).into_owned(),
+        Some(ref e) if e == "ISO88591" || e == "88591" || e == "LATIN1" || e == "1252" ||
+                       e == "CP1252" || e == "WINDOWS1252" => latin1(raw),
+        _ => String::from_utf8(raw.to_vec()).unwrap_or_else(|_| latin1(raw)),
+    }
+}
+
+/// Translates the WKT from a .prj file into a proj4 definition, for the most
+/// common cases (`None` means longitude / latitude). Any other coordinate system
+/// is an error, rather than drawing the layer at a wrong place.
+fn prj_to_proj4(wkt: &str) -> Result<Option<String>, String> {
+    if wkt.starts_with("GEOGCS") {
+        return Ok(None);
+    }
+    if let Some(code) = top_level_epsg_code(wkt) {
+        return Ok(Some(format!("+init=epsg:{}", code)));
+    }
+    let name = wkt.split('"').nth(1).unwrap_or("");
+    if name.contains("Web_Mercator") || name.contains("Pseudo_Mercator") {
+        return Ok(Some("+init=epsg:3857".to_string()));
+    }
+    if name == "RGF_1993_Lambert_93" || name == "RGF93_Lambert_93" {
+        return Ok(Some("+init=epsg:2154".to_string()));
+    }
+    if name.starts_with("WGS_1984_UTM_Zone_") {
+        let zone = &name[18..];
+        let south = zone.ends_with('S');
+        return Ok(Some(format!("+proj=utm +zone={}{} +datum=WGS84 +units=m +no_defs",
+                               zone.trim_end_matches(|c| c == 'N' || c == 'S'),
+                               if south { " +south" } else { "" })));
+    }
+    Err(format!("Unsupported coordinate system in .prj file: {}", name))
+}
+
+/// EPSG code of the authority of the coordinate system itself, at the top level of
+/// the WKT: the authorities of its GEOGCS, projection or units don't describe it.
+fn top_level_epsg_code(wkt: &str) -> Option<String> {
+    let mut depth = 0;
+    let mut in_quotes = false;
+    for (ix, c) in wkt.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '[' | '(' if !in_quotes => depth += 1,
+            ']' | ')' if !in_quotes => depth -= 1,
+            'A' if !in_quotes && depth == 1 && wkt[ix..].starts_with("AUTHORITY[\"EPSG\",") => {
+                let code = wkt[ix + 17..]
+                    .trim_start_matches('"')
+                    .chars()
+                    .take_while(|c| c.is_digit(10))
+                    .collect::<String>();
+                if !code.is_empty() {
+                    return Some(code);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{BigEndian, LittleEndian, WriteBytesExt};
+    use geojson::Value;
+    use serde_json::Value as JsonValue;
+    use super::*;
+    use super::super::test_utils::{TempDir, features};
+
+    /// Content of a polygon record made of the given rings.
+    fn polygon_record(rings: &[&[(f64, f64)]]) -> Vec<u8> {
+        let mut content = Vec::new();
+        content.write_i32::<LittleEndian>(5).unwrap();
+        for _ in 0..4 {
+            content.write_f64::<LittleEndian>(0.0).unwrap();
+        }
+        let nb_points = rings.iter().map(|r| r.len()).sum::<usize>();
+        content.write_i32::<LittleEndian>(rings.len() as i32).unwrap();
+        content.write_i32::<LittleEndian>(nb_points as i32).unwrap();
+        let mut start = 0;
+        for ring in rings {
+            content.write_i32::<LittleEndian>(start).unwrap();
+            start += ring.len() as i32;
+        }
+        for ring in rings {
+            for &(x, y) in ring.iter() {
+                content.write_f64::<LittleEndian>(x).unwrap();
+                content.write_f64::<LittleEndian>(y).unwrap();
+            }
+        }
+        content
+    }
+
+    fn point_record(x: f64, y: f64) -> Vec<u8> {
+        let mut content = Vec::new();
+        content.write_i32::<LittleEndian>(1).unwrap();
+        content.write_f64::<LittleEndian>(x).unwrap();
+        content.write_f64::<LittleEndian>(y).unwrap();
+        content
+    }
+
+    fn shp_file(records: &[Vec<u8>]) -> Vec<u8> {
+        let length = 100 + records.iter().map(|r| 8 + r.len()).sum::<usize>();
+        let mut data = Vec::new();
+        data.write_i32::<BigEndian>(9994).unwrap();
+        data.extend_from_slice(&[0u8; 20]);
+        data.write_i32::<BigEndian>((length / 2) as i32).unwrap();
+        data.extend_from_slice(&[0u8; 72]);
+        for (ix, record) in records.iter().enumerate() {
+            data.write_i32::<BigEndian>(ix as i32 + 1).unwrap();
+            data.write_i32::<BigEndian>((record.len() / 2) as i32).unwrap();
+            data.extend_from_slice(record);
+        }
+        data
+    }
+
+    /// A .dbf file with a character field `NAME` (10) and a numeric field `POP` (8),
+    /// the second record being deleted.
+    fn dbf_file(records: &[(&str, &str, bool)]) -> Vec<u8> {
+        let mut data = vec![3u8, 118, 1, 1];
+        data.write_u32::<LittleEndian>(records.len() as u32).unwrap();
+        data.write_u16::<LittleEndian>(32 + 2 * 32 + 1).unwrap();
+        data.write_u16::<LittleEndian>(1 + 10 + 8).unwrap();
+        data.extend_from_slice(&[0u8; 20]);
+        for &(name, field_type, length) in &[("NAME", b'C', 10u8), ("POP", b'N', 8u8)] {
+            let mut descriptor = [0u8; 32];
+            descriptor[..name.len()].copy_from_slice(name.as_bytes());
+            descriptor[11] = field_type;
+            descriptor[16] = length;
+            data.extend_from_slice(&descriptor);
+        }
+        data.push(0x0D);
+        for &(name, pop, deleted) in records {
+            data.push(if deleted { b'*' } else { b' ' });
+            data.extend_from_slice(format!("{:<10}", name).as_bytes());
+            data.extend_from_slice(format!("{:>8}", pop).as_bytes());
+        }
+        data.push(0x1A);
+        data
+    }
+
+    #[test]
+    fn reads_points_and_polygons_with_holes() {
+        let square = [(0.0, 0.0), (0.0, 10.0), (10.0, 10.0), (10.0, 0.0), (0.0, 0.0)];
+        let hole = [(2.0, 2.0), (4.0, 2.0), (4.0, 4.0), (2.0, 4.0), (2.0, 2.0)];
+        match read_shape(&polygon_record(&[&square, &hole])).unwrap() {
+            Some(Value::Polygon(rings)) => {
+                assert_eq!(rings.len(), 2);
+                assert_eq!(rings[1][1], vec![4.0, 2.0]);
+            }
+            other => panic!("Unexpected geometry: {:?}", other),
+        }
+        let other_square = [(20.0, 0.0), (20.0, 5.0), (25.0, 5.0), (25.0, 0.0), (20.0, 0.0)];
+        match read_shape(&polygon_record(&[&square, &other_square])).unwrap() {
+            Some(Value::MultiPolygon(polygons)) => assert_eq!(polygons.len(), 2),
+            other => panic!("Unexpected geometry: {:?}", other),
+        }
+        assert_eq!(read_shape(&point_record(1.5, -2.0)).unwrap(),
+                   Some(Value::Point(vec![1.5, -2.0])));
+        assert_eq!(read_shape(&[0, 0, 0, 0]).unwrap(), None);
+        assert!(read_shape(&[31, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_counts_of_parts_and_points() {
+        let square = [(0.0, 0.0), (0.0, 10.0), (10.0, 10.0), (10.0, 0.0), (0.0, 0.0)];
+        for &(offset, count) in &[(36, -1), (36, 1000), (40, -5), (40, i32::max_value())] {
+            let mut record = polygon_record(&[&square]);
+            (&mut record[offset..offset + 4]).write_i32::<LittleEndian>(count).unwrap();
+            assert!(read_shape(&record).is_err());
+        }
+    }
+
+    #[test]
+    fn translates_prj_files() {
+        assert_eq!(prj_to_proj4("GEOGCS[\"GCS_WGS_1984\",DATUM[\"D_WGS_1984\"]]").unwrap(),
+                   None);
+        let lambert = "PROJCS[\"RGF93 / Lambert-93\",GEOGCS[\"RGF93\",AUTHORITY[\"EPSG\",\"4171\"]],\
+                       AUTHORITY[\"EPSG\",\"2154\"]]";
+        assert_eq!(prj_to_proj4(lambert).unwrap(),
+                   Some("+init=epsg:2154".to_string()));
+        assert_eq!(prj_to_proj4("PROJCS[\"WGS_1984_UTM_Zone_33S\",GEOGCS[\"GCS_WGS_1984\"]]")
+                       .unwrap(),
+                   Some("+proj=utm +zone=33 +south +datum=WGS84 +units=m +no_defs".to_string()));
+        assert_eq!(prj_to_proj4("PROJCS[\"WGS_84_Pseudo_Mercator\",GEOGCS[\"GCS_WGS_1984\"]]")
+                       .unwrap(),
+                   Some("+init=epsg:3857".to_string()));
+        assert!(prj_to_proj4("PROJCS[\"NAD_1983_StatePlane_Texas\",GEOGCS[\"GCS_NAD83\"]]").is_err());
+        // Without an authority of its own, the PROJCS isn't the system of its GEOGCS
+        // (nor of its units):
+        let lambert = "PROJCS[\"RGF93_Lambert_93\",GEOGCS[\"GCS_RGF_1993\",AUTHORITY[\"EPSG\",\"4171\"]],\
+                       PROJECTION[\"Lambert_Conformal_Conic\"],UNIT[\"Meter\",1.0,AUTHORITY[\"EPSG\",\"9001\"]]]";
+        assert_eq!(prj_to_proj4(lambert).unwrap(),
+                   Some("+init=epsg:2154".to_string()));
+        assert!(prj_to_proj4("PROJCS[\"NAD_1983_StatePlane_Texas\",GEOGCS[\"GCS_NAD83\",\
+                              AUTHORITY[\"EPSG\",\"4269\"]]]")
+                        .is_err());
+    }
+
+    #[test]
+    fn reads_dates() {
+        let field = DbfField {
+            name: "DATE".to_string(),
+            field_type: b'D',
+            length: 8,
+            decimals: 0,
+        };
+        assert_eq!(decode_field(&field, b"20170621", None),
+                   JsonValue::String("2017-06-21".to_string()));
+        assert_eq!(decode_field(&field, &[0x32, 0x30, 0x31, 0xE9, 0x30, 0x31, 0x32], None),
+                   JsonValue::Null);
+        assert_eq!(decode_field(&field, b"        ", None), JsonValue::Null);
+    }
+
+    #[test]
+    fn decodes_latin1_and_utf8_text() {
+        assert_eq!(decode(&[0x53, 0xE3, 0x6F], Some("ISO-8859-1")), "São");
+        assert_eq!(decode("São".as_bytes(), None), "São");
+        assert_eq!(decode(&[0x53, 0xE3, 0x6F], None), "São");
+        assert_eq!(decode(b"abc\0\0", Some("UTF-8")), "abc");
+    }
+
+    #[test]
+    fn reads_a_layer_with_its_attributes() {
+        let dir = TempDir::new("shp-test");
+        let shp_path = dir.write("places.shp",
+                                 &shp_file(&[point_record(1.0, 2.0),
+                                             point_record(3.0, 4.0),
+                                             point_record(5.0, 6.0)]));
+        dir.write("places.dbf",
+                  &dbf_file(&[("Lyon", "513275", false),
+                              ("Removed", "1", true),
+                              ("Nowhere", "", false)]));
+        let layer = read(shp_path.to_str().unwrap(), None).unwrap();
+        assert_eq!(layer.crs, None);
+        let features = features(layer);
+        assert_eq!(features.len(), 2);
+        let properties = features[0].properties.as_ref().unwrap();
+        assert_eq!(properties["NAME"], JsonValue::String("Lyon".to_string()));
+        assert_eq!(properties["POP"], JsonValue::Number(Number::from(513275)));
+        assert_eq!(features[1].properties.as_ref().unwrap()["POP"], JsonValue::Null);
+        assert_eq!(features[1].geometry.as_ref().unwrap().value,
+                   Value::Point(vec![5.0, 6.0]));
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::BTreeMap;
+use geojson::{Feature, Geometry, Value};
+use rusqlite::{Connection, OpenFlags};
+use rusqlite::types::Value as SqlValue;
+use serde_json::{Map, Number, Value as JsonValue};
+use toml;
+
+use super::LayerSource;
+use super::wkb::WkbReader;
+
+/// Reads a table of a GeoPackage or of a SpatiaLite database. The table is given
+/// by the `table` option of the layer, and can be filtered with a SQL `where` option.
+pub fn read(path: &str, options: Option<&BTreeMap<String, toml::Value>>) -> Result<LayerSource, String> {
+    let option = |name: &str| options.and_then(|o| o.get(name)).and_then(|v| v.as_str());
+    let table = option("table")
+        .ok_or_else(|| "The \"table\" option is required to read a database layer!".to_string())?;
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|err| err.to_string())?;
+
+    // Find the geometry column (and its coordinate system) from the metadata
+    // tables of GeoPackage or, failing that, of SpatiaLite:
+    let gpkg = table_exists(&conn, "gpkg_geometry_columns");
+    let (geometry_column, srs_id) = if gpkg {
+        conn.query_row("SELECT column_name, srs_id FROM gpkg_geometry_columns WHERE table_name = ?1",
+                       &[&table],
+                       |row| (row.get::<_, String>(0), row.get::<_, i64>(1)))
+            .map_err(|_| format!("No table with geometries named \"{}\" in the GeoPackage!", table))?
+    } else if table_exists(&conn, "geometry_columns") {
+        conn.query_row("SELECT f_geometry_column, srid FROM geometry_columns \
+                        WHERE lower(f_table_name) = lower(?1)",
+                       &[&table],
+                       |row| (row.get::<_, String>(0), row.get::<_, i64>(1)))
+            .map_err(|_| format!("No table with geometries named \"{}\" in the database!", table))?
+    } else {
+        return Err("The database is neither a GeoPackage nor a SpatiaLite database!".to_string());
+    };
+    let crs = if gpkg {
+        conn.query_row("SELECT organization, organization_coordsys_id FROM gpkg_spatial_ref_sys \
+                        WHERE srs_id = ?1",
+                       &[&srs_id],
+                       |row| (row.get::<_, String>(0), row.get::<_, i64>(1)))
+            .ok()
+            .and_then(|(organization, code)| epsg_to_proj4(&organization, code))
+    } else {
+        epsg_to_proj4("EPSG", srs_id)
+    };
+
+    let mut query = format!("SELECT * FROM \"{}\"", table.replace("\"", "\"\""));
+    if let Some(filter) = option("where") {
+        query.push_str(" WHERE ");
+        query.push_str(filter);
+    }
+    let mut stmt = conn.prepare(&query).map_err(|err| err.to_string())?;
+    let column_names = stmt.column_names()
+        .into_iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<String>>();
+    let mut rows = stmt.query(&[]).map_err(|err| err.to_string())?;
+
+    let mut features = Vec::new();
+    while let Some(row) = rows.next() {
+        let row = row.map_err(|err| err.to_string())?;
+        let mut properties = Map::new();
+        let mut geometry = None;
+        for (ix, name) in column_names.iter().enumerate() {
+            let value = row.get_checked::<_, SqlValue>(ix as i32).map_err(|err| err.to_string())?;
+            if name == &geometry_column {
+                if let SqlValue::Blob(ref blob) = value {
+                    geometry = if gpkg {
+                        decode_gpkg_geometry(blob)?
+                    } else {
+                        Some(decode_spatialite_geometry(blob)?)
+                    };
+                }
+                continue;
+            }
+            let value = match value {
+                SqlValue::Null | SqlValue::Blob(_) => JsonValue::Null,
+                SqlValue::Integer(v) => JsonValue::Number(Number::from(v)),
+                SqlValue::Real(v) => Number::from_f64(v).map(JsonValue::Number).unwrap_or(JsonValue::Null),
+                SqlValue::Text(v) => JsonValue::String(v),
+            };
+            properties.insert(name.clone(), value);
+        }
+        features.push(Feature {
+                          geometry: geometry.map(Geometry::new),
+                          properties: Some(properties),
+                          bbox: None,
+                          id: None,
+                          foreign_members: None,
+                      });
+    }
+    Ok(LayerSource { crs: crs, ..LayerSource::from_features(features) })
+}
+
+fn table_exists(conn: &Connection, name: &str) -> bool {
+    conn.query_row("SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                   &[&name],
+                   |row| row.get::<_, i64>(0))
+        .map(|count| count > 0)
+        .unwrap_or(false)
+}
+
+fn epsg_to_proj4(organization: &str, code: i64) -> Option<String> {
+    if organization.to_uppercase() == "EPSG" && code > 0 && code != 4326 {
+        Some(format!("+init=epsg:{}", code))
+    } else {
+        None
+    }
+}
+
+/// Decodes a GeoPackage binary geometry (a header followed by a WKB geometry).
+fn decode_gpkg_geometry(blob: &[u8]) -> Result<Option<Value>, String> {
+    if blob.len() < 8 || &blob[..2] != b"GP" {
+        return Err("Invalid GeoPackage geometry!".to_string());
+    }
+    let flags = blob[3];
+    if flags & 0b0001_0000 != 0 {
+        // Empty geometry:
+        return Ok(None);
+    }
+    let envelope_size = match (flags >> 1) & 0b111 {
+        0 => 0,
+        1 => 32,
+        2 | 3 => 48,
+        4 => 64,
+        _ => return Err("Invalid GeoPackage geometry (wrong envelope type)!".to_string()),
+    };
+    let start = 8 + envelope_size;
+    if blob.len() <= start {
+        return Err("Invalid GeoPackage geometry!".to_string());
+    }
+    WkbReader::new(&blob[start..]).geometry().map(Some)
+}
+
+/// Decodes a SpatiaLite (uncompressed) blob geometry.
+fn decode_spatialite_geometry(blob: &[u8]) -> Result<Value, String> {
+    // Start marker, byte order, srid, mbr (4 doubles) and mbr end marker:
+    if blob.len() < 44 || blob[0] != 0x00 || blob[38] != 0x7C || blob[blob.len() - 1] != 0xFE {
+        return Err("Invalid SpatiaLite geometry!".to_string());
+    }
+    WkbReader::spatialite(&blob[39..blob.len() - 1], blob[1] == 0x01).body()
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use super::*;
+    use super::super::test_utils::{TempDir, features};
+
+    fn wkb_point(x: f64, y: f64) -> Vec<u8> {
+        let mut data = vec![1];
+        data.write_u32::<LittleEndian>(1).unwrap();
+        data.write_f64::<LittleEndian>(x).unwrap();
+        data.write_f64::<LittleEndian>(y).unwrap();
+        data
+    }
+
+    /// GeoPackage geometry of a point, with an XY envelope.
+    fn gpkg_point(x: f64, y: f64) -> Vec<u8> {
+        let mut data = vec![b'G', b'P', 0, 0b0000_0011];
+        data.write_i32::<LittleEndian>(4326).unwrap();
+        for &v in &[x, x, y, y] {
+            data.write_f64::<LittleEndian>(v).unwrap();
+        }
+        data.extend(wkb_point(x, y));
+        data
+    }
+
+    #[test]
+    fn decodes_geopackage_geometries() {
+        assert_eq!(decode_gpkg_geometry(&gpkg_point(1.0, 2.0)).unwrap(),
+                   Some(Value::Point(vec![1.0, 2.0])));
+        let empty = vec![b'G', b'P', 0, 0b0001_0001, 0, 0, 0, 0];
+        assert_eq!(decode_gpkg_geometry(&empty).unwrap(), None);
+        assert!(decode_gpkg_geometry(b"XX\0\x01\0\0\0\0").is_err());
+        assert!(decode_gpkg_geometry(&[b'G', b'P', 0, 0b0000_1011, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn decodes_spatialite_geometries() {
+        let mut blob = vec![0x00, 0x01];
+        blob.write_i32::<LittleEndian>(4326).unwrap();
+        for &v in &[1.0, 2.0, 1.0, 2.0] {
+            blob.write_f64::<LittleEndian>(v).unwrap();
+        }
+        blob.push(0x7C);
+        blob.write_u32::<LittleEndian>(1).unwrap();
+        blob.write_f64::<LittleEndian>(1.0).unwrap();
+        blob.write_f64::<LittleEndian>(2.0).unwrap();
+        blob.push(0xFE);
+        assert_eq!(decode_spatialite_geometry(&blob).unwrap(), Value::Point(vec![1.0, 2.0]));
+        blob.pop();
+        assert!(decode_spatialite_geometry(&blob).is_err());
+    }
+
+    #[test]
+    fn translates_the_coordinate_systems() {
+        assert_eq!(epsg_to_proj4("epsg", 2154), Some("+init=epsg:2154".to_string()));
+        assert_eq!(epsg_to_proj4("EPSG", 4326), None);
+        assert_eq!(epsg_to_proj4("NONE", -1), None);
+    }
+
+    #[test]
+    fn reads_a_geopackage_table() {
+        let dir = TempDir::new("gpkg-test");
+        let path = dir.path("cities.gpkg");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch("CREATE TABLE gpkg_spatial_ref_sys (srs_id INTEGER, organization TEXT, \
+                                                                   organization_coordsys_id INTEGER);
+                                INSERT INTO gpkg_spatial_ref_sys VALUES (2154, 'EPSG', 2154);
+                                CREATE TABLE gpkg_geometry_columns (table_name TEXT, column_name TEXT, \
+                                                                    srs_id INTEGER);
+                                INSERT INTO gpkg_geometry_columns VALUES ('cities', 'geom', 2154);
+                                CREATE TABLE cities (fid INTEGER, name TEXT, pop REAL, geom BLOB);")
+                .unwrap();
+            for &(fid, name, x, y) in &[(1, "Lyon", 842000.0, 6519000.0), (2, "Paris", 652000.0, 6862000.0)] {
+                conn.execute("INSERT INTO cities VALUES (?1, ?2, ?3, ?4)",
+                             &[&(fid as i64), &name, &1.5, &gpkg_point(x, y)])
+                    .unwrap();
+            }
+        }
+        let mut options = BTreeMap::new();
+        options.insert("table".to_string(), toml::Value::String("cities".to_string()));
+        options.insert("where".to_string(), toml::Value::String("name = 'Paris'".to_string()));
+        assert!(read(path.to_str().unwrap(), None).is_err());
+        let layer = read(path.to_str().unwrap(), Some(&options)).unwrap();
+        assert_eq!(layer.crs, Some("+init=epsg:2154".to_string()));
+        let features = features(layer);
+        assert_eq!(features.len(), 1);
+        let properties = features[0].properties.as_ref().unwrap();
+        assert_eq!(properties["name"], JsonValue::String("Paris".to_string()));
+        assert_eq!(properties["fid"], JsonValue::Number(Number::from(2)));
+        assert!(!properties.contains_key("geom"));
+        assert_eq!(features[0].geometry.as_ref().unwrap().value,
+                   Value::Point(vec![652000.0, 6862000.0]));
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::{BufReader, Bytes, Read};
+use geojson::{Feature, GeoJson};
+
+use super::archive::open_input;
+use super::geojsonseq::{SeqTexts, decode_text};
+
+/// Iterator over the features of a GeoJSON FeatureCollection, decoding
+/// only one feature at a time instead of the whole document.
+pub struct FeatureStream {
+    bytes: Bytes<BufReader<Box<dyn Read>>>,
+    started: bool,
+    finished: bool,
+}
+
+impl FeatureStream {
+    pub fn new(path: &str) -> Result<FeatureStream, String> {
+        Ok(FeatureStream {
+               bytes: BufReader::new(open_input(path)?).bytes(),
+               started: false,
+               finished: false,
+           })
+    }
+
+    fn next_byte(&mut self) -> Result<u8, String> {
+        match self.bytes.next() {
+            Some(b) => b.map_err(|err| err.to_string()),
+            None => Err("Unexpected end of GeoJSON document!".to_string()),
+        }
+    }
+
+    /// Moves to the beginning of the "features" array of the collection.
+    fn seek_features(&mut self) -> Result<(), String> {
+        let mut depth = 0;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut current = Vec::new();
+        let mut key: Option<Vec<u8>> = None;
+        loop {
+            let b = self.next_byte()?;
+            if in_string {
+                if escaped {
+                    escaped = false;
+                    current.push(b);
+                } else if b == b'\\' {
+                    escaped = true;
+                    current.push(b);
+                } else if b == b'"' {
+                    in_string = false;
+                    if depth == 1 {
+                        key = Some(current.clone());
+                    }
+                } else {
+                    current.push(b);
+                }
+                continue;
+            }
+            match b {
+                b'"' => {
+                    in_string = true;
+                    current.clear();
+                }
+                b':' if depth == 1 && key.as_ref().map(|k| k.as_slice()) == Some(b"features") => {
+                    loop {
+                        match self.next_byte()? {
+                            b'[' => return Ok(()),
+                            b' ' | b'\t' | b'\n' | b'\r' => continue,
+                            _ => return Err("\"features\" should be an array!".to_string()),
+                        }
+                    }
+                }
+                b':' | b' ' | b'\t' | b'\n' | b'\r' => {}
+                b'{' | b'[' => {
+                    depth += 1;
+                    key = None;
+                }
+                b'}' | b']' => {
+                    depth -= 1;
+                    key = None;
+                }
+                _ => key = None,
+            }
+        }
+    }
+
+    /// Reads the next feature of the array (`None` at the end of the array).
+    fn next_feature(&mut self) -> Result<Option<Feature>, String> {
+        loop {
+            match self.next_byte()? {
+                b'{' => break,
+                b']' => return Ok(None),
+                b',' | b' ' | b'\t' | b'\n' | b'\r' => continue,
+                _ => return Err("Invalid GeoJSON feature!".to_string()),
+            }
+        }
+        let mut buf = vec![b'{'];
+        let mut depth = 1;
+        let mut in_string = false;
+        let mut escaped = false;
+        while depth > 0 {
+            let b = self.next_byte()?;
+            buf.push(b);
+            if in_string {
+                if escaped {
+                    escaped = false;
+                } else if b == b'\\' {
+                    escaped = true;
+                } else if b == b'"' {
+                    in_string = false;
+                }
+            } else {
+                match b {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => depth -= 1,
+                    _ => {}
+                }
+            }
+        }
+        let text = String::from_utf8(buf).map_err(|err| err.to_string())?;
+        match text.parse::<GeoJson>() {
+            Ok(GeoJson::Feature(feature)) => Ok(Some(feature)),
+            Ok(_) => Err("Expected a GeoJSON Feature!".to_string()),
+            Err(err) => Err(format!("{:?}", err)),
+        }
+    }
+}
+
+impl Iterator for FeatureStream {
+    type Item = Result<Feature, String>;
+
+    fn next(&mut self) -> Option<Result<Feature, String>> {
+        if self.finished {
+            return None;
+        }
+        if !self.started {
+            self.started = true;
+            if let Err(err) = self.seek_features() {
+                self.finished = true;
+                return Some(Err(err));
+            }
+        }
+        match self.next_feature() {
+            Ok(Some(feature)) => Some(Ok(feature)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            }
+            Err(err) => {
+                self.finished = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Iterator over the features of a GeoJSON text sequence, decoding one text at a time.
+pub fn stream_seq(path: &str) -> Result<Box<dyn Iterator<Item = Result<Feature, String>>>, String> {
+    let texts = SeqTexts::new(path)?;
+    Ok(Box::new(texts.flat_map(|text| {
+        match text.and_then(|(location, text)| decode_text(&location, &text)) {
+            Ok(features) => features.into_iter().map(Ok).collect::<Vec<_>>(),
+            Err(err) => vec![Err(err)],
+        }
+    })))
+}
@@ -0,0 +1,337 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use geojson::{Feature, Geometry, Value};
+use serde_json;
+use serde_json::Value as JsonValue;
+use toml;
+
+use super::LayerSource;
+use super::archive::open_input;
+
+type Transform = ((f64, f64), (f64, f64));
+
+pub fn read(path: &str, options: Option<&BTreeMap<String, toml::Value>>) -> Result<LayerSource, String> {
+    let mut file = open_input(path)?;
+    let mut raw_json = String::new();
+    file.read_to_string(&mut raw_json).map_err(|err| err.to_string())?;
+    let topology: JsonValue = serde_json::from_str(&raw_json).map_err(|err| err.to_string())?;
+    if topology.get("type").and_then(|t| t.as_str()) != Some("Topology") {
+        return Err("Expected a TopoJSON Topology!".to_string());
+    }
+
+    let transform = match topology.get("transform") {
+        Some(t) => {
+            let scale = t.get("scale").and_then(|s| as_position(s));
+            let translate = t.get("translate").and_then(|s| as_position(s));
+            match (scale, translate) {
+                (Some(s), Some(t)) => Some(((s[0], s[1]), (t[0], t[1]))),
+                _ => return Err("Invalid TopoJSON transform!".to_string()),
+            }
+        }
+        None => None,
+    };
+    let arcs = decode_arcs(topology.get("arcs"), transform)?;
+
+    // The object to use can be chosen in the layer section; otherwise the one
+    // with the same name as the file (or the only one of the topology) is used:
+    let objects = topology
+        .get("objects")
+        .and_then(|o| o.as_object())
+        .ok_or_else(|| "The topology doesn't have any object!".to_string())?;
+    let layer_name = Path::new(super::strip_gz(path))
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("");
+    let object_name = match options.and_then(|o| o.get("object")) {
+        Some(&toml::Value::String(ref name)) => name.as_str(),
+        Some(_) => return Err("\"object\" should be the name of an object of the topology!".to_string()),
+        None if objects.contains_key(layer_name) => layer_name,
+        None if objects.len() == 1 => objects.keys().next().unwrap().as_str(),
+        None => {
+            return Err(format!("Several objects in the topology, use \"object\" to choose one of: {}",
+                               objects.keys().cloned().collect::<Vec<String>>().join(", ")))
+        }
+    };
+    let object = objects
+        .get(object_name)
+        .ok_or_else(|| format!("No object named \"{}\" in the topology!", object_name))?;
+
+    let mut arc_usage = vec![0u32; arcs.len()];
+    let mut features = Vec::new();
+    if object.get("type").and_then(|t| t.as_str()) == Some("GeometryCollection") {
+        for geom in object.get("geometries").and_then(|g| g.as_array()).unwrap_or(&Vec::new()) {
+            features.push(decode_feature(geom, &arcs, transform, &mut arc_usage)?);
+        }
+    } else {
+        features.push(decode_feature(object, &arcs, transform, &mut arc_usage)?);
+    }
+
+    // Arcs shared by several polygon rings are interior borders:
+    let shared_arcs = arcs.iter()
+        .zip(arc_usage.iter())
+        .filter(|&(_, &usage)| usage > 1)
+        .map(|(arc, _)| arc.clone())
+        .collect::<Vec<_>>();
+
+    let borders = Feature {
+        geometry: Some(Geometry::new(Value::MultiLineString(shared_arcs))),
+        properties: None,
+        bbox: None,
+        id: None,
+        foreign_members: None,
+    };
+    Ok(LayerSource {
+           borders: Some(LayerSource::from_features(vec![borders]).geojson),
+           ..LayerSource::from_features(features)
+       })
+}
+
+fn as_position(value: &JsonValue) -> Option<Vec<f64>> {
+    value.as_array().and_then(|coords| {
+        if coords.len() < 2 {
+            return None;
+        }
+        coords.iter().map(|c| c.as_f64()).collect::<Option<Vec<f64>>>()
+    })
+}
+
+fn apply_transform(position: &[f64], transform: Option<Transform>) -> Vec<f64> {
+    match transform {
+        Some(((sx, sy), (tx, ty))) => vec![position[0] * sx + tx, position[1] * sy + ty],
+        None => vec![position[0], position[1]],
+    }
+}
+
+/// Decodes the arcs of the topology (delta-encoded when the topology is quantized).
+fn decode_arcs(arcs: Option<&JsonValue>, transform: Option<Transform>) -> Result<Vec<Vec<Vec<f64>>>, String> {
+    let arcs = arcs.and_then(|a| a.as_array())
+        .ok_or_else(|| "The topology doesn't have any arc!".to_string())?;
+    let mut res = Vec::with_capacity(arcs.len());
+    for arc in arcs {
+        let positions = arc.as_array().ok_or_else(|| "Invalid arc!".to_string())?;
+        let mut decoded = Vec::with_capacity(positions.len());
+        let (mut x, mut y) = (0.0, 0.0);
+        for position in positions {
+            let p = as_position(position).ok_or_else(|| "Invalid arc position!".to_string())?;
+            if transform.is_some() {
+                x += p[0];
+                y += p[1];
+                decoded.push(apply_transform(&[x, y], transform));
+            } else {
+                decoded.push(vec![p[0], p[1]]);
+            }
+        }
+        res.push(decoded);
+    }
+    Ok(res)
+}
+
+/// Builds a line (or a ring) from a list of arc indexes, a negative index
+/// `~i` meaning the arc `i` in reverse order.
+fn stitch_arcs(indexes: &JsonValue,
+               arcs: &[Vec<Vec<f64>>],
+               arc_usage: Option<&mut Vec<u32>>)
+               -> Result<Vec<Vec<f64>>, String> {
+    let indexes = indexes.as_array().ok_or_else(|| "Invalid arc indexes!".to_string())?;
+    let mut line: Vec<Vec<f64>> = Vec::new();
+    let mut usage = arc_usage;
+    for index in indexes {
+        let index = index.as_i64().ok_or_else(|| "Invalid arc index!".to_string())?;
+        let (ix, reversed) = if index < 0 { (!index as usize, true) } else { (index as usize, false) };
+        let arc = arcs.get(ix).ok_or_else(|| format!("Unknown arc: {}", ix))?;
+        if let Some(ref mut u) = usage {
+            u[ix] += 1;
+        }
+        let points: Vec<Vec<f64>> = if reversed {
+            arc.iter().rev().cloned().collect()
+        } else {
+            arc.clone()
+        };
+        // The first point of an arc is the last point of the previous one:
+        let skip = if line.is_empty() { 0 } else { 1 };
+        line.extend(points.into_iter().skip(skip));
+    }
+    Ok(line)
+}
+
+fn decode_geometry(geom: &JsonValue,
+                   arcs: &[Vec<Vec<f64>>],
+                   transform: Option<Transform>,
+                   arc_usage: &mut Vec<u32>)
+                   -> Result<Option<Value>, String> {
+    let geom_type = geom.get("type").and_then(|t| t.as_str()).unwrap_or("");
+    let invalid = || format!("Invalid {} geometry!", geom_type);
+    let value = match geom_type {
+        "Point" => {
+            let p = geom.get("coordinates").and_then(as_position).ok_or_else(&invalid)?;
+            Value::Point(apply_transform(&p, transform))
+        }
+        "MultiPoint" => {
+            let points = geom.get("coordinates")
+                .and_then(|c| c.as_array())
+                .ok_or_else(&invalid)?;
+            let mut res = Vec::with_capacity(points.len());
+            for point in points {
+                let p = as_position(point).ok_or_else(&invalid)?;
+                res.push(apply_transform(&p, transform));
+            }
+            Value::MultiPoint(res)
+        }
+        "LineString" => {
+            Value::LineString(stitch_arcs(geom.get("arcs").ok_or_else(&invalid)?, arcs, None)?)
+        }
+        "MultiLineString" => {
+            let lines = geom.get("arcs").and_then(|a| a.as_array()).ok_or_else(&invalid)?;
+            let mut res = Vec::with_capacity(lines.len());
+            for line in lines {
+                res.push(stitch_arcs(line, arcs, None)?);
+            }
+            Value::MultiLineString(res)
+        }
+        "Polygon" => {
+            let rings = geom.get("arcs").and_then(|a| a.as_array()).ok_or_else(&invalid)?;
+            let mut res = Vec::with_capacity(rings.len());
+            for ring in rings {
+                res.push(stitch_arcs(ring, arcs, Some(&mut *arc_usage))?);
+            }
+            Value::Polygon(res)
+        }
+        "MultiPolygon" => {
+            let polygons = geom.get("arcs").and_then(|a| a.as_array()).ok_or_else(&invalid)?;
+            let mut res = Vec::with_capacity(polygons.len());
+            for polygon in polygons {
+                let rings = polygon.as_array().ok_or_else(&invalid)?;
+                let mut poly = Vec::with_capacity(rings.len());
+                for ring in rings {
+                    poly.push(stitch_arcs(ring, arcs, Some(&mut *arc_usage))?);
+                }
+                res.push(poly);
+            }
+            Value::MultiPolygon(res)
+        }
+        "GeometryCollection" => {
+            let geometries = geom.get("geometries").and_then(|g| g.as_array()).ok_or_else(&invalid)?;
+            let mut res = Vec::with_capacity(geometries.len());
+            for g in geometries {
+                if let Some(value) = decode_geometry(g, arcs, transform, arc_usage)? {
+                    res.push(Geometry::new(value));
+                }
+            }
+            Value::GeometryCollection(res)
+        }
+        "" | "null" => return Ok(None),
+        _ => return Err(format!("Unknown geometry type: {}", geom_type)),
+    };
+    Ok(Some(value))
+}
+
+fn decode_feature(geom: &JsonValue,
+                  arcs: &[Vec<Vec<f64>>],
+                  transform: Option<Transform>,
+                  arc_usage: &mut Vec<u32>)
+                  -> Result<Feature, String> {
+    Ok(Feature {
+           geometry: decode_geometry(geom, arcs, transform, arc_usage)?.map(Geometry::new),
+           properties: geom.get("properties").and_then(|p| p.as_object()).cloned(),
+           bbox: None,
+           id: geom.get("id").cloned(),
+           foreign_members: None,
+       })
+}
+
+#[cfg(test)]
+mod tests {
+    use geojson::GeoJson;
+    use super::*;
+    use super::super::test_utils::{TempDir, features};
+
+    fn json(raw: &str) -> JsonValue {
+        serde_json::from_str(raw).unwrap()
+    }
+
+    /// Two unit squares sharing the arc 0 (their common edge).
+    const TOPOLOGY: &'static str = r#"{
+        "type": "Topology",
+        "transform": {"scale": [0.5, 0.5], "translate": [10, 20]},
+        "objects": {
+            "squares": {
+                "type": "GeometryCollection",
+                "geometries": [
+                    {"type": "Polygon", "arcs": [[0, 1]], "id": "a", "properties": {"v": 1}},
+                    {"type": "Polygon", "arcs": [[2, -1]], "id": "b"},
+                    {"type": "Point", "coordinates": [4, 2]}
+                ]
+            },
+            "other": {"type": "Point", "coordinates": [0, 0]}
+        },
+        "arcs": [
+            [[2, 0], [0, 2]],
+            [[2, 2], [-2, 0], [0, -2], [2, 0]],
+            [[2, 0], [2, 0], [0, 2], [-2, 0]]
+        ]
+    }"#;
+
+    #[test]
+    fn decodes_quantized_arcs() {
+        let transform = Some(((0.5, 0.5), (10.0, 20.0)));
+        let arcs = decode_arcs(Some(&json("[[[2, 0], [0, 2], [-1, -1]]]")), transform).unwrap();
+        assert_eq!(arcs, vec![vec![vec![11.0, 20.0], vec![11.0, 21.0], vec![10.5, 20.5]]]);
+        let arcs = decode_arcs(Some(&json("[[[2, 0], [0, 2]]]")), None).unwrap();
+        assert_eq!(arcs, vec![vec![vec![2.0, 0.0], vec![0.0, 2.0]]]);
+        assert!(decode_arcs(Some(&json("[[[2]]]")), None).is_err());
+        assert!(decode_arcs(None, None).is_err());
+    }
+
+    #[test]
+    fn stitches_reversed_arcs() {
+        let arcs = vec![vec![vec![0.0, 0.0], vec![1.0, 0.0]],
+                        vec![vec![1.0, 1.0], vec![1.0, 0.0]]];
+        let mut usage = vec![0, 0];
+        let line = stitch_arcs(&json("[0, -2]"), &arcs, Some(&mut usage)).unwrap();
+        assert_eq!(line, vec![vec![0.0, 0.0], vec![1.0, 0.0], vec![1.0, 1.0]]);
+        assert_eq!(usage, vec![1, 1]);
+        assert!(stitch_arcs(&json("[2]"), &arcs, None).is_err());
+    }
+
+    #[test]
+    fn reads_the_object_named_like_the_file() {
+        let dir = TempDir::new("topojson-test");
+        let path = dir.write("squares.topojson", TOPOLOGY.as_bytes());
+        let mut layer = read(path.to_str().unwrap(), None).unwrap();
+        let borders = match layer.borders.take() {
+            Some(GeoJson::FeatureCollection(collection)) => collection.features,
+            _ => panic!("Expected the borders of the layer"),
+        };
+
+        let features = features(layer);
+        assert_eq!(features.len(), 3);
+        assert_eq!(features[0].id, Some(json("\"a\"")));
+        assert_eq!(features[0].properties.as_ref().unwrap()["v"], json("1"));
+        assert_eq!(features[0].geometry.as_ref().unwrap().value,
+                   Value::Polygon(vec![vec![vec![11.0, 20.0],
+                                            vec![11.0, 21.0],
+                                            vec![10.0, 21.0],
+                                            vec![10.0, 20.0],
+                                            vec![11.0, 20.0]]]));
+        assert_eq!(features[2].geometry.as_ref().unwrap().value,
+                   Value::Point(vec![12.0, 21.0]));
+
+        // Only the common edge of the two squares is an interior border:
+        assert_eq!(borders[0].geometry.as_ref().unwrap().value,
+                   Value::MultiLineString(vec![vec![vec![11.0, 20.0], vec![11.0, 21.0]]]));
+    }
+
+    #[test]
+    fn chooses_the_object_from_the_options() {
+        let dir = TempDir::new("topojson-options-test");
+        let path = dir.write("world.topojson", TOPOLOGY.as_bytes());
+        assert!(read(path.to_str().unwrap(), None).is_err());
+
+        let mut options = BTreeMap::new();
+        options.insert("object".to_string(), toml::Value::String("other".to_string()));
+        let features = features(read(path.to_str().unwrap(), Some(&options)).unwrap());
+        assert_eq!(features[0].geometry.as_ref().unwrap().value,
+                   Value::Point(vec![10.0, 20.0]));
+    }
+}
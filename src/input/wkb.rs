@@ -0,0 +1,263 @@
+use std::io::Cursor;
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use geojson::{Geometry, Value};
+
+/// Reader for Well-Known Binary geometries (ISO and extended WKB) and for the
+/// body of SpatiaLite geometries, which use the same layout with different
+/// markers for the members of collections.
+pub struct WkbReader<'a> {
+    cursor: Cursor<&'a [u8]>,
+    little_endian: bool,
+    spatialite: bool,
+}
+
+impl<'a> WkbReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        WkbReader {
+            cursor: Cursor::new(data),
+            little_endian: true,
+            spatialite: false,
+        }
+    }
+
+    /// Reader for the body of a SpatiaLite blob, starting at the class type.
+    pub fn spatialite(data: &'a [u8], little_endian: bool) -> Self {
+        WkbReader {
+            cursor: Cursor::new(data),
+            little_endian: little_endian,
+            spatialite: true,
+        }
+    }
+
+    /// Capacity to reserve for `count` items of at least `item_size` bytes, bounded by
+    /// the number of bytes left (as the counts of a corrupt geometry can be huge).
+    fn capacity(&self, count: u32, item_size: usize) -> usize {
+        let remaining = (self.cursor.get_ref().len() as u64).saturating_sub(self.cursor.position());
+        (count as u64).min(remaining / item_size as u64) as usize
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        self.cursor.read_u8().map_err(|err| err.to_string())
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        if self.little_endian {
+            self.cursor.read_u32::<LittleEndian>().map_err(|err| err.to_string())
+        } else {
+            self.cursor.read_u32::<BigEndian>().map_err(|err| err.to_string())
+        }
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        if self.little_endian {
+            self.cursor.read_f64::<LittleEndian>().map_err(|err| err.to_string())
+        } else {
+            self.cursor.read_f64::<BigEndian>().map_err(|err| err.to_string())
+        }
+    }
+
+    /// Reads a geometry type, returning the base type (1 to 7) and the
+    /// number of coordinates of each position.
+    fn geometry_type(&mut self) -> Result<(u32, usize), String> {
+        let raw_type = self.u32()?;
+        // Extended WKB flags:
+        let (ewkb_z, ewkb_m) = (raw_type & 0x8000_0000 != 0, raw_type & 0x4000_0000 != 0);
+        if raw_type & 0x2000_0000 != 0 {
+            // Skip the SRID:
+            self.u32()?;
+        }
+        let iso_type = raw_type & 0x0FFF_FFFF;
+        if iso_type > 1_000_000 {
+            return Err("Compressed SpatiaLite geometries are not supported!".to_string());
+        }
+        let (base_type, has_z, has_m) = (iso_type % 1000,
+                                         ewkb_z || iso_type / 1000 == 1 || iso_type / 1000 == 3,
+                                         ewkb_m || iso_type / 1000 == 2 || iso_type / 1000 == 3);
+        let dims = 2 + has_z as usize + has_m as usize;
+        Ok((base_type, dims))
+    }
+
+    fn position(&mut self, dims: usize) -> Result<Vec<f64>, String> {
+        let x = self.f64()?;
+        let y = self.f64()?;
+        for _ in 2..dims {
+            self.f64()?;
+        }
+        Ok(vec![x, y])
+    }
+
+    fn line(&mut self, dims: usize) -> Result<Vec<Vec<f64>>, String> {
+        let nb_points = self.u32()?;
+        let mut res = Vec::with_capacity(self.capacity(nb_points, 8 * dims));
+        for _ in 0..nb_points {
+            res.push(self.position(dims)?);
+        }
+        Ok(res)
+    }
+
+    fn polygon(&mut self, dims: usize) -> Result<Vec<Vec<Vec<f64>>>, String> {
+        let nb_rings = self.u32()?;
+        let mut res = Vec::with_capacity(self.capacity(nb_rings, 4));
+        for _ in 0..nb_rings {
+            res.push(self.line(dims)?);
+        }
+        Ok(res)
+    }
+
+    /// Reads the header of a member of a collection (a byte order in WKB,
+    /// an entity marker in SpatiaLite) followed by its geometry.
+    fn member(&mut self) -> Result<Value, String> {
+        let marker = self.u8()?;
+        if self.spatialite {
+            if marker != 0x69 {
+                return Err("Invalid SpatiaLite geometry (wrong entity marker)!".to_string());
+            }
+        } else {
+            self.little_endian = marker == 1;
+        }
+        self.body()
+    }
+
+    /// Reads a full WKB geometry (byte order, type and coordinates).
+    pub fn geometry(&mut self) -> Result<Value, String> {
+        let byte_order = self.u8()?;
+        self.little_endian = byte_order == 1;
+        self.body()
+    }
+
+    /// Reads a geometry type followed by the corresponding coordinates.
+    pub fn body(&mut self) -> Result<Value, String> {
+        let (base_type, dims) = self.geometry_type()?;
+        match base_type {
+            1 => Ok(Value::Point(self.position(dims)?)),
+            2 => Ok(Value::LineString(self.line(dims)?)),
+            3 => Ok(Value::Polygon(self.polygon(dims)?)),
+            4 | 5 | 6 | 7 => {
+                let nb_members = self.u32()?;
+                // Each member has at least a header (a byte) and a type:
+                let mut members = Vec::with_capacity(self.capacity(nb_members, 5));
+                for _ in 0..nb_members {
+                    members.push(self.member()?);
+                }
+                Ok(match base_type {
+                       4 => {
+                           Value::MultiPoint(members
+                                                 .into_iter()
+                                                 .filter_map(|m| match m {
+                                                                 Value::Point(p) => Some(p),
+                                                                 _ => None,
+                                                             })
+                                                 .collect())
+                       }
+                       5 => {
+                           Value::MultiLineString(members
+                                                      .into_iter()
+                                                      .filter_map(|m| match m {
+                                                                      Value::LineString(l) => Some(l),
+                                                                      _ => None,
+                                                                  })
+                                                      .collect())
+                       }
+                       6 => {
+                           Value::MultiPolygon(members
+                                                   .into_iter()
+                                                   .filter_map(|m| match m {
+                                                                   Value::Polygon(p) => Some(p),
+                                                                   _ => None,
+                                                               })
+                                                   .collect())
+                       }
+                       _ => Value::GeometryCollection(members.into_iter().map(Geometry::new).collect()),
+                   })
+            }
+            _ => Err(format!("Unsupported WKB geometry type: {}", base_type)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::WriteBytesExt;
+    use super::*;
+
+    /// WKB of a geometry (little endian), given its type and the rest of its content.
+    fn wkb(geom_type: u32, content: &[u8]) -> Vec<u8> {
+        let mut data = vec![1];
+        data.write_u32::<LittleEndian>(geom_type).unwrap();
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn doubles(values: &[f64]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &v in values {
+            data.write_f64::<LittleEndian>(v).unwrap();
+        }
+        data
+    }
+
+    #[test]
+    fn reads_points_in_both_byte_orders() {
+        let point = wkb(1, &doubles(&[1.5, -2.0]));
+        assert_eq!(WkbReader::new(&point).geometry().unwrap(), Value::Point(vec![1.5, -2.0]));
+
+        let mut point = vec![0];
+        point.write_u32::<BigEndian>(1).unwrap();
+        point.write_f64::<BigEndian>(3.0).unwrap();
+        point.write_f64::<BigEndian>(4.0).unwrap();
+        assert_eq!(WkbReader::new(&point).geometry().unwrap(), Value::Point(vec![3.0, 4.0]));
+    }
+
+    #[test]
+    fn skips_the_extra_dimensions_and_the_srid() {
+        // ISO WKB PointZM:
+        let point = wkb(3001, &doubles(&[1.0, 2.0, 3.0, 4.0]));
+        assert_eq!(WkbReader::new(&point).geometry().unwrap(), Value::Point(vec![1.0, 2.0]));
+        // Extended WKB LineStringZ with a SRID:
+        let mut content = Vec::new();
+        content.write_u32::<LittleEndian>(4326).unwrap();
+        content.write_u32::<LittleEndian>(2).unwrap();
+        content.extend(doubles(&[0.0, 0.0, 10.0, 1.0, 1.0, 20.0]));
+        let line = wkb(0xA000_0002, &content);
+        assert_eq!(WkbReader::new(&line).geometry().unwrap(),
+                   Value::LineString(vec![vec![0.0, 0.0], vec![1.0, 1.0]]));
+    }
+
+    #[test]
+    fn reads_collections() {
+        let mut content = Vec::new();
+        content.write_u32::<LittleEndian>(2).unwrap();
+        for &(x, y) in &[(0.0, 0.0), (5.0, 5.0)] {
+            let mut ring = Vec::new();
+            ring.write_u32::<LittleEndian>(1).unwrap();
+            ring.write_u32::<LittleEndian>(4).unwrap();
+            ring.extend(doubles(&[x, y, x + 1.0, y, x, y + 1.0, x, y]));
+            content.extend(wkb(3, &ring));
+        }
+        match WkbReader::new(&wkb(6, &content)).geometry().unwrap() {
+            Value::MultiPolygon(polygons) => {
+                assert_eq!(polygons.len(), 2);
+                assert_eq!(polygons[1][0][1], vec![6.0, 5.0]);
+            }
+            other => panic!("Unexpected geometry: {:?}", other),
+        }
+
+        let mut content = Vec::new();
+        content.write_u32::<LittleEndian>(1).unwrap();
+        content.extend(wkb(1, &doubles(&[1.0, 2.0])));
+        assert_eq!(WkbReader::new(&wkb(7, &content)).geometry().unwrap(),
+                   Value::GeometryCollection(vec![Geometry::new(Value::Point(vec![1.0, 2.0]))]));
+    }
+
+    #[test]
+    fn rejects_invalid_geometries() {
+        assert!(WkbReader::new(&wkb(17, &[])).geometry().is_err());
+        assert!(WkbReader::new(&wkb(1, &doubles(&[1.0]))).geometry().is_err());
+        assert!(WkbReader::new(&[]).geometry().is_err());
+        // A huge number of points, without the points:
+        let mut content = Vec::new();
+        content.write_u32::<LittleEndian>(u32::max_value()).unwrap();
+        content.extend(doubles(&[1.0, 2.0]));
+        assert!(WkbReader::new(&wkb(2, &content)).geometry().is_err());
+    }
+}
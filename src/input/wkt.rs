@@ -0,0 +1,221 @@
+use geojson::{Geometry, Value};
+
+/// Parses a geometry in Well-Known Text (an optional EWKT `SRID=...;` prefix is ignored),
+/// `None` meaning an empty geometry (like `POINT EMPTY`).
+pub fn parse_wkt(text: &str) -> Result<Option<Value>, String> {
+    let text = match text.find(';') {
+        Some(ix) if text[..ix].trim().to_uppercase().starts_with("SRID") => &text[ix + 1..],
+        _ => text,
+    };
+    let mut parser = WktParser {
+        tokens: tokenize(text),
+        position: 0,
+    };
+    let value = parser.geometry()?;
+    if parser.position != parser.tokens.len() {
+        return Err(format!("Unexpected content at the end of WKT geometry: {}", text));
+    }
+    Ok(value)
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for c in text.chars() {
+        if c == '(' || c == ')' || c == ',' || c.is_whitespace() {
+            if !current.is_empty() {
+                tokens.push(current.clone());
+                current.clear();
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+struct WktParser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl WktParser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| "Unexpected end of WKT geometry!".to_string())?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(format!("Expected \"{}\" in WKT geometry, found \"{}\"!", expected, token))
+        }
+    }
+
+    /// Returns `true` (and consumes the token) if the next token is `EMPTY`.
+    fn empty(&mut self) -> bool {
+        if self.peek().map(|t| t.to_uppercase()) == Some("EMPTY".to_string()) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parses a list of items between parentheses, separated by commas.
+    fn list<T, F>(&mut self, mut item: F) -> Result<Vec<T>, String>
+        where F: FnMut(&mut WktParser) -> Result<T, String>
+    {
+        if self.empty() {
+            return Ok(Vec::new());
+        }
+        self.expect("(")?;
+        let mut res = vec![item(self)?];
+        while self.peek() == Some(",") {
+            self.position += 1;
+            res.push(item(self)?);
+        }
+        self.expect(")")?;
+        Ok(res)
+    }
+
+    fn position(&mut self) -> Result<Vec<f64>, String> {
+        let mut coords = Vec::new();
+        while let Some(token) = self.peek().map(|t| t.to_string()) {
+            if token == "," || token == ")" {
+                break;
+            }
+            coords.push(token.parse::<f64>()
+                            .map_err(|_| format!("Invalid coordinate in WKT geometry: {}", token))?);
+            self.position += 1;
+        }
+        if coords.len() < 2 {
+            return Err("Expected at least 2 coordinates in WKT geometry!".to_string());
+        }
+        // Only the x and y coordinates are used:
+        coords.truncate(2);
+        Ok(coords)
+    }
+
+    fn line(&mut self) -> Result<Vec<Vec<f64>>, String> {
+        self.list(|p| p.position())
+    }
+
+    fn polygon(&mut self) -> Result<Vec<Vec<Vec<f64>>>, String> {
+        self.list(|p| p.line())
+    }
+
+    /// Parses a geometry, `None` meaning an empty geometry.
+    fn geometry(&mut self) -> Result<Option<Value>, String> {
+        let geom_type = self.next()?.to_uppercase();
+        // Skip the dimension qualifier:
+        if let Some(dim) = self.peek().map(|t| t.to_uppercase()) {
+            if dim == "Z" || dim == "M" || dim == "ZM" {
+                self.position += 1;
+            }
+        }
+        if self.empty() {
+            return match geom_type.as_str() {
+                "POINT" | "LINESTRING" | "POLYGON" | "MULTIPOINT" | "MULTILINESTRING" |
+                "MULTIPOLYGON" | "GEOMETRYCOLLECTION" => Ok(None),
+                _ => Err(format!("Unknown WKT geometry type: {}", geom_type)),
+            };
+        }
+        let value = match geom_type.as_str() {
+            "POINT" => {
+                self.expect("(")?;
+                let point = self.position()?;
+                self.expect(")")?;
+                Value::Point(point)
+            }
+            "LINESTRING" => Value::LineString(self.line()?),
+            "POLYGON" => Value::Polygon(self.polygon()?),
+            "MULTIPOINT" => {
+                // Both "MULTIPOINT (1 2, 3 4)" and "MULTIPOINT ((1 2), (3 4))" are valid:
+                Value::MultiPoint(self.list(|p| if p.peek() == Some("(") {
+                                                p.position += 1;
+                                                let point = p.position()?;
+                                                p.expect(")")?;
+                                                Ok(point)
+                                            } else {
+                                                p.position()
+                                            })?)
+            }
+            "MULTILINESTRING" => Value::MultiLineString(self.list(|p| p.line())?),
+            "MULTIPOLYGON" => Value::MultiPolygon(self.list(|p| p.polygon())?),
+            "GEOMETRYCOLLECTION" => {
+                // The empty members of the collection are dropped:
+                let geometries = self.list(|p| p.geometry())?;
+                Value::GeometryCollection(geometries.into_iter()
+                                              .filter_map(|g| g.map(Geometry::new))
+                                              .collect())
+            }
+            _ => return Err(format!("Unknown WKT geometry type: {}", geom_type)),
+        };
+        Ok(Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_simple_geometries() {
+        assert_eq!(parse_wkt("POINT (1 2)").unwrap(), Some(Value::Point(vec![1.0, 2.0])));
+        assert_eq!(parse_wkt("SRID=4326;point z (1.5 -2 10)").unwrap(),
+                   Some(Value::Point(vec![1.5, -2.0])));
+        assert_eq!(parse_wkt("LINESTRING (0 0, 1 1, 2 0)").unwrap(),
+                   Some(Value::LineString(vec![vec![0.0, 0.0], vec![1.0, 1.0], vec![2.0, 0.0]])));
+        assert_eq!(parse_wkt("POLYGON ((0 0, 4 0, 4 4, 0 0), (1 1, 2 1, 2 2, 1 1))").unwrap(),
+                   Some(Value::Polygon(vec![vec![vec![0.0, 0.0],
+                                                 vec![4.0, 0.0],
+                                                 vec![4.0, 4.0],
+                                                 vec![0.0, 0.0]],
+                                            vec![vec![1.0, 1.0],
+                                                 vec![2.0, 1.0],
+                                                 vec![2.0, 2.0],
+                                                 vec![1.0, 1.0]]])));
+    }
+
+    #[test]
+    fn parses_both_multipoint_syntaxes() {
+        let expected = Some(Value::MultiPoint(vec![vec![1.0, 2.0], vec![3.0, 4.0]]));
+        assert_eq!(parse_wkt("MULTIPOINT (1 2, 3 4)").unwrap(), expected);
+        assert_eq!(parse_wkt("MULTIPOINT ((1 2), (3 4))").unwrap(), expected);
+    }
+
+    #[test]
+    fn parses_empty_geometries_as_no_geometry() {
+        assert_eq!(parse_wkt("POINT EMPTY").unwrap(), None);
+        assert_eq!(parse_wkt("MULTIPOLYGON Z EMPTY").unwrap(), None);
+        assert_eq!(parse_wkt("GEOMETRYCOLLECTION (POINT EMPTY, POINT (1 2))").unwrap(),
+                   Some(Value::GeometryCollection(vec![Geometry::new(Value::Point(vec![1.0, 2.0]))])));
+    }
+
+    #[test]
+    fn rejects_invalid_geometries() {
+        assert!(parse_wkt("CIRCLE (1 2)").is_err());
+        assert!(parse_wkt("CIRCLE EMPTY").is_err());
+        assert!(parse_wkt("POINT (1)").is_err());
+        assert!(parse_wkt("POINT (1 a)").is_err());
+        assert!(parse_wkt("LINESTRING (0 0, 1 1").is_err());
+        assert!(parse_wkt("POINT (1 2) 3").is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use std::io::Read;
+use xml::reader::{EventReader, XmlEvent};
+
+/// A (very) simple XML element tree, using the local names of elements and attributes.
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    pub text: String,
+}
+
+impl Element {
+    pub fn parse<R: Read>(reader: R) -> Result<Element, String> {
+        let mut stack: Vec<Element> = Vec::new();
+        for event in EventReader::new(reader) {
+            match event.map_err(|err| err.to_string())? {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    stack.push(Element {
+                                   name: name.local_name,
+                                   attributes: attributes
+                                       .into_iter()
+                                       .map(|a| (a.name.local_name, a.value))
+                                       .collect(),
+                                   children: Vec::new(),
+                                   text: String::new(),
+                               });
+                }
+                XmlEvent::EndElement { .. } => {
+                    let element = stack.pop().unwrap();
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                XmlEvent::Characters(text) |
+                XmlEvent::CData(text) => {
+                    if let Some(current) = stack.last_mut() {
+                        current.text.push_str(&text);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Err("Unexpected end of XML document!".to_string())
+    }
+
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|a| a.0 == name)
+            .map(|a| a.1.as_str())
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    pub fn children_named(&self, name: &str) -> Vec<&Element> {
+        self.children.iter().filter(|c| c.name == name).collect()
+    }
+
+    /// Trimmed text of the given child, if any.
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|c| c.text.trim())
+    }
+}
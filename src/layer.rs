@@ -1,6 +1,6 @@
-use std::f64;
 use geojson::{Feature, FeatureCollection, GeoJson, Geometry, Value};
 use proj::Proj;
+use serde_json::Value as JsonValue;
 
 use config_params::MapExtent;
 
@@ -8,204 +8,249 @@ pub fn get_nb_class(nb_features: usize) -> u32 {
     (1.0 + 3.3 * (nb_features as f64).log(10.0)).floor() as u32
 }
 
-pub fn get_values(features: &[Feature], field_name: &String) -> Vec<f64> {
-    let mut res = Vec::new();
-    for feature in features {
-        if let Some(ref prop) = feature.properties {
-            res.push(prop[field_name].as_f64().unwrap());
-        } else {
-            panic!("Unable to find field {}!", field_name);
-        }
-    }
-    res
+/// Reads the value of a field for each feature, `None` meaning that the feature
+/// has no valid numeric value (missing field, null, non-numeric string, ...).
+pub fn get_values(features: &[Feature], field_name: &String) -> Vec<Option<f64>> {
+    features.iter()
+        .map(|feature| {
+            let value = match feature.properties.as_ref().and_then(|p| p.get(field_name)) {
+                Some(&JsonValue::Number(ref n)) => n.as_f64(),
+                Some(&JsonValue::String(ref s)) => s.trim().parse::<f64>().ok(),
+                _ => None,
+            };
+            value.and_then(|v| if v.is_finite() { Some(v) } else { None })
+        })
+        .collect()
 }
 
-pub fn get_extent(geojson: &GeoJson) -> MapExtent {
-    fn verif(point: &Vec<f64>, extent: &mut MapExtent) {
-        if point[0] > extent.right {
-            extent.right = point[0];
-        } else if point[0] < extent.left {
-            extent.left = point[0];
+/// Grows `extent` so that it contains the given geometry.
+pub fn extend_extent(value: &Value, extent: &mut MapExtent) {
+    match *value {
+        Value::Point(ref point) => {
+            extent.expand(point[0], point[1]);
+        }
+        Value::MultiPoint(ref points) |
+        Value::LineString(ref points) => {
+            for point in points {
+                extent.expand(point[0], point[1]);
+            }
+        }
+        Value::MultiLineString(ref rings) |
+        Value::Polygon(ref rings) => {
+            for ring in rings {
+                for point in ring {
+                    extent.expand(point[0], point[1]);
+                }
+            }
+        }
+        Value::MultiPolygon(ref polygons) => {
+            for polygon in polygons {
+                for ring in polygon {
+                    for point in ring {
+                        extent.expand(point[0], point[1]);
+                    }
+                }
+            }
         }
-        if point[1] > extent.top {
-            extent.top = point[1];
-        } else if point[1] < extent.bottom {
-            extent.bottom = point[1];
+        Value::GeometryCollection(ref geometries) => {
+            for geom in geometries {
+                extend_extent(&geom.value, extent);
+            }
         }
     }
+}
+
+/// Computes the bounding box of all the features of a layer (without any padding).
+pub fn get_extent(geojson: &GeoJson) -> MapExtent {
     let features = match geojson {
         &GeoJson::FeatureCollection(ref collection) => &collection.features,
         _ => panic!("Error: expected a Feature collection of polygons!"),
     };
-    let mut extent = MapExtent {
-        left: f64::MAX,
-        right: f64::MIN,
-        bottom: f64::MAX,
-        top: f64::MIN,
-    };
+    let mut extent = MapExtent::empty();
     for feature in features {
         if let Some(ref geom) = feature.geometry {
-            match geom.value {
-                Value::Point(ref point) => {
-                    verif(point, &mut extent);
-                }
-                Value::MultiPoint(ref points) |
-                Value::LineString(ref points) => {
-                    for point in points {
-                        verif(point, &mut extent);
-                    }
-                }
-                Value::MultiLineString(ref rings) |
-                Value::Polygon(ref rings) => {
-                    for ring in rings {
-                        for point in ring {
-                            verif(point, &mut extent);
-                        }
-                    }
-                }
-                Value::MultiPolygon(ref polygons) => {
-                    for polygon in polygons {
-                        for ring in polygon {
-                            for point in ring {
-                                verif(point, &mut extent);
-                            }
-                        }
-                    }
-                }
-                _ => panic!("GeometryCollection not handled yet!"),
-            }
+            extend_extent(&geom.value, &mut extent);
         }
     }
-    let a = (extent.right - extent.left) / 10.0;
-    let b = (extent.top - extent.bottom) / 10.0;
-    extent.right += a;
-    extent.left -= a;
-    extent.top += b;
-    extent.bottom -= b;
     extent
 }
 
-pub fn reproj(decoded_geojson: &mut GeoJson, input_proj: &Proj, output_proj: &Proj) -> GeoJson {
-    let features = match decoded_geojson {
-        &mut GeoJson::FeatureCollection(ref mut collection) => &collection.features,
-        _ => panic!("Error: expected a Feature collection of polygons!"),
+/// Points along the edges of an extent, with `nb_points` intermediate points on each edge.
+fn densified_edges(extent: &MapExtent, nb_points: u32) -> Vec<(f64, f64)> {
+    let mut points = Vec::with_capacity(4 * (nb_points as usize + 1));
+    for i in 0..nb_points + 1 {
+        let t = i as f64 / nb_points as f64;
+        let x = extent.left + t * extent.width();
+        let y = extent.bottom + t * extent.height();
+        points.push((x, extent.bottom));
+        points.push((x, extent.top));
+        points.push((extent.left, y));
+        points.push((extent.right, y));
+    }
+    points
+}
+
+/// Projects an extent expressed in longitude / latitude. Each edge is densified
+/// with `nb_points` intermediate points, so curved parallels and meridians
+/// are still enclosed by the resulting extent.
+pub fn project_extent(extent: &MapExtent,
+                      input_proj: &Proj,
+                      output_proj: &Proj,
+                      nb_points: u32)
+                      -> MapExtent {
+    let mut res = MapExtent::empty();
+    for (lon, lat) in densified_edges(extent, nb_points) {
+        if let Ok(p) = input_proj.project(&output_proj, (lon.to_radians(), lat.to_radians())) {
+            res.expand(p.0, p.1);
+        }
+    }
+    res
+}
+
+/// Converts a projected extent back to longitude / latitude (the inverse of `project_extent`).
+pub fn unproject_extent(extent: &MapExtent,
+                        output_proj: &Proj,
+                        input_proj: &Proj,
+                        nb_points: u32)
+                        -> MapExtent {
+    let mut res = MapExtent::empty();
+    for (x, y) in densified_edges(extent, nb_points) {
+        if let Ok(p) = output_proj.project(&input_proj, (x, y)) {
+            res.expand(p.0.to_degrees(), p.1.to_degrees());
+        }
+    }
+    res
+}
+
+fn reproj_value<F>(value: Value, project: &F) -> Value
+    where F: Fn(&[f64]) -> Vec<f64>
+{
+    match value {
+        Value::Point(point) => Value::Point(project(&point)),
+        Value::MultiPoint(points) => Value::MultiPoint(points.iter().map(|p| project(p)).collect()),
+        Value::LineString(positions) => {
+            Value::LineString(positions.iter().map(|p| project(p)).collect())
+        }
+        Value::MultiLineString(lines) => {
+            Value::MultiLineString(lines
+                                       .iter()
+                                       .map(|line| line.iter().map(|p| project(p)).collect())
+                                       .collect())
+        }
+        Value::Polygon(rings) => {
+            Value::Polygon(rings
+                               .iter()
+                               .map(|ring| ring.iter().map(|p| project(p)).collect())
+                               .collect())
+        }
+        Value::MultiPolygon(polygons) => {
+            Value::MultiPolygon(polygons
+                                    .iter()
+                                    .map(|poly| {
+                                             poly.iter()
+                                                 .map(|ring| ring.iter().map(|p| project(p)).collect())
+                                                 .collect()
+                                         })
+                                    .collect())
+        }
+        Value::GeometryCollection(geometries) => {
+            Value::GeometryCollection(geometries
+                                          .into_iter()
+                                          .map(|g| Geometry::new(reproj_value(g.value, project)))
+                                          .collect())
+        }
+    }
+}
+
+/// Converts a geometry from a projected coordinate system to longitude / latitude
+/// (in degrees), `lonlat` being a geographic coordinate system.
+pub fn unproject_value(value: Value, input_proj: &Proj, lonlat: &Proj) -> Value {
+    let unproject = |point: &[f64]| {
+        let p = input_proj.project(&lonlat, (point[0], point[1])).unwrap();
+        vec![p.0.to_degrees(), p.1.to_degrees()]
     };
-    let mut res = Vec::new();
-    for feature in features {
-        let geom = feature.to_owned().geometry.unwrap();
-        match geom.value {
-            Value::Point(ref point) => {
-                let p = input_proj
-                    .project(&output_proj, (point[0].to_radians(), point[1].to_radians()))
-                    .unwrap();
-                res.push(Feature {
-                             geometry: Some(Geometry::new(Value::Point(vec![p.0, p.1]))),
-                             properties: feature.properties.to_owned(),
-                             bbox: None,
-                             id: feature.id.to_owned(),
-                             foreign_members: None,
-                         });
-            }
-            Value::MultiPoint(points) => {
-                let mut pts = Vec::new();
-                for point in points {
-                    let p = input_proj
-                        .project(&output_proj, (point[0].to_radians(), point[1].to_radians()))
-                        .unwrap();
-                    pts.push(vec![p.0, p.1]);
-                }
-                res.push(Feature {
-                             geometry: Some(Geometry::new(Value::MultiPoint(pts))),
-                             properties: feature.properties.to_owned(),
-                             bbox: None,
-                             id: feature.id.to_owned(),
-                             foreign_members: None,
-                         });
-            }
-            Value::LineString(positions) => {
-                let mut pos = Vec::new();
-                for point in positions {
-                    let p = input_proj
-                        .project(&output_proj, (point[0].to_radians(), point[1].to_radians()))
-                        .unwrap();
-                    pos.push(vec![p.0, p.1]);
-                }
-                res.push(Feature {
-                             geometry: Some(Geometry::new(Value::LineString(pos))),
-                             properties: feature.properties.to_owned(),
-                             bbox: None,
-                             id: feature.id.to_owned(),
-                             foreign_members: None,
-                         });
-            }
-            Value::MultiLineString(lines) => {
-                let mut pos = Vec::new();
-                for line in lines {
-                    let mut v = Vec::new();
-                    for point in line {
-                        let p = input_proj
-                            .project(&output_proj, (point[0].to_radians(), point[1].to_radians()))
-                            .unwrap();
-                        v.push(vec![p.0, p.1]);
-                    }
-                    pos.push(v);
-                }
-                res.push(Feature {
-                             geometry: Some(Geometry::new(Value::MultiLineString(pos))),
-                             properties: feature.properties.to_owned(),
-                             bbox: None,
-                             id: feature.id.to_owned(),
-                             foreign_members: None,
-                         });
-            }
-            Value::Polygon(poly) => {
-                let mut pos = Vec::new();
-                for ring in poly {
-                    let mut v = Vec::new();
-                    for point in ring {
-                        let p = input_proj
-                            .project(&output_proj, (point[0].to_radians(), point[1].to_radians()))
-                            .unwrap();
-                        v.push(vec![p.0, p.1]);
-                    }
-                    pos.push(v);
-                }
-                res.push(Feature {
-                             geometry: Some(Geometry::new(Value::Polygon(pos))),
-                             properties: feature.properties.to_owned(),
-                             bbox: None,
-                             id: feature.id.to_owned(),
-                             foreign_members: None,
-                         });
-            }
-            Value::MultiPolygon(positions) => {
-                let mut pos = Vec::new();
-                for poly in positions {
-                    let mut v = Vec::new();
-                    for ring in poly {
-                        let mut _v = Vec::new();
-                        for point in ring {
-                            let p = input_proj
-                                .project(&output_proj,
-                                         (point[0].to_radians(), point[1].to_radians()))
-                                .unwrap();
-                            _v.push(vec![p.0, p.1]);
-                        }
-                        v.push(_v)
-                    }
-                    pos.push(v);
-                }
-                res.push(Feature {
-                             geometry: Some(Geometry::new(Value::MultiPolygon(pos))),
-                             properties: feature.properties.to_owned(),
-                             bbox: None,
-                             id: feature.id.to_owned(),
-                             foreign_members: None,
-                         });
-            }
-            _ => panic!("I don't know what to do!"),
+    reproj_value(value, &unproject)
+}
+
+/// Radius (in meters) of the sphere having the same area as the WGS84 ellipsoid.
+const AUTHALIC_RADIUS: f64 = 6371007.2;
+
+/// Area (in square meters) enclosed by a ring of longitudes / latitudes, on the
+/// authalic sphere (positive whatever the orientation of the ring).
+fn ring_area(ring: &[Vec<f64>]) -> f64 {
+    let n = ring.len();
+    if n < 3 {
+        return 0.0;
+    }
+    let mut sum = 0.0;
+    for i in 0..n {
+        let previous = &ring[(i + n - 1) % n];
+        let next = &ring[(i + 1) % n];
+        sum += (next[0] - previous[0]).to_radians() * ring[i][1].to_radians().sin();
+    }
+    (sum * AUTHALIC_RADIUS * AUTHALIC_RADIUS / 2.0).abs()
+}
+
+/// Geodesic area (in square meters) of a geometry in longitude / latitude, the
+/// holes of the polygons being subtracted (the points and lines having no area).
+pub fn geodesic_area(value: &Value) -> f64 {
+    let polygon_area = |rings: &[Vec<Vec<f64>>]| {
+        rings.iter()
+            .enumerate()
+            .map(|(ix, ring)| if ix == 0 { ring_area(ring) } else { -ring_area(ring) })
+            .sum::<f64>()
+            .max(0.0)
+    };
+    match *value {
+        Value::Polygon(ref rings) => polygon_area(rings),
+        Value::MultiPolygon(ref polygons) => polygons.iter().map(|p| polygon_area(p)).sum(),
+        Value::GeometryCollection(ref geometries) => {
+            geometries.iter().map(|g| geodesic_area(&g.value)).sum()
         }
+        _ => 0.0,
+    }
+}
+
+/// Reprojects a single feature, `input_latlong` indicating whether its coordinates
+/// are longitudes / latitudes (in degrees) or already projected.
+pub fn reproj_feature(feature: Feature,
+                      input_proj: &Proj,
+                      output_proj: &Proj,
+                      input_latlong: bool)
+                      -> Feature {
+    let project = |point: &[f64]| {
+        let (x, y) = if input_latlong {
+            (point[0].to_radians(), point[1].to_radians())
+        } else {
+            (point[0], point[1])
+        };
+        let p = input_proj.project(&output_proj, (x, y)).unwrap();
+        vec![p.0, p.1]
+    };
+    Feature {
+        geometry: feature.geometry.map(|geom| Geometry::new(reproj_value(geom.value, &project))),
+        properties: feature.properties,
+        bbox: None,
+        id: feature.id,
+        foreign_members: None,
     }
+}
+
+/// Reprojects a layer (features without geometry are kept, so that they
+/// still match their values).
+pub fn reproj(decoded_geojson: &mut GeoJson,
+              input_proj: &Proj,
+              output_proj: &Proj,
+              input_latlong: bool)
+              -> GeoJson {
+    let features = match decoded_geojson {
+        &mut GeoJson::FeatureCollection(ref mut collection) => &mut collection.features,
+        _ => panic!("Error: expected a Feature collection of polygons!"),
+    };
+    let res = features
+        .drain(..)
+        .map(|feature| reproj_feature(feature, input_proj, output_proj, input_latlong))
+        .collect();
     GeoJson::from(FeatureCollection {
                       bbox: None,
                       foreign_members: None,
@@ -213,6 +258,33 @@ pub fn reproj(decoded_geojson: &mut GeoJson, input_proj: &Proj, output_proj: &Pr
                   })
 }
 
+/// Converts a layer from its own projected coordinate system to longitude /
+/// latitude (in degrees), for the maps drawn without any projection.
+pub fn unproject(decoded_geojson: &mut GeoJson, input_proj: &Proj, lonlat: &Proj) -> GeoJson {
+    let features = match decoded_geojson {
+        &mut GeoJson::FeatureCollection(ref mut collection) => &mut collection.features,
+        _ => panic!("Error: expected a Feature collection of polygons!"),
+    };
+    let res = features
+        .drain(..)
+        .map(|feature| {
+            Feature {
+                geometry: feature.geometry.map(|geom| {
+                    Geometry::new(unproject_value(geom.value, input_proj, lonlat))
+                }),
+                properties: feature.properties,
+                bbox: None,
+                id: feature.id,
+                foreign_members: None,
+            }
+        })
+        .collect();
+    GeoJson::from(FeatureCollection {
+                      bbox: None,
+                      foreign_members: None,
+                      features: res,
+                  })
+}
 
 pub fn reproj_graticule(decoded_geojson: &mut GeoJson,
                         input_proj: &Proj,
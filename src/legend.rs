@@ -0,0 +1,259 @@
+use std::collections::BTreeMap;
+use svg::Node;
+use svg::node::element::{Definitions, Group, Line, LinearGradient, Rectangle as Rect, Stop, Text};
+use svg::node::Text as NodeText;
+use toml;
+
+use config_params::as_number;
+
+/// Content of the legend of a layer, as computed when rendering it.
+pub enum Legend {
+    /// Classes of a choropleth: the bounds of the classes and their colors.
+    Classes {
+        title: String,
+        bounds: Vec<f64>,
+        colors: Vec<String>,
+        no_data: Option<NoDataEntry>,
+    },
+    /// Color scale of an unclassed choropleth, drawn as a gradient bar: its colors
+    /// (evenly spaced) and ticks (a position between 0 and 1, and its value).
+    Gradient {
+        /// Identifier of the gradient definition.
+        id: String,
+        title: String,
+        stops: Vec<String>,
+        ticks: Vec<(f64, f64)>,
+        no_data: Option<NoDataEntry>,
+    },
+    /// Grid of the classes of a bivariate choropleth, given the fields of its
+    /// axes and its colors (row by row from the lowest class of the second field).
+    Bivariate {
+        fields: (String, String),
+        colors: Vec<String>,
+        nb_class: usize,
+        no_data: Option<NoDataEntry>,
+    },
+}
+
+/// Entry of the features without a valid value.
+pub struct NoDataEntry {
+    /// Fill of the features (a color or the reference to a hatch pattern).
+    pub fill: String,
+    pub label: String,
+    pub count: usize,
+}
+
+/// Options of the legend of a layer, read from its `legend` section:
+///
+/// ```toml
+/// [layer.legend]
+/// position = [10, 10]  # top-left corner
+/// title = "Population"  # the name of the field by default
+/// font-size = "12"
+/// precision = 2  # number of decimals of the class bounds
+/// x-label = "Income"  # axes of a bivariate legend (the names of the fields by default)
+/// y-label = "Education"
+/// ```
+pub struct LegendOptions {
+    position: (f64, f64),
+    title: Option<String>,
+    font_size: f64,
+    precision: usize,
+    axis_labels: (Option<String>, Option<String>),
+}
+
+impl LegendOptions {
+    pub fn from_config(c: &BTreeMap<String, toml::Value>) -> Result<Self, String> {
+        let position = match c.get("position") {
+            Some(&toml::Value::Array(ref pos)) if pos.len() == 2 => {
+                match (as_number(&pos[0]), as_number(&pos[1])) {
+                    (Some(x), Some(y)) => (x, y),
+                    _ => return Err("The legend \"position\" should be two numbers!".to_string()),
+                }
+            }
+            Some(_) => return Err("The legend \"position\" should be two numbers!".to_string()),
+            None => (10.0, 10.0),
+        };
+        let font_size = string_or_default!(c.get("font-size"), "12");
+        let font_size = font_size
+            .parse::<f64>()
+            .ok()
+            .and_then(|v| if v > 0.0 { Some(v) } else { None })
+            .ok_or_else(|| format!("Invalid legend \"font-size\": {}", font_size))?;
+        let precision = match c.get("precision") {
+            Some(&toml::Value::Integer(v)) if v >= 0 => v as usize,
+            Some(_) => return Err("The legend \"precision\" should be a positive integer!".to_string()),
+            None => 2,
+        };
+        Ok(LegendOptions {
+               position: position,
+               title: c.get("title").and_then(|t| t.as_str()).map(|t| t.to_string()),
+               font_size: font_size,
+               precision: precision,
+               axis_labels: (c.get("x-label").and_then(|t| t.as_str()).map(|t| t.to_string()),
+                             c.get("y-label").and_then(|t| t.as_str()).map(|t| t.to_string())),
+           })
+    }
+}
+
+impl Legend {
+    pub fn render(&self, options: &LegendOptions) -> Group {
+        let (x, y) = options.position;
+        let font_size = options.font_size;
+        let box_width = font_size * 1.6;
+        let box_height = font_size * 1.2;
+        let spacing = font_size * 0.4;
+        let mut group = Group::new().set("font-size", font_size);
+        match *self {
+            Legend::Classes { ref title, ref bounds, ref colors, ref no_data } => {
+                let title = options.title.as_ref().unwrap_or(title);
+                group.append(Text::new()
+                                 .set("x", x)
+                                 .set("y", y + font_size)
+                                 .set("font-weight", "bold")
+                                 .add(NodeText::new(title.as_str())));
+                let mut entry_y = y + font_size * 1.5;
+                for (ix, color) in colors.iter().enumerate() {
+                    let label = format!("{:.*} – {:.*}",
+                                        options.precision,
+                                        bounds[ix],
+                                        options.precision,
+                                        bounds[ix + 1]);
+                    legend_entry(&mut group, (x, entry_y), (box_width, box_height), color, &label);
+                    entry_y += box_height + spacing;
+                }
+                if let Some(ref no_data) = *no_data {
+                    entry_y += spacing;
+                    let label = format!("{} ({})", no_data.label, no_data.count);
+                    legend_entry(&mut group,
+                                 (x, entry_y),
+                                 (box_width, box_height),
+                                 &no_data.fill,
+                                 &label);
+                }
+            }
+            Legend::Gradient { ref id, ref title, ref stops, ref ticks, ref no_data } => {
+                let title = options.title.as_ref().unwrap_or(title);
+                group.append(Text::new()
+                                 .set("x", x)
+                                 .set("y", y + font_size)
+                                 .set("font-weight", "bold")
+                                 .add(NodeText::new(title.as_str())));
+                // Vertical bar, the highest values at the top:
+                let mut gradient = LinearGradient::new()
+                    .set("id", id.as_str())
+                    .set("x1", 0)
+                    .set("y1", 1)
+                    .set("x2", 0)
+                    .set("y2", 0);
+                for (ix, color) in stops.iter().enumerate() {
+                    gradient.append(Stop::new()
+                                        .set("offset", ix as f64 / (stops.len() - 1) as f64)
+                                        .set("stop-color", color.as_str()));
+                }
+                group.append(Definitions::new().add(gradient));
+                let bar_y = y + font_size * 1.5;
+                let bar_height = box_height * 8.0;
+                group.append(Rect::new()
+                                 .set("x", x)
+                                 .set("y", bar_y)
+                                 .set("width", box_width)
+                                 .set("height", bar_height)
+                                 .set("fill", format!("url(#{})", id))
+                                 .set("stroke", "black")
+                                 .set("stroke-width", "0.5"));
+                for &(position, value) in ticks {
+                    let tick_y = bar_y + (1.0 - position) * bar_height;
+                    group.append(Line::new()
+                                     .set("x1", x + box_width)
+                                     .set("y1", tick_y)
+                                     .set("x2", x + box_width + spacing)
+                                     .set("y2", tick_y)
+                                     .set("stroke", "black")
+                                     .set("stroke-width", "0.5"));
+                    group.append(Text::new()
+                                     .set("x", x + box_width + spacing * 2.0)
+                                     .set("y", tick_y + font_size * 0.35)
+                                     .add(NodeText::new(format!("{:.*}", options.precision, value))));
+                }
+                if let Some(ref no_data) = *no_data {
+                    let label = format!("{} ({})", no_data.label, no_data.count);
+                    legend_entry(&mut group,
+                                 (x, bar_y + bar_height + spacing * 3.0),
+                                 (box_width, box_height),
+                                 &no_data.fill,
+                                 &label);
+                }
+            }
+            Legend::Bivariate { ref fields, ref colors, nb_class, ref no_data } => {
+                let mut grid_y = y;
+                if let Some(ref title) = options.title {
+                    group.append(Text::new()
+                                     .set("x", x)
+                                     .set("y", y + font_size)
+                                     .set("font-weight", "bold")
+                                     .add(NodeText::new(title.as_str())));
+                    grid_y += font_size * 1.5;
+                }
+                // Room for the label of the vertical axis:
+                let grid_x = x + font_size * 1.5;
+                let cell = box_width;
+                let grid_size = cell * nb_class as f64;
+                for (ix, color) in colors.iter().enumerate() {
+                    let (column, row) = (ix % nb_class, ix / nb_class);
+                    group.append(Rect::new()
+                                     .set("x", grid_x + column as f64 * cell)
+                                     .set("y", grid_y + (nb_class - 1 - row) as f64 * cell)
+                                     .set("width", cell)
+                                     .set("height", cell)
+                                     .set("fill", color.as_str())
+                                     .set("stroke", "white")
+                                     .set("stroke-width", "0.5"));
+                }
+                let x_label = options.axis_labels.0.as_ref().unwrap_or(&fields.0);
+                let y_label = options.axis_labels.1.as_ref().unwrap_or(&fields.1);
+                group.append(Text::new()
+                                 .set("x", grid_x + grid_size / 2.0)
+                                 .set("y", grid_y + grid_size + font_size * 1.2)
+                                 .set("text-anchor", "middle")
+                                 .add(NodeText::new(format!("{} →", x_label))));
+                let (label_x, label_y) = (grid_x - font_size * 0.4, grid_y + grid_size / 2.0);
+                group.append(Text::new()
+                                 .set("x", label_x)
+                                 .set("y", label_y)
+                                 .set("text-anchor", "middle")
+                                 .set("transform", format!("rotate(-90 {} {})", label_x, label_y))
+                                 .add(NodeText::new(format!("{} →", y_label))));
+                if let Some(ref no_data) = *no_data {
+                    let label = format!("{} ({})", no_data.label, no_data.count);
+                    legend_entry(&mut group,
+                                 (x, grid_y + grid_size + font_size * 2.0),
+                                 (box_width, box_height),
+                                 &no_data.fill,
+                                 &label);
+                }
+            }
+        }
+        group
+    }
+}
+
+/// Draws a box with the given fill, followed by its label.
+fn legend_entry(group: &mut Group,
+                (x, y): (f64, f64),
+                (width, height): (f64, f64),
+                fill: &str,
+                label: &str) {
+    group.append(Rect::new()
+                     .set("x", x)
+                     .set("y", y)
+                     .set("width", width)
+                     .set("height", height)
+                     .set("fill", fill)
+                     .set("stroke", "black")
+                     .set("stroke-width", "0.5"));
+    group.append(Text::new()
+                     .set("x", x + width + height * 0.4)
+                     .set("y", y + height * 0.8)
+                     .add(NodeText::new(label)));
+}
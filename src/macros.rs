@@ -1,12 +1,3 @@
-macro_rules! expect_float {
-    ($value:expr, $name:expr) => (
-        match $value.as_float() {
-            Some(v) => v,
-            None => panic!("Expected float value on property \"{}\"!", $name)
-        }
-    )
-}
-
 macro_rules! string_or_default {
     ($value:expr, $default:expr) => (
         if $value.is_none() {
@@ -19,3 +10,15 @@ macro_rules! string_or_default {
         }
     )
 }
+
+macro_rules! style_or_default {
+    ($value:expr, $default:expr) => (
+        match ::config_params::StyleValue::from_config($value, $default) {
+            Ok(v) => v,
+            Err(err) => {
+                println!("{}", err);
+                ::std::process::exit(1)
+            }
+        }
+    )
+}
@@ -5,11 +5,23 @@ extern crate toml;
 extern crate proj;
 extern crate colorbrewer;
 extern crate classif;
+extern crate byteorder;
+extern crate rusqlite;
+extern crate serde_json;
+extern crate xml;
+extern crate flate2;
+extern crate zip;
+extern crate resvg;
+extern crate usvg;
+extern crate tiny_skia;
+extern crate svg2pdf;
 
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use clap::{Arg, App};
 use classif::{BoundsInfo, Classification};
-use geojson::{GeoJson, Value};
+use geojson::{Feature, FeatureCollection, GeoJson, Value};
+use serde_json::{Map, Value as JsonValue};
 use proj::Proj;
 use std::env::set_current_dir;
 use std::fs::File;
@@ -17,7 +29,8 @@ use std::io::Read;
 use std::path::Path as StdPath;
 use svg::Document;
 use svg::Node;
-use svg::node::element::{Circle, Group, Path, Rectangle as Rect, Text};
+use svg::node::element::{Circle, Definitions, Group, Line, Path, Pattern, Rectangle as Rect,
+                         Style, Text};
 use svg::node::Text as NodeText;
 use svg::node::element::path::Data;
 
@@ -26,94 +39,719 @@ mod macros;
 mod layer;
 mod graticule;
 mod config_params;
+mod input;
+mod output;
+mod expression;
+mod color;
+mod legend;
+mod dots;
+mod cartogram;
 
-use config_params::MapExtent;
-use layer::{reproj, reproj_graticule, get_nb_class, get_extent, get_values};
+use config_params::{FitMode, MapExtent, MapScale, OutputFormat, Padding, StyleValue, as_number,
+                    number_list, print_dpi, raster_zoom, string_list};
+use layer::{reproj, reproj_feature, reproj_graticule, get_nb_class, extend_extent, geodesic_area,
+            get_extent, get_values, project_extent, unproject, unproject_extent, unproject_value};
 use graticule::prepare_geojson_graticule;
+use output::{FontConfig, save_pdf, save_png};
+use expression::Expr;
+use color::{ColorScale, Interpolation, bivariate_palette};
+use dots::{MAX_DOTS, Rng, random_points};
+use cartogram::cartogram;
+use legend::{Legend, LegendOptions, NoDataEntry};
+use input::{BboxFilter, JoinTable, is_indexed, is_streamable, layer_name, read_layer,
+            stream_layer};
+
+/// Property in which the geodesic area (in square meters) of the features is
+/// stored, when a choropleth maps a density.
+const AREA_PROPERTY: &'static str = "$area";
+
+/// How the values of the field of a choropleth are normalized.
+enum Normalization {
+    /// Divided by the values of another field.
+    Ratio(String),
+    /// Divided by the geodesic area of the features, given the size (in square
+    /// meters) and the name of the area unit.
+    Density(f64, &'static str),
+}
 
 struct ChoroplethLayerProperties {
     type_classification: String,
     field_name: String,
+    normalization: Option<Normalization>,
+    /// Factor applied to the normalized values (like 1000 for "per 1000 inhabitants").
+    per: f64,
     palette_name: String,
-    fill_opacity: String,
-    stroke: String,
-    stroke_opacity: String,
-    stroke_width: String,
-    radius: String,
+    /// Number of classes (given by Sturges' rule by default).
+    nb_class: Option<u32>,
+    /// Bounds of the classes, from the minimum to the maximum (instead of a classification).
+    breaks: Option<Vec<f64>>,
+    /// Colors of the classes (instead of a palette).
+    colors: Option<Vec<String>>,
+    reverse_palette: bool,
+    /// Value on which a diverging palette is centered (the classes being computed
+    /// separately on each side of it).
+    center: Option<f64>,
+    /// Interpolation of the colors of an unclassed choropleth (without classes,
+    /// each feature being colored according to its position on the color scale).
+    interpolation: Option<Interpolation>,
+    style: ClassedStyle,
 }
 
 impl ChoroplethLayerProperties {
     fn from_config(c: &BTreeMap<String, toml::value::Value>) -> Self {
-        ChoroplethLayerProperties {
+        let prop = ChoroplethLayerProperties {
             type_classification: string_or_default!(c.get("classification"), "Quantiles"),
             field_name: string_or_default!(c.get("field"), "aaa"),
+            normalization: match c.get("normalize").and_then(|v| v.as_str()) {
+                None => None,
+                Some("ratio") => {
+                    match c.get("divide-by").and_then(|v| v.as_str()) {
+                        Some(field) => Some(Normalization::Ratio(field.to_string())),
+                        None => {
+                            println!("\"divide-by\" (the name of a field) is required to normalize by ratio!");
+                            std::process::exit(1)
+                        }
+                    }
+                }
+                Some("density") => {
+                    match c.get("area-unit").and_then(|v| v.as_str()).unwrap_or("km2") {
+                        "km2" => Some(Normalization::Density(1e6, "km²")),
+                        "ha" => Some(Normalization::Density(1e4, "ha")),
+                        "m2" => Some(Normalization::Density(1.0, "m²")),
+                        unit => {
+                            println!("Invalid \"area-unit\": {} (expected \"km2\", \"ha\" or \"m2\")",
+                                     unit);
+                            std::process::exit(1)
+                        }
+                    }
+                }
+                Some(_) => {
+                    println!("\"normalize\" should be \"ratio\" or \"density\"!");
+                    std::process::exit(1)
+                }
+            },
+            per: match c.get("per").map(as_number) {
+                Some(Some(v)) if v > 0.0 => v,
+                Some(_) => {
+                    println!("\"per\" should be a positive number!");
+                    std::process::exit(1)
+                }
+                None => 1.0,
+            },
             palette_name: string_or_default!(c.get("palette"), "Greens"),
-            fill_opacity: string_or_default!(c.get("fill-opacity"), "0.8"),
-            stroke: string_or_default!(c.get("stroke"), "black"),
-            stroke_opacity: string_or_default!(c.get("stroke-opacity"), "1"),
-            stroke_width: string_or_default!(c.get("stroke-width"), "0.7"),
-            radius: string_or_default!(c.get("radius"), "4"),
+            nb_class: match c.get("nb-class") {
+                Some(&toml::Value::Integer(v)) if v > 0 => Some(v as u32),
+                Some(_) => {
+                    println!("\"nb-class\" should be a positive integer!");
+                    std::process::exit(1)
+                }
+                None => None,
+            },
+            breaks: number_list(c.get("breaks"), "breaks").unwrap_or_else(|err| {
+                println!("{}", err);
+                std::process::exit(1)
+            }),
+            colors: string_list(c.get("colors"), "colors").unwrap_or_else(|err| {
+                println!("{}", err);
+                std::process::exit(1)
+            }),
+            reverse_palette: match c.get("reverse-palette") {
+                Some(&toml::Value::Boolean(v)) => v,
+                Some(_) => {
+                    println!("\"reverse-palette\" should be a boolean!");
+                    std::process::exit(1)
+                }
+                None => false,
+            },
+            center: match c.get("center").map(as_number) {
+                Some(Some(v)) => Some(v),
+                Some(None) => {
+                    println!("\"center\" should be a number!");
+                    std::process::exit(1)
+                }
+                None => None,
+            },
+            interpolation: c.get("interpolation").map(|v| {
+                v.as_str()
+                    .ok_or_else(|| "\"interpolation\" should be a string!".to_string())
+                    .and_then(|v| v.parse::<Interpolation>())
+                    .unwrap_or_else(|err| {
+                                        println!("{}", err);
+                                        std::process::exit(1)
+                                    })
+            }),
+            style: ClassedStyle::from_config(c),
+        };
+        if let Err(err) = prop.check_classes() {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+        prop
+    }
+
+    /// Checks that the number of classes, the breaks and the colors (when given) agree.
+    fn check_classes(&self) -> Result<(), String> {
+        if let Some(ref breaks) = self.breaks {
+            if breaks.len() < 2 {
+                return Err("\"breaks\" should contain at least two values (the minimum and the maximum)!"
+                               .to_string());
+            }
+            if breaks.windows(2).any(|w| w[0] >= w[1]) {
+                return Err("\"breaks\" should be in strictly ascending order!".to_string());
+            }
+        }
+        if let Some(ref colors) = self.colors {
+            if colors.is_empty() {
+                return Err("\"colors\" should contain at least one color!".to_string());
+            }
+        }
+        let nb_class_breaks = self.breaks.as_ref().map(|b| b.len() - 1);
+        let nb_class_colors = self.colors.as_ref().map(|c| c.len());
+        if self.interpolation.is_some() && (self.breaks.is_some() || self.center.is_some()) {
+            return Err("An unclassed choropleth (with \"interpolation\") can't use \"breaks\" or \"center\"!"
+                           .to_string());
+        }
+        if self.center.is_some() {
+            if self.breaks.is_some() {
+                return Err("\"center\" and \"breaks\" can't be used together!".to_string());
+            }
+            let nb_class = self.nb_class.map(|n| n as usize).or(nb_class_colors);
+            if nb_class.map_or(false, |n| n % 2 != 0) {
+                return Err("A choropleth centered on a value needs an even number of classes!"
+                               .to_string());
+            }
+        }
+        match (self.nb_class.map(|n| n as usize), nb_class_breaks, nb_class_colors) {
+            (Some(n), Some(b), _) if n != b => {
+                Err(format!("\"nb-class\" is {} but \"breaks\" define {} classes!", n, b))
+            }
+            (Some(n), _, Some(c)) if n != c => {
+                Err(format!("\"nb-class\" is {} but {} \"colors\" are given!", n, c))
+            }
+            (_, Some(b), Some(c)) if b != c => {
+                Err(format!("\"breaks\" define {} classes but {} \"colors\" are given!", b, c))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Values to classify: the values of the field, normalized if requested (the
+    /// features with a zero or missing denominator, or a value which isn't positive
+    /// with a logarithmic interpolation, having no valid value).
+    fn values(&self, features: &[Feature]) -> Vec<Option<f64>> {
+        let values = self.normalized_values(features);
+        if self.interpolation == Some(Interpolation::Log) {
+            values.into_iter().map(|v| v.and_then(|v| if v > 0.0 { Some(v) } else { None })).collect()
+        } else {
+            values
+        }
+    }
+
+    fn normalized_values(&self, features: &[Feature]) -> Vec<Option<f64>> {
+        let values = get_values(features, &self.field_name);
+        let (denominators, unit) = match self.normalization {
+            None => return values,
+            Some(Normalization::Ratio(ref field)) => (get_values(features, field), 1.0),
+            Some(Normalization::Density(unit, _)) => {
+                (get_values(features, &AREA_PROPERTY.to_string()), unit)
+            }
+        };
+        values.into_iter()
+            .zip(denominators)
+            .map(|(value, denominator)| match (value, denominator) {
+                     (Some(v), Some(d)) if d != 0.0 => Some(v / (d / unit) * self.per),
+                     _ => None,
+                 })
+            .collect()
+    }
+
+    /// Description of the mapped values (the default title of the legend).
+    fn value_title(&self) -> String {
+        let per = if self.per == 1.0 {
+            String::new()
+        } else {
+            format!("{} ", self.per)
+        };
+        match self.normalization {
+            None => self.field_name.clone(),
+            Some(Normalization::Ratio(ref field)) if self.per == 1.0 => {
+                format!("{} / {}", self.field_name, field)
+            }
+            Some(Normalization::Ratio(ref field)) => {
+                format!("{} per {}{}", self.field_name, per, field)
+            }
+            Some(Normalization::Density(_, unit)) => {
+                format!("{} per {}{}", self.field_name, per, unit)
+            }
+        }
+    }
+}
+
+/// Style shared by the classes of a choropleth (or bivariate) layer, and style
+/// of the features without a valid value.
+struct ClassedStyle {
+    fill_opacity: StyleValue,
+    stroke: StyleValue,
+    stroke_opacity: StyleValue,
+    stroke_width: StyleValue,
+    radius: StyleValue,
+    /// Color of the features without a valid value.
+    no_data: String,
+    /// Whether these features are filled with a hatch (instead of a plain color).
+    no_data_hatch: bool,
+    no_data_label: String,
+}
+
+impl ClassedStyle {
+    fn from_config(c: &BTreeMap<String, toml::value::Value>) -> Self {
+        ClassedStyle {
+            fill_opacity: style_or_default!(c.get("fill-opacity"), "0.8"),
+            stroke: style_or_default!(c.get("stroke"), "black"),
+            stroke_opacity: style_or_default!(c.get("stroke-opacity"), "1"),
+            stroke_width: style_or_default!(c.get("stroke-width"), "0.7"),
+            radius: style_or_default!(c.get("radius"), "4"),
+            no_data: string_or_default!(c.get("no-data"), "lightgrey"),
+            no_data_hatch: match c.get("no-data-style").and_then(|v| v.as_str()) {
+                Some("fill") | None => false,
+                Some("hatch") => true,
+                Some(_) => {
+                    println!("\"no-data-style\" should be \"fill\" or \"hatch\"!");
+                    std::process::exit(1)
+                }
+            },
+            no_data_label: string_or_default!(c.get("no-data-label"), "No data"),
+        }
+    }
+
+    /// Fill of the features without a valid value: the "no data" color, or the
+    /// hatch pattern of the layer.
+    fn no_data_fill(&self, layer_name: &str) -> String {
+        if self.no_data_hatch {
+            format!("url(#{}-no-data-hatch)", css_class_name(layer_name))
+        } else {
+            self.no_data.clone()
+        }
+    }
+
+    /// Hatch pattern (diagonal lines of the "no data" color) used by the
+    /// features without a valid value.
+    fn no_data_pattern(&self, layer_name: &str) -> Pattern {
+        Pattern::new()
+            .set("id", format!("{}-no-data-hatch", css_class_name(layer_name)))
+            .set("patternUnits", "userSpaceOnUse")
+            .set("width", 6)
+            .set("height", 6)
+            .set("patternTransform", "rotate(45)")
+            .add(Line::new()
+                     .set("x1", 0)
+                     .set("y1", 0)
+                     .set("x2", 0)
+                     .set("y2", 6)
+                     .set("stroke", self.no_data.as_str())
+                     .set("stroke-width", 3))
+    }
+
+    /// Adds the CSS rules styling the features of the layer: every element has the
+    /// class `class` and the class of its class break (like `class-2`, or `class-no-data`
+    /// without a valid value), the lines and the points also have the `line` or `point` class.
+    /// The palette of an unclassed choropleth is empty, its colors being set on the features.
+    fn add_css_rules(&self,
+                     class: &str,
+                     palette: &[String],
+                     no_data_fill: &str,
+                     stylesheet: &mut StyleSheet) {
+        stylesheet.add_style_rule(&format!(".{}", class),
+                                  &[("fill-opacity", &self.fill_opacity),
+                                    ("stroke", &self.stroke),
+                                    ("stroke-width", &self.stroke_width),
+                                    ("stroke-opacity", &self.stroke_opacity)]);
+        stylesheet.add_rule(&format!(".{}.line", class), &[("fill", "none")]);
+        stylesheet.add_rule(&format!(".{}.point", class),
+                            &[("fill-opacity", "1"), ("stroke", "none")]);
+        for (ix, color) in palette.iter().enumerate() {
+            stylesheet.add_rule(&format!(".{}-{}", class, ix), &[("fill", color)]);
+            stylesheet.add_rule(&format!(".{}-{}.line", class, ix), &[("stroke", color)]);
+        }
+        stylesheet.add_rule(&format!(".{}-no-data", class), &[("fill", no_data_fill)]);
+        stylesheet.add_rule(&format!(".{}-no-data.line", class), &[("stroke", no_data_fill)]);
+    }
+}
+
+/// Index of the class of a value, the upper bound of each class being included
+/// in it (the values out of the bounds are in the first or the last class).
+fn class_index(bounds: &[f64], value: f64) -> usize {
+    let nb_class = bounds.len() - 1;
+    (1..nb_class)
+        .find(|&ix| value <= bounds[ix])
+        .map(|ix| ix - 1)
+        .unwrap_or(nb_class - 1)
+}
+
+/// Entry of the legend for the features without a valid value (if any).
+fn no_data_entry(style: &ClassedStyle, fill: &str, nb_no_data: usize) -> Option<NoDataEntry> {
+    if nb_no_data > 0 {
+        Some(NoDataEntry {
+                 fill: fill.to_string(),
+                 label: style.no_data_label.clone(),
+                 count: nb_no_data,
+             })
+    } else {
+        None
+    }
+}
+
+/// Classes of a choropleth layer, computed from the valid values of its field.
+struct ChoroplethClasses {
+    /// Bounds of the classes (one more than the number of classes).
+    bounds: Vec<f64>,
+    palette: Vec<String>,
+    /// Color scale of an unclassed choropleth (without bounds nor palette), the
+    /// color of each feature being then set on the feature itself.
+    scale: Option<ColorScale>,
+    no_data_fill: String,
+    /// Number of features without a valid value.
+    nb_no_data: usize,
+}
+
+impl ChoroplethClasses {
+    /// Suffix of the class name (the class index, or `no-data`) and fill of a feature,
+    /// the features of an unclassed choropleth having no class of their own.
+    fn style_of(&self, value: Option<f64>) -> (Option<String>, Cow<str>) {
+        match (value, self.scale.as_ref()) {
+            (Some(v), Some(scale)) => (None, Cow::Owned(scale.color(v))),
+            (Some(v), None) => {
+                let ix = class_index(&self.bounds, v);
+                (Some(ix.to_string()), Cow::Borrowed(self.palette[ix].as_str()))
+            }
+            (None, _) => (Some("no-data".to_string()), Cow::Borrowed(self.no_data_fill.as_str())),
+        }
+    }
+
+    fn legend(&self, name: &str, prop: &ChoroplethLayerProperties) -> Legend {
+        if let Some(ref scale) = self.scale {
+            let ticks = [0.0, 0.25, 0.5, 0.75, 1.0];
+            return Legend::Gradient {
+                       id: format!("{}-legend-gradient", css_class_name(name)),
+                       title: prop.value_title(),
+                       stops: scale.stops(),
+                       ticks: ticks.iter().map(|&t| (t, scale.value_at(t))).collect(),
+                       no_data: no_data_entry(&prop.style, &self.no_data_fill, self.nb_no_data),
+                   };
+        }
+        Legend::Classes {
+            title: prop.value_title(),
+            bounds: self.bounds.clone(),
+            colors: self.palette.clone(),
+            no_data: no_data_entry(&prop.style, &self.no_data_fill, self.nb_no_data),
+        }
+    }
+}
+
+struct BivariateLayerProperties {
+    /// Fields shown on the horizontal and the vertical axis of the legend.
+    fields: (String, String),
+    type_classification: String,
+    /// Number of classes of each field (from 2 to 4).
+    nb_class: usize,
+    palette_name: String,
+    /// Colors of the classes (instead of a palette), row by row from the lowest
+    /// class of the second field.
+    colors: Option<Vec<String>>,
+    style: ClassedStyle,
+}
+
+impl BivariateLayerProperties {
+    fn from_config(c: &BTreeMap<String, toml::value::Value>) -> Self {
+        let field = |name: &str| match c.get(name).and_then(|v| v.as_str()) {
+            Some(field) => field.to_string(),
+            None => {
+                println!("\"{}\" (the name of a field) is required by a bivariate choropleth!",
+                         name);
+                std::process::exit(1)
+            }
+        };
+        let prop = BivariateLayerProperties {
+            fields: (field("field-x"), field("field-y")),
+            type_classification: string_or_default!(c.get("classification"), "Quantiles"),
+            nb_class: match c.get("nb-class") {
+                Some(&toml::Value::Integer(v)) if v >= 2 && v <= 4 => v as usize,
+                Some(_) => {
+                    println!("\"nb-class\" of a bivariate choropleth should be 2, 3 or 4!");
+                    std::process::exit(1)
+                }
+                None => 3,
+            },
+            palette_name: string_or_default!(c.get("palette"), "TealPink"),
+            colors: string_list(c.get("colors"), "colors").unwrap_or_else(|err| {
+                println!("{}", err);
+                std::process::exit(1)
+            }),
+            style: ClassedStyle::from_config(c),
+        };
+        if let Some(ref colors) = prop.colors {
+            if colors.len() != prop.nb_class * prop.nb_class {
+                println!("{} \"colors\" are needed for {} × {} classes, {} are given!",
+                         prop.nb_class * prop.nb_class,
+                         prop.nb_class,
+                         prop.nb_class,
+                         colors.len());
+                std::process::exit(1);
+            }
+        }
+        prop
+    }
+}
+
+/// Classes of a bivariate layer: the class bounds of each field and the colors
+/// of the grid of classes.
+struct BivariateClasses {
+    bounds: (Vec<f64>, Vec<f64>),
+    palette: Vec<String>,
+    no_data_fill: String,
+    nb_no_data: usize,
+}
+
+impl BivariateClasses {
+    /// Suffix of the class name (the index of the class in the grid, or `no-data`)
+    /// and fill of a feature.
+    fn style_of(&self, values: (Option<f64>, Option<f64>)) -> (String, &str) {
+        match values {
+            (Some(x), Some(y)) => {
+                let nb_class = self.bounds.0.len() - 1;
+                let ix = class_index(&self.bounds.1, y) * nb_class + class_index(&self.bounds.0, x);
+                (ix.to_string(), self.palette[ix].as_str())
+            }
+            _ => ("no-data".to_string(), self.no_data_fill.as_str()),
+        }
+    }
+
+    fn legend(&self, prop: &BivariateLayerProperties) -> Legend {
+        Legend::Bivariate {
+            fields: prop.fields.clone(),
+            colors: self.palette.clone(),
+            nb_class: prop.nb_class,
+            no_data: no_data_entry(&prop.style, &self.no_data_fill, self.nb_no_data),
         }
     }
 }
 
 
 struct SingleColorLayerProperties {
-    fill: String,
-    fill_opacity: String,
-    stroke: String,
-    stroke_opacity: String,
-    stroke_width: String,
-    radius: String,
+    fill: StyleValue,
+    fill_opacity: StyleValue,
+    stroke: StyleValue,
+    stroke_opacity: StyleValue,
+    stroke_width: StyleValue,
+    radius: StyleValue,
 }
 
 impl SingleColorLayerProperties {
     fn from_config(c: &BTreeMap<String, toml::value::Value>) -> Self {
         SingleColorLayerProperties {
-            fill: string_or_default!(c.get("fill"), "blue"),
-            fill_opacity: string_or_default!(c.get("fill-opacity"), "0.8"),
-            stroke: string_or_default!(c.get("stroke"), "black"),
-            stroke_opacity: string_or_default!(c.get("stroke-opacity"), "1"),
-            stroke_width: string_or_default!(c.get("stroke-width"), "0.7"),
-            radius: string_or_default!(c.get("radius"), "4"),
+            fill: style_or_default!(c.get("fill"), "blue"),
+            fill_opacity: style_or_default!(c.get("fill-opacity"), "0.8"),
+            stroke: style_or_default!(c.get("stroke"), "black"),
+            stroke_opacity: style_or_default!(c.get("stroke-opacity"), "1"),
+            stroke_width: style_or_default!(c.get("stroke-width"), "0.7"),
+            radius: style_or_default!(c.get("radius"), "4"),
         }
     }
+    /// Adds the CSS rules styling the features of the layer, whose elements have
+    /// the class `class` (and the `line` or `point` class for lines and points).
+    fn add_css_rules(&self, class: &str, stylesheet: &mut StyleSheet) {
+        stylesheet.add_style_rule(&format!(".{}", class),
+                                  &[("fill", &self.fill),
+                                    ("fill-opacity", &self.fill_opacity),
+                                    ("stroke", &self.stroke),
+                                    ("stroke-width", &self.stroke_width),
+                                    ("stroke-opacity", &self.stroke_opacity)]);
+        stylesheet.add_rule(&format!(".{}.line", class), &[("fill", "none")]);
+        stylesheet.add_rule(&format!(".{}.point", class),
+                            &[("fill-opacity", "1"), ("stroke", "none")]);
+    }
+
     fn default() -> Self {
         SingleColorLayerProperties {
-            fill: String::from("blue"),
-            fill_opacity: String::from("0.8"),
-            stroke: String::from("black"),
-            stroke_opacity: String::from("1"),
-            stroke_width: String::from("0.7"),
-            radius: String::from("4"),
+            fill: StyleValue::Constant(String::from("blue")),
+            fill_opacity: StyleValue::Constant(String::from("0.8")),
+            stroke: StyleValue::Constant(String::from("black")),
+            stroke_opacity: StyleValue::Constant(String::from("1")),
+            stroke_width: StyleValue::Constant(String::from("0.7")),
+            radius: StyleValue::Constant(String::from("4")),
         }
     }
 }
 
-struct Converter<'a> {
+struct DotDensityLayerProperties {
+    fields: Vec<String>,
+    /// Color of the dots of each field.
+    colors: Vec<StyleValue>,
+    /// Value represented by each dot.
+    dot_value: f64,
+    seed: u64,
+    radius: StyleValue,
+    dot_opacity: StyleValue,
+    /// Style of the polygons in which the dots are drawn.
+    outline: SingleColorLayerProperties,
+}
+
+impl DotDensityLayerProperties {
+    fn from_config(c: &BTreeMap<String, toml::value::Value>) -> Self {
+        let fields = match string_list(c.get("fields"), "fields") {
+            Ok(Some(ref fields)) if !fields.is_empty() => fields.clone(),
+            Ok(_) => {
+                println!("\"fields\" (a list of fields) is required by a dot density layer!");
+                std::process::exit(1)
+            }
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1)
+            }
+        };
+        let colors = match string_list(c.get("colors"), "colors") {
+            Ok(Some(colors)) => colors,
+            Ok(None) => {
+                "Set1"
+                    .parse::<colorbrewer::Palette>()
+                    .ok()
+                    .and_then(|p| colorbrewer::get_color_ramp(p, fields.len().max(3) as u32))
+                    .unwrap_or_else(|| {
+                        println!("\"colors\" are required to draw the dots of {} fields!",
+                                 fields.len());
+                        std::process::exit(1)
+                    })
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect()
+            }
+            Err(err) => {
+                println!("{}", err);
+                std::process::exit(1)
+            }
+        };
+        if colors.len() < fields.len() {
+            println!("{} \"colors\" are given for {} \"fields\"!", colors.len(), fields.len());
+            std::process::exit(1);
+        }
+        DotDensityLayerProperties {
+            colors: colors.into_iter().take(fields.len()).map(StyleValue::Constant).collect(),
+            fields: fields,
+            dot_value: match c.get("dot-value").map(as_number) {
+                Some(Some(v)) if v > 0.0 => v,
+                Some(_) => {
+                    println!("\"dot-value\" should be a positive number!");
+                    std::process::exit(1)
+                }
+                None => 1.0,
+            },
+            seed: match c.get("seed") {
+                Some(&toml::Value::Integer(v)) => v as u64,
+                Some(_) => {
+                    println!("\"seed\" should be an integer!");
+                    std::process::exit(1)
+                }
+                None => 0,
+            },
+            radius: style_or_default!(c.get("radius"), "1"),
+            dot_opacity: style_or_default!(c.get("dot-opacity"), "1"),
+            outline: SingleColorLayerProperties {
+                fill: style_or_default!(c.get("fill"), "none"),
+                fill_opacity: style_or_default!(c.get("fill-opacity"), "1"),
+                stroke: style_or_default!(c.get("stroke"), "grey"),
+                stroke_opacity: style_or_default!(c.get("stroke-opacity"), "1"),
+                stroke_width: style_or_default!(c.get("stroke-width"), "0.5"),
+                radius: StyleValue::Constant("0".to_string()),
+            },
+        }
+    }
+
+    /// Adds the CSS rules styling the polygons (with the class `class`) and the
+    /// dots (with the class `class-dot` and the class of their field, like `class-dot-1`).
+    fn add_css_rules(&self, class: &str, stylesheet: &mut StyleSheet) {
+        self.outline.add_css_rules(class, stylesheet);
+        stylesheet.add_style_rule(&format!(".{}-dot", class),
+                                  &[("fill-opacity", &self.dot_opacity)]);
+        stylesheet.add_rule(&format!(".{}-dot", class), &[("stroke", "none")]);
+        for (ix, color) in self.colors.iter().enumerate() {
+            stylesheet.add_style_rule(&format!(".{}-dot-{}", class, ix), &[("fill", color)]);
+        }
+    }
+}
+
+struct Converter {
     viewport_width: u32,
     viewport_height: u32,
-    map_extent: &'a MapExtent,
-    resolution: f64,
+    map_extent: MapExtent,
+    xres: f64,
+    yres: f64,
+    offset_x: f64,
+    offset_y: f64,
 }
 
-impl<'a> Converter<'a> {
-    pub fn new(viewport_width: u32, viewport_height: u32, map_extent: &'a MapExtent) -> Self {
-        let xres = (map_extent.right - map_extent.left) / viewport_width as f64;
-        let yres = (map_extent.top - map_extent.bottom) / viewport_height as f64;
-        let res = xres.max(yres);
+impl Converter {
+    pub fn new(viewport_width: u32,
+               viewport_height: u32,
+               map_extent: MapExtent,
+               fit_mode: FitMode)
+               -> Self {
+        let xres = map_extent.width() / viewport_width as f64;
+        let yres = map_extent.height() / viewport_height as f64;
+        let (xres, yres) = match fit_mode {
+            FitMode::Contain => (xres.max(yres), xres.max(yres)),
+            FitMode::Cover => (xres.min(yres), xres.min(yres)),
+            FitMode::Stretch => (xres, yres),
+        };
+        // Center the map in the viewport:
+        let offset_x = (viewport_width as f64 - map_extent.width() / xres) / 2.0;
+        let offset_y = (viewport_height as f64 - map_extent.height() / yres) / 2.0;
         Converter {
             viewport_width: viewport_width,
             viewport_height: viewport_height,
             map_extent: map_extent,
-            resolution: res,
+            xres: xres,
+            yres: yres,
+            offset_x: offset_x,
+            offset_y: offset_y,
+        }
+    }
+
+    /// Derives the extent of the map from its (projected) center and its scale.
+    pub fn from_center(viewport_width: u32,
+                       viewport_height: u32,
+                       center: (f64, f64),
+                       scale: &MapScale)
+                       -> Self {
+        let res = scale.resolution();
+        let half_width = viewport_width as f64 * res / 2.0;
+        let half_height = viewport_height as f64 * res / 2.0;
+        let map_extent = MapExtent {
+            left: center.0 - half_width,
+            right: center.0 + half_width,
+            bottom: center.1 - half_height,
+            top: center.1 + half_height,
+        };
+        Converter::new(viewport_width, viewport_height, map_extent, FitMode::Stretch)
+    }
+
+    /// Extent of the area visible in the viewport (which differs from the
+    /// extent of the map when the aspect ratio is preserved).
+    pub fn visible_extent(&self) -> MapExtent {
+        MapExtent {
+            left: self.map_extent.left - self.offset_x * self.xres,
+            right: self.map_extent.left + (self.viewport_width as f64 - self.offset_x) * self.xres,
+            bottom: self.map_extent.top - (self.viewport_height as f64 - self.offset_y) * self.yres,
+            top: self.map_extent.top + self.offset_y * self.yres,
         }
     }
 
+    fn to_viewport(&self, point: &[f64]) -> (f64, f64) {
+        (self.offset_x + (point[0] - self.map_extent.left) / self.xres,
+         self.offset_y + (self.map_extent.top - point[1]) / self.yres)
+    }
+
     pub fn draw_point(&self, point: &[f64]) -> Circle {
-        Circle::new()
-            .set("cx", (point[0] - self.map_extent.left) / self.resolution)
-            .set("cy", (self.map_extent.top - point[1]) / self.resolution)
+        let (x, y) = self.to_viewport(point);
+        Circle::new().set("cx", x).set("cy", y)
     }
 
     pub fn draw_path_ring(&self, positions: &[Vec<Vec<f64>>], d: Option<Data>) -> Data {
@@ -124,16 +762,135 @@ impl<'a> Converter<'a> {
         };
         for ring in positions {
             let mut iter = ring.iter();
-            let first = iter.next().unwrap();
-            data = data.move_to(((first[0] - self.map_extent.left) / self.resolution,
-                                 (self.map_extent.top - first[1]) / self.resolution));
+            let first = match iter.next() {
+                Some(first) => first,
+                None => continue,
+            };
+            data = data.move_to(self.to_viewport(first));
             for point in iter {
-                data = data.line_to(((point[0] - self.map_extent.left) / self.resolution,
-                                     (self.map_extent.top - point[1]) / self.resolution));
+                data = data.line_to(self.to_viewport(point));
             }
         }
         if close { data.close() } else { data }
     }
+
+    /// Draws a geometry, as circles for its points and as paths for its
+    /// lines and polygons.
+    pub fn draw_shapes(&self, value: &Value) -> Vec<Shape> {
+        match *value {
+            Value::Point(ref point) => vec![Shape::Point(self.draw_point(point))],
+            Value::MultiPoint(ref points) => {
+                points.iter().map(|point| Shape::Point(self.draw_point(point))).collect()
+            }
+            Value::LineString(ref positions) => {
+                let data = self.draw_path_ring(&[positions.to_vec()], Some(Data::new()));
+                vec![Shape::Line(Path::new().set("d", data))]
+            }
+            Value::MultiLineString(ref lines) => {
+                let mut data = Data::new();
+                for positions in lines {
+                    data = self.draw_path_ring(&[positions.to_vec()], Some(data));
+                }
+                vec![Shape::Line(Path::new().set("d", data))]
+            }
+            Value::Polygon(ref positions) => {
+                vec![Shape::Polygon(Path::new().set("d", self.draw_path_ring(positions, None)))]
+            }
+            Value::MultiPolygon(ref polys) => {
+                let mut data = Data::new();
+                for positions in polys {
+                    data = self.draw_path_ring(positions, Some(data));
+                }
+                vec![Shape::Polygon(Path::new().set("d", data.close()))]
+            }
+            Value::GeometryCollection(ref geometries) => {
+                geometries.iter().flat_map(|geom| self.draw_shapes(&geom.value)).collect()
+            }
+        }
+    }
+}
+
+/// Sets the style of an element drawn for a feature. When the features are styled
+/// with CSS classes, the element gets its `class` and only the data-driven properties
+/// (and the radius of the points, which isn't a CSS property) are set as attributes.
+fn styled<T: Node>(mut element: T,
+                   class: Option<String>,
+                   style: &[(&str, &StyleValue)],
+                   properties: &Map<String, JsonValue>)
+                   -> T {
+    let with_class = class.is_some();
+    if let Some(class) = class {
+        element.assign("class", class);
+    }
+    for &(name, value) in style {
+        if !with_class || value.constant().is_none() || name == "r" {
+            element.assign(name, value.evaluate(properties));
+        }
+    }
+    element
+}
+
+/// Element drawn for (a part of) a geometry.
+enum Shape {
+    Point(Circle),
+    Line(Path),
+    Polygon(Path),
+}
+
+/// CSS rules of the document, used when the features are styled with
+/// classes (`css-classes = true`) instead of presentation attributes.
+struct StyleSheet {
+    rules: Vec<String>,
+}
+
+impl StyleSheet {
+    fn new() -> Self {
+        StyleSheet { rules: Vec::new() }
+    }
+
+    fn add_rule(&mut self, selector: &str, declarations: &[(&str, &str)]) {
+        let declarations = declarations
+            .iter()
+            .map(|&(property, value)| format!("{}: {};", property, value))
+            .collect::<Vec<String>>();
+        self.rules.push(format!("{} {{ {} }}", selector, declarations.join(" ")));
+    }
+
+    /// Adds a rule with the given style properties, leaving out the data-driven
+    /// ones (which are set on each element).
+    fn add_style_rule(&mut self, selector: &str, properties: &[(&str, &StyleValue)]) {
+        let declarations = properties
+            .iter()
+            .filter_map(|&(property, value)| value.constant().map(|v| (property, v)))
+            .collect::<Vec<(&str, &str)>>();
+        self.add_rule(selector, &declarations);
+    }
+
+    fn to_css(&self) -> String {
+        self.rules.join("\n")
+    }
+}
+
+/// Name of the CSS class of a layer (its name, without the characters
+/// which aren't allowed in a class name). When the name has to be changed,
+/// a hash of the original name is appended, so that layers like "a b" and
+/// "a-b" still get different classes.
+fn css_class_name(layer_name: &str) -> String {
+    let mut class = layer_name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect::<String>();
+    if class.starts_with(|c: char| c.is_ascii_digit()) {
+        class = format!("layer-{}", class);
+    }
+    if class != layer_name {
+        // FNV-1a hash of the name:
+        let hash = layer_name
+            .bytes()
+            .fold(0x811c9dc5u32, |h, b| (h ^ b as u32).wrapping_mul(0x01000193));
+        class = format!("{}-{:08x}", class, hash);
+    }
+    class
 }
 
 // enum Representation {
@@ -142,43 +899,551 @@ impl<'a> Converter<'a> {
 //     Choropleth,
 // }
 
-struct Renderer {}
+struct Renderer {}
+
+impl Renderer {
+    fn render_graticule(converter: &Converter, reprojected_graticule: GeoJson) -> Group {
+        let features = match reprojected_graticule {
+            GeoJson::FeatureCollection(collection) => collection.features,
+            _ => {
+                println!("Expected a GeoJSON feature collection!");
+                std::process::exit(1);
+            }
+        };
+
+        let mut group = Group::new();
+        for feature in features {
+            let geom = feature.geometry.unwrap();
+            match geom.value {
+                Value::MultiLineString(lines) => {
+                    let mut data = Data::new();
+                    for positions in &lines {
+                        data = converter.draw_path_ring(&[positions.to_vec()], Some(data));
+                    }
+                    group.append(Path::new()
+                                     .set("fill", "none")
+                                     .set("stroke", "grey")
+                                     .set("stroke-dasharray", "5")
+                                     .set("d", data));
+                }
+                _ => {}
+            }
+        }
+        group
+    }
+
+    fn render_unicolor(converter: &Converter,
+                       decoded_geojson: GeoJson,
+                       prop: &SingleColorLayerProperties,
+                       styles: Option<(&str, &mut StyleSheet)>)
+                       -> Group {
+        let features = match decoded_geojson {
+            GeoJson::FeatureCollection(collection) => collection.features,
+            _ => {
+                println!("Expected a GeoJSON feature collection!");
+                std::process::exit(1)
+            }
+        };
+        let class = styles.map(|(class, stylesheet)| {
+                                   prop.add_css_rules(class, stylesheet);
+                                   class
+                               });
+
+        let mut group = Group::new();
+        for feature in features {
+            Renderer::unicolor_feature(converter, &feature, prop, class, &mut group);
+        }
+        group
+    }
+
+    /// Distorts the features of a cartogram layer (once projected, before the extent
+    /// of the map is computed) so that their areas become proportional to the values
+    /// of a field.
+    fn distort_cartogram(name: &str,
+                         decoded_geojson: GeoJson,
+                         options: &BTreeMap<String, toml::Value>)
+                         -> GeoJson {
+        let cartogram_options = match options.get("cartogram") {
+            Some(&toml::Value::Table(ref c)) => c,
+            _ => {
+                println!("A [{}.cartogram] section is needed by the \"cartogram\" representation!",
+                         name);
+                std::process::exit(1)
+            }
+        };
+        let field = match cartogram_options.get("field").and_then(|v| v.as_str()) {
+            Some(field) => field.to_string(),
+            None => {
+                println!("\"field\" (the name of a field) is required by a cartogram!");
+                std::process::exit(1)
+            }
+        };
+        let iterations = match cartogram_options.get("iterations") {
+            Some(&toml::Value::Integer(v)) if v >= 0 => v as u32,
+            Some(_) => {
+                println!("\"iterations\" should be a positive integer!");
+                std::process::exit(1)
+            }
+            None => 5,
+        };
+        let mut features = match decoded_geojson {
+            GeoJson::FeatureCollection(collection) => collection.features,
+            _ => {
+                println!("Expected a GeoJSON feature collection!");
+                std::process::exit(1)
+            }
+        };
+        let values = get_values(&features, &field)
+            .into_iter()
+            .map(|v| v.and_then(|v| if v >= 0.0 { Some(v) } else { None }))
+            .collect::<Vec<Option<f64>>>();
+        let nb_missing = values.iter().filter(|v| v.is_none()).count();
+        if nb_missing > 0 {
+            println!("Warning: {} feature(s) of layer \"{}\" without valid value of \"{}\" (keeping their area).",
+                     nb_missing,
+                     name,
+                     field);
+        }
+        let error = cartogram(&mut features, &values, iterations);
+        println!("Cartogram of layer \"{}\": mean area error of {:.1}% after {} iteration(s).",
+                 name,
+                 error * 100.0,
+                 iterations);
+        GeoJson::from(FeatureCollection {
+                          bbox: None,
+                          foreign_members: None,
+                          features: features,
+                      })
+    }
+
+    /// Renders a contiguous cartogram (whose features are already distorted) with a
+    /// single color or as a choropleth (`style = "choropleth"`, with the `choropleth`
+    /// section).
+    fn render_cartogram(converter: &Converter,
+                        name: &str,
+                        decoded_geojson: GeoJson,
+                        options: &BTreeMap<String, toml::Value>,
+                        styles: Option<(&str, &mut StyleSheet)>)
+                        -> (Group, Option<Legend>) {
+        match options["cartogram"].get("style").and_then(|v| v.as_str()) {
+            Some("unicolor") | None => {
+                let layer_properties = SingleColorLayerProperties::from_config(options);
+                (Renderer::render_unicolor(converter, decoded_geojson, &layer_properties, styles),
+                 None)
+            }
+            Some("choropleth") => {
+                let layer_properties = match options.get("choropleth") {
+                    Some(&toml::Value::Table(ref c)) => ChoroplethLayerProperties::from_config(c),
+                    _ => {
+                        println!("A [{}.choropleth] section is needed to style the cartogram as a choropleth!",
+                                 name);
+                        std::process::exit(1)
+                    }
+                };
+                let (group, legend) = Renderer::render_choropleth(converter,
+                                                                  name,
+                                                                  decoded_geojson,
+                                                                  &layer_properties,
+                                                                  styles);
+                (group, Some(legend))
+            }
+            Some(_) => {
+                println!("The \"style\" of a cartogram should be \"unicolor\" or \"choropleth\"!");
+                std::process::exit(1)
+            }
+        }
+    }
+
+    fn render_dotdensity(converter: &Converter,
+                         name: &str,
+                         decoded_geojson: GeoJson,
+                         prop: &DotDensityLayerProperties,
+                         styles: Option<(&str, &mut StyleSheet)>)
+                         -> Group {
+        let features = match decoded_geojson {
+            GeoJson::FeatureCollection(collection) => collection.features,
+            _ => {
+                println!("Expected a GeoJSON feature collection!");
+                std::process::exit(1)
+            }
+        };
+        let class = styles.map(|(class, stylesheet)| {
+                                   prop.add_css_rules(class, stylesheet);
+                                   class
+                               });
+        let mut rng = Rng::new(prop.seed);
+        let mut group = Group::new();
+        let mut nb_missing = 0;
+        for feature in &features {
+            nb_missing += Renderer::dotdensity_feature(converter,
+                                                       feature,
+                                                       prop,
+                                                       &mut rng,
+                                                       class,
+                                                       &mut group);
+        }
+        Renderer::report_missing_dots(name, nb_missing);
+        group
+    }
+
+    /// Draws the polygons of a feature, then its dots (the dots of all the fields
+    /// being drawn in random order, so that no field hides the others). Returns
+    /// the number of dots which couldn't be placed in the polygons.
+    fn dotdensity_feature(converter: &Converter,
+                          feature: &Feature,
+                          prop: &DotDensityLayerProperties,
+                          rng: &mut Rng,
+                          class: Option<&str>,
+                          group: &mut Group)
+                          -> usize {
+        let geom = match feature.geometry {
+            Some(ref geom) => geom,
+            None => return 0,
+        };
+        Renderer::unicolor_feature(converter, feature, &prop.outline, class, group);
+        let mut dots = Vec::new();
+        let mut nb_missing = 0;
+        for (ix, field) in prop.fields.iter().enumerate() {
+            let value = get_values(std::slice::from_ref(feature), field)[0];
+            let nb_dots = value.map_or(0.0, |v| (v / prop.dot_value).round().max(0.0));
+            if nb_dots > MAX_DOTS as f64 {
+                println!("The value {} of field \"{}\" would be drawn with more than {} dots, \
+                          use a larger \"dot-value\"!",
+                         value.unwrap(),
+                         field,
+                         MAX_DOTS);
+                std::process::exit(1)
+            }
+            let nb_dots = nb_dots as usize;
+            let points = random_points(&geom.value, nb_dots, rng);
+            nb_missing += nb_dots - points.len();
+            dots.extend(points.into_iter().map(|point| (ix, point)));
+        }
+        rng.shuffle(&mut dots);
+        let no_properties = Map::new();
+        let properties = feature.properties.as_ref().unwrap_or(&no_properties);
+        for (ix, point) in dots {
+            group.append(styled(converter.draw_point(&point),
+                                class.map(|c| format!("{0}-dot {0}-dot-{1}", c, ix)),
+                                &[("fill", &prop.colors[ix]),
+                                  ("fill-opacity", &prop.dot_opacity),
+                                  ("r", &prop.radius)],
+                                properties));
+        }
+        nb_missing
+    }
+
+    fn report_missing_dots(name: &str, nb_missing: usize) {
+        if nb_missing > 0 {
+            println!("Warning: {} dot(s) of layer \"{}\" couldn't be placed inside their polygon.",
+                     nb_missing,
+                     name);
+        }
+    }
+
+    /// Draws a feature of a single color layer, its style being given either by
+    /// presentation attributes or by the CSS `class` of the layer.
+    fn unicolor_feature(converter: &Converter,
+                        feature: &Feature,
+                        prop: &SingleColorLayerProperties,
+                        class: Option<&str>,
+                        group: &mut Group) {
+        let geom = match feature.geometry {
+            Some(ref geom) => geom,
+            None => return,
+        };
+        let no_properties = Map::new();
+        let properties = feature.properties.as_ref().unwrap_or(&no_properties);
+        let no_fill = StyleValue::Constant("none".to_string());
+        for shape in converter.draw_shapes(&geom.value) {
+            match shape {
+                Shape::Point(circle) => {
+                    group.append(styled(circle,
+                                        class.map(|c| format!("{} point", c)),
+                                        &[("fill", &prop.fill), ("r", &prop.radius)],
+                                        properties))
+                }
+                Shape::Line(path) => {
+                    group.append(styled(path,
+                                        class.map(|c| format!("{} line", c)),
+                                        &[("fill", &no_fill),
+                                          ("stroke", &prop.stroke),
+                                          ("stroke-width", &prop.stroke_width),
+                                          ("stroke-opacity", &prop.stroke_opacity)],
+                                        properties))
+                }
+                Shape::Polygon(path) => {
+                    group.append(styled(path,
+                                        class.map(|c| c.to_string()),
+                                        &[("fill", &prop.fill),
+                                          ("fill-opacity", &prop.fill_opacity),
+                                          ("stroke", &prop.stroke),
+                                          ("stroke-width", &prop.stroke_width),
+                                          ("stroke-opacity", &prop.stroke_opacity)],
+                                        properties))
+                }
+            }
+        }
+    }
+
+    /// Computes the class bounds of a choropleth and the color of each class, the
+    /// features without a valid value being left out of the classification.
+    fn choropleth_classes(name: &str,
+                          values: &[Option<f64>],
+                          prop: &ChoroplethLayerProperties)
+                          -> ChoroplethClasses {
+        let valid_values = values.iter().filter_map(|v| *v).collect::<Vec<f64>>();
+        let nb_no_data = values.len() - valid_values.len();
+        Renderer::report_no_data(name, &prop.value_title(), nb_no_data, values.len(), &prop.style);
+        if let Some(interpolation) = prop.interpolation {
+            let colors = Renderer::palette_colors(prop, prop.nb_class.unwrap_or(9) as usize);
+            let scale = ColorScale::new(&valid_values, &colors, interpolation)
+                .unwrap_or_else(|err| {
+                                    println!("{}", err);
+                                    std::process::exit(1)
+                                });
+            return ChoroplethClasses {
+                       bounds: Vec::new(),
+                       palette: Vec::new(),
+                       scale: Some(scale),
+                       no_data_fill: prop.style.no_data_fill(name),
+                       nb_no_data: nb_no_data,
+                   };
+        }
+        let bounds = match prop.breaks {
+            None if prop.center.is_some() => {
+                Renderer::diverging_bounds(name, &valid_values, prop.center.unwrap(), prop)
+            }
+            Some(ref breaks) => {
+                let (min, max) = (breaks[0], breaks[breaks.len() - 1]);
+                let nb_outside = valid_values.iter().filter(|&&v| v < min || v > max).count();
+                if nb_outside > 0 {
+                    println!("Warning: {} value(s) of layer \"{}\" outside of the breaks (put in the first or the last class).",
+                             nb_outside,
+                             name);
+                }
+                breaks.clone()
+            }
+            None => {
+                let nb_class = prop.nb_class
+                    .or_else(|| prop.colors.as_ref().map(|c| c.len() as u32))
+                    .unwrap_or_else(|| get_nb_class(valid_values.len()));
+                Renderer::classify(name, &valid_values, nb_class, &prop.type_classification)
+            }
+        };
+        let palette = Renderer::palette_colors(prop, bounds.len() - 1);
+        ChoroplethClasses {
+            bounds: bounds,
+            palette: palette,
+            scale: None,
+            no_data_fill: prop.style.no_data_fill(name),
+            nb_no_data: nb_no_data,
+        }
+    }
+
+    /// Colors of a choropleth: the given colors, or a ramp of `nb_class` colors of
+    /// the palette (reversed if requested).
+    fn palette_colors(prop: &ChoroplethLayerProperties, nb_class: usize) -> Vec<String> {
+        let mut palette = match prop.colors {
+            Some(ref colors) => colors.clone(),
+            None => {
+                let palette_name: colorbrewer::Palette = prop.palette_name
+                    .parse()
+                    .unwrap_or_else(|_| {
+                                        println!("Unexisting palette name!");
+                                        std::process::exit(1)
+                                    });
+                colorbrewer::get_color_ramp(palette_name, nb_class as u32)
+                    .unwrap_or_else(|| {
+                        println!("No {} colors ramp in the \"{}\" palette!", nb_class, prop.palette_name);
+                        std::process::exit(1)
+                    })
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect()
+            }
+        };
+        if prop.reverse_palette {
+            palette.reverse();
+        }
+        palette
+    }
+
+    /// Computes the bounds of the classes of a choropleth centered on a value: half
+    /// of the classes are computed from the values below the center, the other half
+    /// from the values above it, so that the center is the bound between the two
+    /// middle classes (and so between the two lightest colors of a diverging palette).
+    fn diverging_bounds(name: &str,
+                        values: &[f64],
+                        center: f64,
+                        prop: &ChoroplethLayerProperties)
+                        -> Vec<f64> {
+        const DIVERGING_PALETTES: [&'static str; 9] =
+            ["BrBG", "PiYG", "PRGn", "PuOr", "RdBu", "RdGy", "RdYlBu", "RdYlGn", "Spectral"];
+        if prop.colors.is_none() && !DIVERGING_PALETTES.contains(&prop.palette_name.as_str()) {
+            println!("Warning: \"{}\" isn't a diverging palette (like {}).",
+                     prop.palette_name,
+                     DIVERGING_PALETTES.join(", "));
+        }
+        let nb_class = prop.nb_class
+            .or_else(|| prop.colors.as_ref().map(|c| c.len() as u32))
+            .unwrap_or_else(|| (get_nb_class(values.len()) + 1) / 2 * 2);
+        let below = values.iter().cloned().filter(|&v| v < center).collect::<Vec<f64>>();
+        let above = values.iter().cloned().filter(|&v| v > center).collect::<Vec<f64>>();
+        if below.is_empty() || above.is_empty() {
+            println!("Layer \"{}\" needs values on both sides of the center ({}) to be centered on it!",
+                     name,
+                     center);
+            std::process::exit(1);
+        }
+        let half = nb_class / 2;
+        let lower = Renderer::classify(name, &below, half, &prop.type_classification);
+        let upper = Renderer::classify(name, &above, half, &prop.type_classification);
+        let mut bounds = lower[..lower.len() - 1].to_vec();
+        bounds.push(center);
+        bounds.extend_from_slice(&upper[1..]);
+        bounds
+    }
+
+    /// Computes the bounds of `nb_class` classes of the values, with the given
+    /// classification method.
+    fn classify(name: &str, values: &[f64], nb_class: u32, type_classification: &str) -> Vec<f64> {
+        let type_classif: Classification = type_classification
+            .parse::<Classification>()
+            .unwrap_or_else(|_| {
+                                println!("Invalid classification name!");
+                                std::process::exit(1)
+                            });
+        BoundsInfo::new(nb_class, values, type_classif)
+            .unwrap_or_else(|_| {
+                println!("Unable to compute {} classes from the {} value(s) of layer \"{}\"!",
+                         nb_class,
+                         values.len(),
+                         name);
+                std::process::exit(1)
+            })
+            .bounds
+    }
+
+    /// Warns about the features without a valid value (or stops if none has one).
+    fn report_no_data(name: &str,
+                      description: &str,
+                      nb_no_data: usize,
+                      nb_features: usize,
+                      style: &ClassedStyle) {
+        if nb_no_data == nb_features {
+            println!("No valid value of \"{}\" in layer \"{}\"!", description, name);
+            std::process::exit(1);
+        }
+        if nb_no_data > 0 {
+            println!("Warning: {} feature(s) of layer \"{}\" without valid value of \"{}\" (drawn as \"{}\").",
+                     nb_no_data,
+                     name,
+                     description,
+                     style.no_data_label);
+        }
+    }
+
+    /// Computes the class bounds of both fields of a bivariate layer (the features
+    /// missing one of the values being left out) and the colors of the classes.
+    fn bivariate_classes(name: &str,
+                         values: &[(Option<f64>, Option<f64>)],
+                         prop: &BivariateLayerProperties)
+                         -> BivariateClasses {
+        let valid_values = values
+            .iter()
+            .filter_map(|&(x, y)| match (x, y) {
+                            (Some(x), Some(y)) => Some((x, y)),
+                            _ => None,
+                        })
+            .collect::<Vec<(f64, f64)>>();
+        let nb_no_data = values.len() - valid_values.len();
+        Renderer::report_no_data(name,
+                                 &format!("{} and {}", prop.fields.0, prop.fields.1),
+                                 nb_no_data,
+                                 values.len(),
+                                 &prop.style);
+        let x_values = valid_values.iter().map(|v| v.0).collect::<Vec<f64>>();
+        let y_values = valid_values.iter().map(|v| v.1).collect::<Vec<f64>>();
+        let nb_class = prop.nb_class as u32;
+        let bounds = (Renderer::classify(name, &x_values, nb_class, &prop.type_classification),
+                      Renderer::classify(name, &y_values, nb_class, &prop.type_classification));
+        let palette = match prop.colors {
+            Some(ref colors) => colors.clone(),
+            None => {
+                bivariate_palette(&prop.palette_name, prop.nb_class).unwrap_or_else(|| {
+                    println!("Unexisting bivariate palette name: {} (expected \"TealPink\", \"GreenBlue\" or \"RedBlue\")",
+                             prop.palette_name);
+                    std::process::exit(1)
+                })
+            }
+        };
+        BivariateClasses {
+            bounds: bounds,
+            palette: palette,
+            no_data_fill: prop.style.no_data_fill(name),
+            nb_no_data: nb_no_data,
+        }
+    }
+
+    /// Adds the definitions and the CSS rules used by the features of a choropleth
+    /// (or bivariate) layer, returning its CSS class (if styled with classes).
+    fn choropleth_styles<'a>(name: &str,
+                             palette: &[String],
+                             (no_data_fill, nb_no_data): (&str, usize),
+                             style: &ClassedStyle,
+                             styles: Option<(&'a str, &mut StyleSheet)>,
+                             group: &mut Group)
+                             -> Option<&'a str> {
+        if style.no_data_hatch && nb_no_data > 0 {
+            group.append(Definitions::new().add(style.no_data_pattern(name)));
+        }
+        styles.map(|(class, stylesheet)| {
+                       style.add_css_rules(class, palette, no_data_fill, stylesheet);
+                       class
+                   })
+    }
 
-impl Renderer {
-    fn render_graticule(converter: &Converter, reprojected_graticule: GeoJson) -> Group {
-        let features = match reprojected_graticule {
+    fn render_choropleth(converter: &Converter,
+                         name: &str,
+                         decoded_geojson: GeoJson,
+                         prop: &ChoroplethLayerProperties,
+                         styles: Option<(&str, &mut StyleSheet)>)
+                         -> (Group, Legend) {
+        let features = match decoded_geojson {
             GeoJson::FeatureCollection(collection) => collection.features,
             _ => {
                 println!("Expected a GeoJSON feature collection!");
-                std::process::exit(1);
+                std::process::exit(1)
             }
         };
-
+        let values = prop.values(&features);
+        let classes = Renderer::choropleth_classes(name, &values, prop);
         let mut group = Group::new();
-        for feature in features {
-            let geom = feature.geometry.unwrap();
-            match geom.value {
-                Value::MultiLineString(lines) => {
-                    let mut data = Data::new();
-                    for positions in &lines {
-                        data = converter.draw_path_ring(&[positions.to_vec()], Some(data));
-                    }
-                    group.append(Path::new()
-                                     .set("fill", "none")
-                                     .set("stroke", "grey")
-                                     .set("stroke-dasharray", "5")
-                                     .set("d", data));
-                }
-                _ => {}
-            }
+        let class = Renderer::choropleth_styles(name,
+                                                &classes.palette,
+                                                (&classes.no_data_fill, classes.nb_no_data),
+                                                &prop.style,
+                                                styles,
+                                                &mut group);
+        for (feature, value) in features.iter().zip(values) {
+            let (suffix, color) = classes.style_of(value);
+            Renderer::choropleth_feature(converter,
+                                         feature,
+                                         (suffix.as_ref().map(|s| s.as_str()), &color),
+                                         &prop.style,
+                                         class,
+                                         &mut group);
         }
-        group
+        (group, classes.legend(name, prop))
     }
 
-    fn render_unicolor(converter: &Converter,
-                       decoded_geojson: GeoJson,
-                       prop: &SingleColorLayerProperties)
-                       -> Group {
+    fn render_bivariate(converter: &Converter,
+                        name: &str,
+                        decoded_geojson: GeoJson,
+                        prop: &BivariateLayerProperties,
+                        styles: Option<(&str, &mut StyleSheet)>)
+                        -> (Group, Legend) {
         let features = match decoded_geojson {
             GeoJson::FeatureCollection(collection) => collection.features,
             _ => {
@@ -186,179 +1451,424 @@ impl Renderer {
                 std::process::exit(1)
             }
         };
-
+        let values = get_values(&features, &prop.fields.0)
+            .into_iter()
+            .zip(get_values(&features, &prop.fields.1))
+            .collect::<Vec<(Option<f64>, Option<f64>)>>();
+        let classes = Renderer::bivariate_classes(name, &values, prop);
         let mut group = Group::new();
-        for feature in features {
-            let geom = feature.geometry.unwrap();
-            match geom.value {
-                Value::Point(point) => {
-                    let circle = converter.draw_point(&point);
-                    group.append(circle
-                                     .set("fill", prop.fill.clone())
-                                     .set("r", prop.radius.clone()))
-                }
-                Value::MultiPoint(points) => {
-                    for point in &points {
-                        let circle = converter.draw_point(&point);
-                        group.append(circle
-                                         .set("fill", prop.fill.clone())
-                                         .set("r", prop.radius.clone()))
+        let class = Renderer::choropleth_styles(name,
+                                                &classes.palette,
+                                                (&classes.no_data_fill, classes.nb_no_data),
+                                                &prop.style,
+                                                styles,
+                                                &mut group);
+        for (feature, value) in features.iter().zip(values) {
+            let (suffix, color) = classes.style_of(value);
+            Renderer::choropleth_feature(converter,
+                                         feature,
+                                         (Some(&suffix), color),
+                                         &prop.style,
+                                         class,
+                                         &mut group);
+        }
+        (group, classes.legend(prop))
+    }
+
+    /// Draws a feature of a choropleth (or bivariate) layer, given its class (the
+    /// suffix of its class name and its color), its style being given either by
+    /// presentation attributes or by CSS classes. Without a class suffix (in an
+    /// unclassed choropleth), the color is set on the element even with CSS classes.
+    fn choropleth_feature(converter: &Converter,
+                          feature: &Feature,
+                          (class_suffix, color): (Option<&str>, &str),
+                          prop: &ClassedStyle,
+                          class: Option<&str>,
+                          group: &mut Group) {
+        let geom = match feature.geometry {
+            Some(ref geom) => geom,
+            None => return,
+        };
+        let no_properties = Map::new();
+        let properties = feature.properties.as_ref().unwrap_or(&no_properties);
+        let class_name = |kind: &str| {
+            class.map(|c| match class_suffix {
+                          Some(suffix) => format!("{0} {0}-{1}{2}", c, suffix, kind),
+                          None => format!("{}{}", c, kind),
+                      })
+        };
+        let inline_color = if class.is_some() && class_suffix.is_none() {
+            Some(color)
+        } else {
+            None
+        };
+        let no_fill = StyleValue::Constant("none".to_string());
+        let color = StyleValue::Constant(color.to_string());
+        for shape in converter.draw_shapes(&geom.value) {
+            match shape {
+                Shape::Point(circle) => {
+                    let mut circle = styled(circle,
+                                            class_name(" point"),
+                                            &[("fill", &color), ("r", &prop.radius)],
+                                            properties);
+                    if let Some(color) = inline_color {
+                        circle.assign("fill", color);
                     }
+                    group.append(circle)
                 }
-                Value::LineString(positions) => {
-                    let data = Data::new();
-                    group.append(Path::new()
-                                     .set("fill", "none")
-                                     .set("stroke", prop.stroke.clone())
-                                     .set("stroke-width", prop.stroke_width.clone())
-                                     .set("stroke-opacity", prop.stroke_opacity.clone())
-                                     .set("d",
-                                          converter.draw_path_ring(&[positions], Some(data))));
-                }
-                Value::MultiLineString(lines) => {
-                    let mut data = Data::new();
-                    for positions in &lines {
-                        data = converter.draw_path_ring(&[positions.to_vec()], Some(data));
+                Shape::Line(path) => {
+                    let mut path = styled(path,
+                                          class_name(" line"),
+                                          &[("fill", &no_fill),
+                                            ("stroke", &color),
+                                            ("stroke-width", &prop.stroke_width),
+                                            ("stroke-opacity", &prop.stroke_opacity)],
+                                          properties);
+                    if let Some(color) = inline_color {
+                        path.assign("stroke", color);
                     }
-                    group.append(Path::new()
-                                     .set("fill", "none")
-                                     .set("stroke", prop.stroke.clone())
-                                     .set("stroke-width", prop.stroke_width.clone())
-                                     .set("stroke-opacity", prop.stroke_opacity.clone())
-                                     .set("d", data));
+                    group.append(path)
                 }
-                Value::Polygon(positions) => {
-                    group.append(Path::new()
-                                     .set("fill", prop.fill.clone())
-                                     .set("fill-opacity", prop.fill_opacity.clone())
-                                     .set("stroke", prop.stroke.clone())
-                                     .set("stroke-width", prop.stroke_width.clone())
-                                     .set("stroke-opacity", prop.stroke_opacity.clone())
-                                     .set("d", converter.draw_path_ring(&positions, None)));
-                }
-                Value::MultiPolygon(polys) => {
-                    let mut data = Data::new();
-                    for positions in &polys {
-                        data = converter.draw_path_ring(positions, Some(data));
+                Shape::Polygon(path) => {
+                    let mut path = styled(path,
+                                          class_name(""),
+                                          &[("fill", &color),
+                                            ("fill-opacity", &prop.fill_opacity),
+                                            ("stroke", &prop.stroke),
+                                            ("stroke-width", &prop.stroke_width),
+                                            ("stroke-opacity", &prop.stroke_opacity)],
+                                          properties);
+                    if let Some(color) = inline_color {
+                        path.assign("fill", color);
                     }
-                    data = data.close();
-                    group.append(Path::new()
-                                     .set("fill", prop.fill.clone())
-                                     .set("fill-opacity", prop.fill_opacity.clone())
-                                     .set("stroke", prop.stroke.clone())
-                                     .set("stroke-width", prop.stroke_width.clone())
-                                     .set("stroke-opacity", prop.stroke_opacity.clone())
-                                     .set("d", data));
+                    group.append(path)
                 }
-                _ => panic!("I don't handle GeometryCollection yet!!"),
             }
         }
-        group
     }
-    fn render_choropleth(converter: &Converter,
-                         decoded_geojson: GeoJson,
-                         prop: &ChoroplethLayerProperties)
-                         -> Group {
-        let features = match decoded_geojson {
-            GeoJson::FeatureCollection(collection) => collection.features,
-            _ => panic!("Error: expected a Feature collection!"),
+
+    /// Renders a layer read in streaming mode: the features are read, filtered on
+    /// the visible extent, reprojected and drawn one at a time. A choropleth (or a
+    /// bivariate choropleth) needs two passes over the file (one to classify the
+    /// values, one to draw), which share the pipeline of the layer.
+    fn render_streamed(converter: &Converter,
+                       name: &str,
+                       path: &str,
+                       projs: &Option<(Proj, Proj)>,
+                       options: Option<&BTreeMap<String, toml::Value>>,
+                       pipeline: &mut FeaturePipeline,
+                       styles: Option<(&str, &mut StyleSheet)>)
+                       -> (Group, Option<Legend>) {
+        let visible_extent = converter.visible_extent();
+        let is_visible = |feature: &Feature| match feature.geometry {
+            Some(ref geom) => {
+                let mut extent = MapExtent::empty();
+                extend_extent(&geom.value, &mut extent);
+                !extent.is_empty() && extent.intersects(&visible_extent)
+            }
+            None => false,
         };
-        let values = get_values(&features, &prop.field_name);
-        let nb_class = get_nb_class(values.len());
-        let type_classif: Classification = prop.type_classification
-            .parse::<Classification>()
-            .unwrap_or_else(|_| {
-                                println!("Invalid classification name!");
-                                std::process::exit(1)
-                            });
-        let palette_name: colorbrewer::Palette = prop.palette_name
-            .parse()
-            .unwrap_or_else(|_| {
-                                println!("Unexisting palette name!");
-                                std::process::exit(1)
-                            });
-        let classifier = BoundsInfo::new(nb_class, &values, type_classif).unwrap();
-        let palette = colorbrewer::get_color_ramp(palette_name, nb_class).unwrap();
         let mut group = Group::new();
-        // for (ix, feature) in features.iter().enumerate() {
-        features
-            .iter()
-            .enumerate()
-            .map(|(ix, ref feature)| {
-                if let Some(ref geom) = feature.geometry {
-                    let value = values[ix];
-                    let color = palette[classifier.get_class_index(value).unwrap() as usize];
-                    match geom.value {
-                        Value::Point(ref point) => {
-                            let circle = converter.draw_point(&point);
-                            group.append(circle.set("fill", color).set("r", prop.radius.clone()))
-                        }
-                        Value::MultiPoint(ref points) => {
-                            for point in points {
-                                let circle = converter.draw_point(&point);
-                                group
-                                    .append(circle.set("fill", color).set("r", prop.radius.clone()))
-                            }
-                        }
-                        Value::LineString(ref positions) => {
-                            let data = Data::new();
-                            group.append(Path::new()
-                                             .set("fill", "none")
-                                             .set("stroke", color)
-                                             .set("stroke-width", prop.stroke_width.clone())
-                                             .set("stroke-opacity",
-                                                  prop.stroke_opacity.clone())
-                                             .set("d",
-                                                  converter
-                                                      .draw_path_ring(&[positions.to_vec()],
-                                                                      Some(data))));
-                        }
-                        Value::MultiLineString(ref lines) => {
-                            let mut data = Data::new();
-                            for positions in lines {
-                                data = converter.draw_path_ring(&[positions.to_vec()], Some(data));
-                            }
-                            group.append(Path::new()
-                                             .set("fill", "none")
-                                             .set("stroke", color)
-                                             .set("stroke-width", prop.stroke_width.clone())
-                                             .set("stroke-opacity",
-                                                  prop.stroke_opacity.clone())
-                                             .set("d", data));
-                        }
-                        Value::Polygon(ref positions) => {
-                            group.append(Path::new()
-                                             .set("fill", color)
-                                             .set("fill-opacity", prop.fill_opacity.clone())
-                                             .set("stroke", prop.stroke.clone())
-                                             .set("stroke-width", prop.stroke_width.clone())
-                                             .set("stroke-opacity",
-                                                  prop.stroke_opacity.clone())
-                                             .set("d",
-                                                  converter.draw_path_ring(&positions, None)));
-                        }
-                        Value::MultiPolygon(ref polys) => {
-                            let mut data = Data::new();
-                            for positions in polys {
-                                data = converter.draw_path_ring(positions, Some(data));
-                            }
-                            data = data.close();
-                            group.append(Path::new()
-                                             .set("fill", color)
-                                             .set("fill-opacity", prop.fill_opacity.clone())
-                                             .set("stroke", prop.stroke.clone())
-                                             .set("stroke-width", prop.stroke_width.clone())
-                                             .set("stroke-opacity",
-                                                  prop.stroke_opacity.clone())
-                                             .set("d", data));
-                        }
-                        _ => panic!("I don't handle GeometryCollection yet!!"),
+        let mut legend = None;
+        match options.and_then(|o| o.get("representation")) {
+            None => {
+                let layer_properties = match options {
+                    Some(o) => SingleColorLayerProperties::from_config(o),
+                    None => SingleColorLayerProperties::default(),
+                };
+                let class = styles.map(|(class, stylesheet)| {
+                                           layer_properties.add_css_rules(class, stylesheet);
+                                           class
+                                       });
+                for_each_streamed_feature(path, projs, pipeline, |feature| {
+                    if is_visible(&feature) {
+                        Renderer::unicolor_feature(converter,
+                                                   &feature,
+                                                   &layer_properties,
+                                                   class,
+                                                   &mut group);
+                    }
+                });
+            }
+            Some(&toml::Value::String(ref type_name)) if type_name == "choropleth" => {
+                let layer_properties =
+                    ChoroplethLayerProperties::from_config(options.unwrap()[type_name]
+                                                               .as_table()
+                                                               .unwrap());
+                let mut values = Vec::new();
+                for_each_streamed_feature(path, &None, pipeline, |feature| {
+                    values.extend(layer_properties.values(&[feature]));
+                });
+                let classes = Renderer::choropleth_classes(name, &values, &layer_properties);
+                let class = Renderer::choropleth_styles(name,
+                                                        &classes.palette,
+                                                        (&classes.no_data_fill,
+                                                         classes.nb_no_data),
+                                                        &layer_properties.style,
+                                                        styles,
+                                                        &mut group);
+                let mut values = values.into_iter();
+                for_each_streamed_feature(path, projs, pipeline, |feature| {
+                    let value = values.next().unwrap_or_else(|| layer_changed(name));
+                    if is_visible(&feature) {
+                        let (suffix, color) = classes.style_of(value);
+                        Renderer::choropleth_feature(converter,
+                                                     &feature,
+                                                     (suffix.as_ref().map(|s| s.as_str()), &color),
+                                                     &layer_properties.style,
+                                                     class,
+                                                     &mut group);
                     }
+                });
+                if values.next().is_some() {
+                    layer_changed(name);
                 }
-                // }
-            })
-            .collect::<Vec<_>>();
-        group
+                legend = Some(classes.legend(name, &layer_properties));
+            }
+            Some(&toml::Value::String(ref type_name)) if type_name == "dotdensity" => {
+                let layer_properties =
+                    DotDensityLayerProperties::from_config(options.unwrap()[type_name]
+                                                               .as_table()
+                                                               .unwrap());
+                let class = styles.map(|(class, stylesheet)| {
+                                           layer_properties.add_css_rules(class, stylesheet);
+                                           class
+                                       });
+                let mut rng = Rng::new(layer_properties.seed);
+                let mut nb_missing = 0;
+                for_each_streamed_feature(path, projs, pipeline, |feature| {
+                    if is_visible(&feature) {
+                        nb_missing += Renderer::dotdensity_feature(converter,
+                                                                   &feature,
+                                                                   &layer_properties,
+                                                                   &mut rng,
+                                                                   class,
+                                                                   &mut group);
+                    }
+                });
+                Renderer::report_missing_dots(name, nb_missing);
+            }
+            Some(&toml::Value::String(ref type_name)) if type_name == "bivariate" => {
+                let layer_properties =
+                    BivariateLayerProperties::from_config(options.unwrap()[type_name]
+                                                              .as_table()
+                                                              .unwrap());
+                let mut values = Vec::new();
+                for_each_streamed_feature(path, &None, pipeline, |feature| {
+                    let feature = [feature];
+                    values.push((get_values(&feature, &layer_properties.fields.0)[0],
+                                 get_values(&feature, &layer_properties.fields.1)[0]));
+                });
+                let classes = Renderer::bivariate_classes(name, &values, &layer_properties);
+                let class = Renderer::choropleth_styles(name,
+                                                        &classes.palette,
+                                                        (&classes.no_data_fill,
+                                                         classes.nb_no_data),
+                                                        &layer_properties.style,
+                                                        styles,
+                                                        &mut group);
+                let mut values = values.into_iter();
+                for_each_streamed_feature(path, projs, pipeline, |feature| {
+                    let value = values.next().unwrap_or_else(|| layer_changed(name));
+                    if is_visible(&feature) {
+                        let (suffix, color) = classes.style_of(value);
+                        Renderer::choropleth_feature(converter,
+                                                     &feature,
+                                                     (Some(&suffix), color),
+                                                     &layer_properties.style,
+                                                     class,
+                                                     &mut group);
+                    }
+                });
+                if values.next().is_some() {
+                    layer_changed(name);
+                }
+                legend = Some(classes.legend(&layer_properties));
+            }
+            Some(&toml::Value::String(ref type_name)) if type_name == "cartogram" => {
+                println!("Layer \"{}\" can't be drawn as a cartogram in streaming mode (all its features are needed)!",
+                         name);
+                std::process::exit(1)
+            }
+            Some(value) => invalid_representation(name, value),
+        }
+        pipeline.report(name);
+        (group, legend)
+    }
+}
+
+/// Attribute join and filter applied to the features of a layer as soon as they
+/// are read (so before the computation of the extent, the classification and the
+/// rendering), configured by the `join` section and the `filter` key of the layer.
+/// The geodesic area of the features is also computed there (on their source
+/// geometry) when a choropleth maps a density.
+struct FeaturePipeline {
+    join: Option<JoinTable>,
+    filter: Option<Expr>,
+    /// Geographic coordinate system, used to compute the areas (if needed).
+    lonlat: Option<Proj>,
+}
+
+impl FeaturePipeline {
+    fn from_config(name: &str, options: Option<&BTreeMap<String, toml::Value>>) -> Self {
+        let join = match options.and_then(|o| o.get("join")) {
+            Some(&toml::Value::Table(ref join_options)) => {
+                Some(JoinTable::from_config(join_options).unwrap_or_else(|err| {
+                    println!("Unable to join a table to layer \"{}\": {}", name, err);
+                    std::process::exit(1)
+                }))
+            }
+            Some(_) => {
+                println!("\"join\" should be a section (like [{}.join])!", name);
+                std::process::exit(1)
+            }
+            None => None,
+        };
+        let filter = match options.and_then(|o| o.get("filter")) {
+            Some(&toml::Value::String(ref filter)) => {
+                Some(filter.parse::<Expr>().unwrap_or_else(|err| {
+                    println!("Invalid filter for layer \"{}\": {}", name, err);
+                    std::process::exit(1)
+                }))
+            }
+            Some(_) => {
+                println!("\"filter\" should be a string!");
+                std::process::exit(1)
+            }
+            None => None,
+        };
+        let density = options
+            .and_then(|o| o.get("choropleth"))
+            .and_then(|c| c.get("normalize"))
+            .and_then(|n| n.as_str()) == Some("density");
+        let is_choropleth = match options
+                  .and_then(|o| o.get("representation"))
+                  .and_then(|r| r.as_str()) {
+            Some("choropleth") | Some("cartogram") => true,
+            _ => false,
+        };
+        FeaturePipeline {
+            join: join,
+            filter: filter,
+            lonlat: if density && is_choropleth {
+                Some(Proj::new("+proj=longlat +ellps=WGS84 +datum=WGS84 +no_defs").unwrap())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Joins the feature to its record and computes its area (if needed), then
+    /// returns `true` if it's kept by the filter. `crs` is the coordinate system
+    /// of the layer, if it isn't in longitude / latitude.
+    fn accept(&mut self, feature: &mut Feature, crs: Option<&Proj>) -> bool {
+        if let Some(ref mut join) = self.join {
+            join.join(feature);
+        }
+        if let Some(ref lonlat) = self.lonlat {
+            let area = match (feature.geometry.as_ref(), crs) {
+                (Some(geom), Some(crs)) => {
+                    geodesic_area(&unproject_value(geom.value.clone(), crs, lonlat))
+                }
+                (Some(geom), None) => geodesic_area(&geom.value),
+                (None, _) => 0.0,
+            };
+            feature.properties
+                .get_or_insert_with(Map::new)
+                .insert(AREA_PROPERTY.to_string(), JsonValue::from(area));
+        }
+        match (self.filter.as_ref(), feature.properties.as_ref()) {
+            (Some(filter), Some(properties)) => filter.matches(properties),
+            (Some(filter), None) => filter.matches(&Map::new()),
+            (None, _) => true,
+        }
+    }
+
+    /// Applies the pipeline to every feature of a layer.
+    fn apply(&mut self, geojson: GeoJson, crs: Option<&Proj>) -> GeoJson {
+        match geojson {
+            GeoJson::FeatureCollection(mut collection) => {
+                collection.features = collection.features
+                    .drain(..)
+                    .filter_map(|mut feature| if self.accept(&mut feature, crs) {
+                                    Some(feature)
+                                } else {
+                                    None
+                                })
+                    .collect();
+                GeoJson::FeatureCollection(collection)
+            }
+            other => other,
+        }
+    }
+
+    fn report(&self, name: &str) {
+        if let Some(ref join) = self.join {
+            join.report(name);
+        }
+    }
+}
+
+/// Calls `f` on each feature of a layer read in streaming mode which is kept by
+/// the pipeline of the layer, once reprojected.
+fn for_each_streamed_feature<F: FnMut(Feature)>(path: &str,
+                                                projs: &Option<(Proj, Proj)>,
+                                                pipeline: &mut FeaturePipeline,
+                                                mut f: F) {
+    let features = stream_layer(path).unwrap_or_else(|err| {
+        println!("Unable to read layer at path: \"{}\"\nError: {}", path, err);
+        std::process::exit(1)
+    });
+    for feature in features {
+        let mut feature = feature.unwrap_or_else(|err| {
+            println!("Unable to read layer at path: \"{}\"\nError: {}", path, err);
+            std::process::exit(1)
+        });
+        if !pipeline.accept(&mut feature, None) {
+            continue;
+        }
+        match *projs {
+            Some((ref input_proj, ref output_proj)) => {
+                f(reproj_feature(feature, &input_proj, &output_proj, true))
+            }
+            None => f(feature),
+        }
+    }
+}
+
+/// Values of the `representation` key of a layer.
+const REPRESENTATIONS: [&'static str; 4] = ["choropleth", "bivariate", "cartogram", "dotdensity"];
+
+/// Exits with an error message when the `representation` of a layer is unknown.
+fn invalid_representation(name: &str, value: &toml::Value) -> ! {
+    println!("Invalid \"representation\" for layer \"{}\": {} (expected one of: {})",
+             name,
+             value,
+             REPRESENTATIONS.join(", "));
+    std::process::exit(1)
+}
+
+/// Exits with an error message when a layer read in streaming mode doesn't yield
+/// the same features on each pass over its file.
+fn layer_changed(name: &str) -> ! {
+    println!("Layer \"{}\" changed while being read!", name);
+    std::process::exit(1)
+}
+
+/// Reads the coordinates of a bbox of the configuration (integers or floats),
+/// exiting with an error message if one of them isn't a number.
+fn expect_bbox(bbox: &[toml::Value], name: &str) -> MapExtent {
+    let coord = |ix: usize| {
+        as_number(&bbox[ix]).unwrap_or_else(|| {
+                                                println!("The values of \"{}\" should be numbers!", name);
+                                                std::process::exit(1)
+                                            })
+    };
+    MapExtent {
+        left: coord(0),
+        right: coord(1),
+        bottom: coord(2),
+        top: coord(3),
     }
 }
 
@@ -371,6 +1881,11 @@ fn main() {
                  .required(true)
                  .value_name("CONFIG_FILE")
                  .help("Input configuration file to use (.toml)."))
+        .arg(Arg::with_name("format")
+                 .long("format")
+                 .takes_value(true)
+                 .possible_values(&["svg", "png", "pdf"])
+                 .help("Format of the output file (guessed from its extension by default)."))
         .get_matches();
     let file_path = StdPath::new(matches.value_of("input").unwrap());
     if !file_path.exists() || !file_path.is_file() {
@@ -415,46 +1930,317 @@ fn main() {
 
     // Fetch the list of layers to be rendered:
     let layers = config_options_table["map"]["layers"].as_array().unwrap();
-    let geojson_layers = layers
+    let layer_paths = layers
         .iter()
         .map(|input_layer| {
-            let path = input_layer.as_str().unwrap();
-            let name = path.split(".geojson").collect::<Vec<&str>>()[0];
-            let mut file = File::open(path).unwrap_or_else(|err| {
-                println!("Unable to open layer at path: \"{}\"\nError: {}", path, err);
-                std::process::exit(1)
-            });
-            let mut raw_json = String::new();
-            file.read_to_string(&mut raw_json).unwrap();
-            let mut decoded_geojson = raw_json.parse::<GeoJson>().unwrap();
-            if let Some((ref input_proj, ref output_proj)) = projs {
-                decoded_geojson = reproj(&mut decoded_geojson, &input_proj, &output_proj);
-            };
-            (name, decoded_geojson)
-        })
-        .collect::<Vec<(&str, geojson::GeoJson)>>();
+                 let path = input_layer.as_str().unwrap();
+                 (layer_name(path), path)
+             })
+        .collect::<Vec<(&str, &str)>>();
 
-    let map_extent = if let toml::Value::String(ref layer_name) = config_options["map"]["extent"] {
-        let mut extent: MapExtent = Default::default();
-        geojson_layers
-            .iter()
-            .map(|a| if &a.0 == layer_name {
-                     extent = get_extent(&a.1);
-                 })
-            .collect::<Vec<_>>();
-        extent
+    // Layers read in streaming mode (never fully loaded in memory):
+    let streamed_layer_names = layer_paths
+        .iter()
+        .filter(|&&(name, _)| {
+                    config_options_table
+                        .get(name)
+                        .and_then(|t| t.get("streaming"))
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(false)
+                })
+        .map(|&(name, path)| {
+                 if !is_streamable(path) {
+                     println!("Layer \"{}\" can't be read in streaming mode (only GeoJSON and newline-delimited GeoJSON layers can)!",
+                              name);
+                     std::process::exit(1)
+                 }
+                 name
+             })
+        .collect::<Vec<&str>>();
+    // Their join table is read once, their features being read several times (to
+    // compute the extent, to classify them and to draw them):
+    let mut streamed_pipelines = streamed_layer_names
+        .iter()
+        .map(|&name| {
+                 (name,
+                  FeaturePipeline::from_config(name,
+                                               config_options_table
+                                                   .get(name)
+                                                   .and_then(|t| t.as_table())))
+             })
+        .collect::<BTreeMap<&str, FeaturePipeline>>();
+
+    // Names of the layers used to compute the extent of the map:
+    let extent_layer_names: Vec<&str> = if config_options_table["map"].contains_key("center") ||
+                                           config_options_table["map"]
+                                               .contains_key("extent-lonlat") {
+        Vec::new()
     } else {
-        MapExtent {
-            left: expect_float!(config_options["map"]["extent"][0], "extent"),
-            right: expect_float!(config_options["map"]["extent"][1], "extent"),
-            bottom: expect_float!(config_options["map"]["extent"][2], "extent"),
-            top: expect_float!(config_options["map"]["extent"][3], "extent"),
+        match config_options_table["map"].get("extent") {
+            Some(&toml::Value::String(ref layer_name)) if layer_name != "all" => {
+                vec![layer_name.as_str()]
+            }
+            Some(&toml::Value::String(_)) | None => layer_paths.iter().map(|a| a.0).collect(),
+            Some(&toml::Value::Array(ref names)) if names.len() > 0 && names[0].is_str() => {
+                names
+                    .iter()
+                    .map(|n| {
+                             n.as_str().unwrap_or_else(|| {
+                            println!("\"extent\" should be a list of layer names or a list of 4 coordinates!");
+                            std::process::exit(1)
+                        })
+                         })
+                    .collect()
+            }
+            Some(&toml::Value::Array(_)) => Vec::new(),
+            Some(_) => {
+                println!("Invalid \"extent\" value!");
+                std::process::exit(1)
+            }
+        }
+    };
+
+    let load_layer = |name: &str, path: &str, filter: Option<&BboxFilter>| {
+        // The whole layer of a cartogram is needed to distort it, so it's never
+        // filtered on the extent of the map:
+        let is_cartogram = config_options_table
+            .get(name)
+            .and_then(|t| t.get("representation"))
+            .and_then(|r| r.as_str()) == Some("cartogram");
+        let filter = if is_cartogram { None } else { filter };
+        let layer = read_layer(path,
+                               config_options_table.get(name).and_then(|t| t.as_table()),
+                               filter)
+                .unwrap_or_else(|err| {
+                                    println!("Unable to read layer at path: \"{}\"\nError: {}",
+                                             path,
+                                             err);
+                                    std::process::exit(1)
+                                });
+        let mut decoded_geojson = layer.geojson;
+        let mut borders = layer.borders;
+        // Join the attributes table and filter the features (if requested):
+        let mut pipeline = FeaturePipeline::from_config(name,
+                                                        config_options_table
+                                                            .get(name)
+                                                            .and_then(|t| t.as_table()));
+        let layer_proj = layer.crs.as_ref().map(|crs| {
+            Proj::new(crs).unwrap_or_else(|| {
+                println!("Invalid coordinate system for layer \"{}\": {}", name, crs);
+                std::process::exit(1)
+            })
+        });
+        decoded_geojson = pipeline.apply(decoded_geojson, layer_proj.as_ref());
+        pipeline.report(name);
+        if let Some((ref input_proj, ref output_proj)) = projs {
+            let (input_proj, input_latlong) = match layer_proj {
+                Some(ref p) => (p, false),
+                None => (input_proj, true),
+            };
+            decoded_geojson = reproj(&mut decoded_geojson, &input_proj, &output_proj, input_latlong);
+            borders = borders.map(|mut b| reproj(&mut b, &input_proj, &output_proj, input_latlong));
+        } else if let Some(ref layer_proj) = layer_proj {
+            // Without any projection, the map is drawn in longitude / latitude:
+            let lonlat = Proj::new("+proj=longlat +ellps=WGS84 +datum=WGS84 +no_defs").unwrap();
+            decoded_geojson = unproject(&mut decoded_geojson, layer_proj, &lonlat);
+            borders = borders.map(|mut b| unproject(&mut b, layer_proj, &lonlat));
         }
+        // The features of a cartogram are distorted before being used for the extent:
+        if is_cartogram {
+            decoded_geojson = Renderer::distort_cartogram(name,
+                                                          decoded_geojson,
+                                                          config_options_table[name].as_table().unwrap());
+        }
+        (decoded_geojson, borders)
     };
 
+    // Layers read through a spatial index are only read once the extent of the
+    // map is known (unless they are needed to compute this extent):
+    let mut geojson_layers = layer_paths
+        .iter()
+        .filter(|&&(name, path)| {
+                    !streamed_layer_names.contains(&name) &&
+                    (!is_indexed(path) || extent_layer_names.contains(&name))
+                })
+        .map(|&(name, path)| {
+                 let (decoded_geojson, borders) = load_layer(name, path, None);
+                 (name, decoded_geojson, borders)
+             })
+        .collect::<Vec<(&str, geojson::GeoJson, Option<geojson::GeoJson>)>>();
+
     let width: u32 = config_options["map"]["width"].as_integer().unwrap() as u32;
-    let height: u32 = config_options["map"]["height"].as_integer().unwrap() as u32;
-    let converter = Converter::new(width, height, &map_extent);
+
+    let fit_mode = match config_options_table["map"].get("fit") {
+        Some(&toml::Value::String(ref name)) => {
+            name.parse::<FitMode>()
+                .unwrap_or_else(|_| {
+                                    println!("Invalid \"fit\" value (expected \"contain\", \"cover\" or \"stretch\")!");
+                                    std::process::exit(1)
+                                })
+        }
+        Some(_) => {
+            println!("\"fit\" should be a string!");
+            std::process::exit(1)
+        }
+        None => FitMode::Contain,
+    };
+
+    // The map can be framed by a center and a scale (or a zoom level) instead
+    // of an extent:
+    let converter = if let Some(center_value) = config_options_table["map"].get("center") {
+        if config_options_table["map"].contains_key("extent") ||
+           config_options_table["map"].contains_key("extent-lonlat") {
+            println!("\"center\" can't be used together with \"extent\" or \"extent-lonlat\"!");
+            std::process::exit(1)
+        }
+        let height: u32 = match config_options_table["map"].get("height") {
+            Some(&toml::Value::Integer(h)) => h as u32,
+            _ => {
+                println!("\"height\" is required (as an integer) when using \"center\"!");
+                std::process::exit(1)
+            }
+        };
+        let center = match *center_value {
+            toml::Value::Array(ref c) if c.len() == 2 => {
+                match (as_number(&c[0]), as_number(&c[1])) {
+                    (Some(lon), Some(lat)) => (lon, lat),
+                    _ => {
+                        println!("The values of \"center\" should be numbers!");
+                        std::process::exit(1)
+                    }
+                }
+            }
+            _ => {
+                println!("\"center\" should be an array of 2 values (longitude, latitude)!");
+                std::process::exit(1)
+            }
+        };
+        let center = if let Some((ref input_proj, ref output_proj)) = projs {
+            input_proj
+                .project(&output_proj, (center.0.to_radians(), center.1.to_radians()))
+                .unwrap_or_else(|_| {
+                                    println!("Unable to project the center of the map!");
+                                    std::process::exit(1)
+                                })
+        } else {
+            // The scale gives a size in meters, which is meaningless in longitude / latitude:
+            println!("\"center\" requires a \"projection\" (in meters) to apply the scale!");
+            std::process::exit(1)
+        };
+        let scale = MapScale::from_config(config_options_table["map"].as_table().unwrap())
+            .unwrap_or_else(|err| {
+                                println!("{}", err);
+                                std::process::exit(1)
+                            });
+        Converter::from_center(width, height, center, &scale)
+    } else {
+        // Compute the extent of the map, either from some layers, from a bbox
+        // in the map projection or from a bbox in longitude / latitude:
+        let padding = config_options_table["map"]
+            .get("extent-padding")
+            .map(|val| {
+                     Padding::from_config(val).unwrap_or_else(|| {
+                    println!("Invalid \"extent-padding\" value (expected a percentage or a value in px)!");
+                    std::process::exit(1)
+                })
+                 });
+        let (raw_extent, default_padding) = match (config_options_table["map"].get("extent"),
+                                config_options_table["map"].get("extent-lonlat")) {
+            (Some(_), Some(_)) => {
+                println!("Only one of \"extent\" and \"extent-lonlat\" can be provided!");
+                std::process::exit(1)
+            }
+            (None, Some(&toml::Value::Array(ref bbox))) if bbox.len() == 4 => {
+                let extent = expect_bbox(bbox, "extent-lonlat");
+                if let Some((ref input_proj, ref output_proj)) = projs {
+                    (project_extent(&extent, &input_proj, &output_proj, 50), None)
+                } else {
+                    (extent, None)
+                }
+            }
+            (None, Some(_)) => {
+                println!("\"extent-lonlat\" should be an array of 4 values!");
+                std::process::exit(1)
+            }
+            (Some(&toml::Value::Array(ref bbox)), None) if bbox.len() == 4 && !bbox[0].is_str() => {
+                let extent = expect_bbox(bbox, "extent");
+                (extent, None)
+            }
+            (Some(&toml::Value::Array(_)), None) if extent_layer_names.is_empty() => {
+                println!("\"extent\" should be a list of layer names or a list of 4 coordinates!");
+                std::process::exit(1)
+            }
+            (_, None) => {
+                // The extent is the union of some named layers (or of every layer if
+                // no extent is provided or if its value is "all"):
+                let mut extent = MapExtent::empty();
+                for &layer_name in &extent_layer_names {
+                    match geojson_layers.iter().find(|a| a.0 == layer_name) {
+                        Some(layer) => extent = extent.union(&get_extent(&layer.1)),
+                        None if streamed_layer_names.contains(&layer_name) => {
+                            let path = layer_paths.iter().find(|a| a.0 == layer_name).unwrap().1;
+                            let pipeline = streamed_pipelines.get_mut(layer_name).unwrap();
+                            for_each_streamed_feature(path, &projs, pipeline, |feature| {
+                                if let Some(geom) = feature.geometry {
+                                    extend_extent(&geom.value, &mut extent);
+                                }
+                            });
+                        }
+                        None => {
+                            println!("Unable to compute the extent: no layer named \"{}\"!",
+                                     layer_name);
+                            std::process::exit(1)
+                        }
+                    }
+                }
+                if extent.is_empty() {
+                    println!("Unable to compute the extent: the layers have no geometry!");
+                    std::process::exit(1)
+                }
+                (extent, Some(Padding::Percent(10.0)))
+            }
+        };
+        let padding = padding.or(default_padding);
+        // Give a size to the extent of a single point (10 km, or 0.1° without projection):
+        let raw_extent = raw_extent.with_minimum_size(if projs.is_some() { 10000.0 } else { 0.1 });
+
+        // The height can be omitted, in which case it's derived from the aspect
+        // ratio of the extent:
+        let height: u32 = match config_options_table["map"].get("height") {
+            Some(&toml::Value::Integer(h)) => h as u32,
+            Some(_) => {
+                println!("\"height\" should be an integer!");
+                std::process::exit(1)
+            }
+            None => raw_extent.fitting_height(width, padding.as_ref()),
+        };
+        let map_extent = match padding {
+            Some(ref p) => raw_extent.padded(p, (width, height)),
+            None => raw_extent,
+        };
+
+        Converter::new(width, height, map_extent, fit_mode)
+    };
+
+    // Read the remaining layers, only keeping the features visible on the map:
+    let filter = match projs {
+        Some((ref input_proj, ref output_proj)) => {
+            BboxFilter::LonLat(unproject_extent(&converter.visible_extent(), &output_proj, &input_proj, 20))
+        }
+        // The map is already in longitude / latitude:
+        None => BboxFilter::LonLat(converter.visible_extent()),
+    };
+    let geojson_layers = layer_paths
+        .iter()
+        .filter(|&&(name, _)| !streamed_layer_names.contains(&name))
+        .map(|&(name, path)| match geojson_layers.iter().position(|a| a.0 == name) {
+                 Some(ix) => geojson_layers.swap_remove(ix),
+                 None => {
+                     let (decoded_geojson, borders) = load_layer(name, path, Some(&filter));
+                     (name, decoded_geojson, borders)
+                 }
+             })
+        .collect::<Vec<(&str, geojson::GeoJson, Option<geojson::GeoJson>)>>();
 
     // Create a new svg document:
     let mut document = Document::new()
@@ -463,6 +2249,19 @@ fn main() {
         .set("width", format!("{}", converter.viewport_width))
         .set("height", format!("{}", converter.viewport_height));
 
+    // Font used by the title, the source and the labels:
+    let font_family = match config_options_table["map"].get("font-family") {
+        Some(&toml::Value::String(ref family)) => Some(family.as_str()),
+        Some(_) => {
+            println!("\"font-family\" should be a string!");
+            std::process::exit(1)
+        }
+        None => None,
+    };
+    if let Some(family) = font_family {
+        document = document.set("font-family", family);
+    }
+
     // Add an underlying rect if the "background" key is provided:
     if let Some(&toml::Value::String(ref bg_color)) =
         config_options_table["map"].get("background") {
@@ -491,11 +2290,40 @@ fn main() {
     //     SingleColorLayerProperties::default()
     // };
 
-    // Render each layer:
-    for (name, decoded_geojson) in geojson_layers {
+    // The features can be styled with CSS classes instead of presentation attributes:
+    let mut stylesheet = match config_options_table["map"].get("css-classes") {
+        Some(&toml::Value::Boolean(true)) => Some(StyleSheet::new()),
+        Some(&toml::Value::Boolean(false)) | None => None,
+        Some(_) => {
+            println!("\"css-classes\" should be a boolean!");
+            std::process::exit(1)
+        }
+    };
+
+    // Render each layer (keeping the content of their legends):
+    let mut legends = Vec::new();
+    let mut geojson_layers = geojson_layers.into_iter();
+    for &(name, path) in &layer_paths {
+        let class = css_class_name(name);
+        if streamed_layer_names.contains(&name) {
+            let (group, legend) = Renderer::render_streamed(&converter,
+                                                  name,
+                                                  path,
+                                                  &projs,
+                                                  config_options_table
+                                                      .get(name)
+                                                      .and_then(|t| t.as_table()),
+                                                  streamed_pipelines.get_mut(name).unwrap(),
+                                                  stylesheet.as_mut().map(|s| (class.as_str(), s)));
+            document = document.add(group.set("id", name));
+            legends.extend(legend.map(|legend| (name, legend)));
+            continue;
+        }
+        let (_, decoded_geojson, borders) = geojson_layers.next().unwrap();
+        let styles = stylesheet.as_mut().map(|s| (class.as_str(), s));
         let group = if !config_options_table.contains_key(name) {
             let layer_properties = SingleColorLayerProperties::default();
-            Renderer::render_unicolor(&converter, decoded_geojson, &layer_properties)
+            Renderer::render_unicolor(&converter, decoded_geojson, &layer_properties, styles)
 
         } else if !config_options_table[name]
                        .as_table()
@@ -503,7 +2331,7 @@ fn main() {
                        .contains_key("representation") {
             let layer_properties =
                 SingleColorLayerProperties::from_config(&config_options[name].as_table().unwrap());
-            Renderer::render_unicolor(&converter, decoded_geojson, &layer_properties)
+            Renderer::render_unicolor(&converter, decoded_geojson, &layer_properties, styles)
         } else {
             match config_options_table[name].get("representation") {
                 Some(&toml::Value::String(ref type_name)) => {
@@ -513,17 +2341,96 @@ fn main() {
                                                                        [type_name]
                                                                            .as_table()
                                                                            .unwrap());
-                        Renderer::render_choropleth(&converter, decoded_geojson, &layer_properties)
+                        let (group, legend) = Renderer::render_choropleth(&converter,
+                                                                          name,
+                                                                          decoded_geojson,
+                                                                          &layer_properties,
+                                                                          styles);
+                        legends.push((name, legend));
+                        group
+                    } else if type_name == "bivariate" {
+                        let layer_properties =
+                            BivariateLayerProperties::from_config(config_options_table[name]
+                                                                      [type_name]
+                                                                          .as_table()
+                                                                          .unwrap());
+                        let (group, legend) = Renderer::render_bivariate(&converter,
+                                                                         name,
+                                                                         decoded_geojson,
+                                                                         &layer_properties,
+                                                                         styles);
+                        legends.push((name, legend));
+                        group
+                    } else if type_name == "cartogram" {
+                        let (group, legend) = Renderer::render_cartogram(&converter,
+                                                                         name,
+                                                                         decoded_geojson,
+                                                                         config_options_table[name]
+                                                                             .as_table()
+                                                                             .unwrap(),
+                                                                         styles);
+                        legends.extend(legend.map(|legend| (name, legend)));
+                        group
+                    } else if type_name == "dotdensity" {
+                        let layer_properties =
+                            DotDensityLayerProperties::from_config(config_options_table[name]
+                                                                       [type_name]
+                                                                           .as_table()
+                                                                           .unwrap());
+                        Renderer::render_dotdensity(&converter,
+                                                    name,
+                                                    decoded_geojson,
+                                                    &layer_properties,
+                                                    styles)
                     } else {
-                        panic!("Invalid representation name");
+                        invalid_representation(name, &config_options_table[name]["representation"])
                     }
                 }
-                Some(&_) => panic!(""),
-                None => panic!(""),
+                Some(value) => invalid_representation(name, value),
+                None => unreachable!(),
             }
         };
         // let group = Renderer::render_unicolor(&converter, decoded_geojson, &layer_properties);
         document = document.add(group.set("id", name));
+
+        // Draw the interior borders of topological layers if requested:
+        if let (Some(borders), Some(&toml::Value::Table(ref borders_options))) =
+            (borders, config_options_table.get(name).and_then(|t| t.get("borders"))) {
+            let layer_properties = SingleColorLayerProperties::from_config(borders_options);
+            let borders_class = format!("{}-borders", class);
+            let group = Renderer::render_unicolor(&converter,
+                                                  borders,
+                                                  &layer_properties,
+                                                  stylesheet
+                                                      .as_mut()
+                                                      .map(|s| (borders_class.as_str(), s)));
+            document = document.add(group.set("id", format!("{}-borders", name)));
+        }
+    }
+
+    // Add the style rules of the layers, followed by the user stylesheet (if any):
+    let mut css = stylesheet.map(|s| s.to_css()).unwrap_or_default();
+    if let Some(stylesheet_path) = config_options_table["map"].get("stylesheet") {
+        let stylesheet_path = stylesheet_path.as_str().unwrap_or_else(|| {
+            println!("\"stylesheet\" should be the path of a CSS file!");
+            std::process::exit(1)
+        });
+        let mut user_css = String::new();
+        File::open(stylesheet_path)
+            .and_then(|mut f| f.read_to_string(&mut user_css))
+            .unwrap_or_else(|err| {
+                                println!("Unable to read stylesheet at path: \"{}\"\nError: {}",
+                                         stylesheet_path,
+                                         err);
+                                std::process::exit(1)
+                            });
+        css.push('\n');
+        css.push_str(&user_css);
+    }
+    if !css.trim().is_empty() {
+        // In a CDATA section, so that characters like "&" or "<" don't break the document:
+        let css = format!("<![CDATA[\n{}\n]]>", css.replace("]]>", "]]]]><![CDATA[>"));
+        document = document.add(Style::new().add(NodeText::new(css)));
     }
 
     // Add a graticule if requested:
@@ -536,6 +2443,18 @@ fn main() {
         document = document.add(group.set("id", "graticule"));
     }
 
+    // Add the legends of the layers having a "legend" section:
+    for (name, legend) in legends {
+        if let Some(&toml::Value::Table(ref legend_options)) =
+            config_options_table.get(name).and_then(|t| t.get("legend")) {
+            let options = LegendOptions::from_config(legend_options).unwrap_or_else(|err| {
+                println!("{}", err);
+                std::process::exit(1)
+            });
+            document = document.add(legend.render(&options).set("id", format!("{}-legend", name)));
+        }
+    }
+
     // Add the source section:
     if let Some(&toml::Value::Table(ref source_options)) = config_options_table.get("source") {
         if !source_options.contains_key("content") {
@@ -606,5 +2525,80 @@ fn main() {
             .add(NodeText::new(title_options["content"].as_str().unwrap()));
         document = document.add(text);
     }
-    svg::save(path_output, &document).unwrap();
+    let output_format = match matches.value_of("format") {
+        Some(name) => name.parse::<OutputFormat>().unwrap(),
+        None => OutputFormat::from_path(path_output),
+    };
+    if output_format == OutputFormat::Svg {
+        svg::save(path_output, &document).unwrap();
+    } else {
+        let fonts = FontConfig {
+            default_family: font_family,
+            paths: match config_options_table["map"].get("fonts") {
+                Some(&toml::Value::Array(ref paths)) => {
+                    paths.iter()
+                        .map(|p| {
+                                 p.as_str().unwrap_or_else(|| {
+                                println!("\"fonts\" should be a list of paths!");
+                                std::process::exit(1)
+                            })
+                             })
+                        .collect()
+                }
+                Some(_) => {
+                    println!("\"fonts\" should be a list of paths!");
+                    std::process::exit(1)
+                }
+                None => Vec::new(),
+            },
+        };
+        let map_options = config_options_table["map"].as_table().unwrap();
+        let res = if output_format == OutputFormat::Png {
+            raster_zoom(map_options).and_then(|zoom| save_png(&document, path_output, zoom, &fonts))
+        } else {
+            print_dpi(map_options).and_then(|dpi| save_pdf(&document, path_output, dpi, &fonts))
+        };
+        res.unwrap_or_else(|err| {
+                               println!("Unable to save the map: {}", err);
+                               std::process::exit(1)
+                           });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geojson::Geometry;
+
+    #[test]
+    fn renders_a_layer_of_a_single_point() {
+        let layer = GeoJson::from(FeatureCollection {
+                                      bbox: None,
+                                      foreign_members: None,
+                                      features: vec![Feature {
+                                                         geometry: Some(Geometry::new(Value::Point(vec![2.35, 48.85]))),
+                                                         properties: None,
+                                                         bbox: None,
+                                                         id: None,
+                                                         foreign_members: None,
+                                                     }],
+                                  });
+        let padding = Padding::Percent(10.0);
+        let extent = get_extent(&layer).with_minimum_size(0.1);
+        let height = extent.fitting_height(500, Some(&padding));
+        assert_eq!(height, 500);
+        let converter = Converter::new(500,
+                                       height,
+                                       extent.padded(&padding, (500, height)),
+                                       FitMode::Contain);
+        let (x, y) = converter.to_viewport(&[2.35, 48.85]);
+        assert!((x - 250.0).abs() < 1e-6 && (y - 250.0).abs() < 1e-6);
+        let group = Renderer::render_unicolor(&converter,
+                                              layer,
+                                              &SingleColorLayerProperties::from_config(&BTreeMap::new()),
+                                              None);
+        let svg = group.to_string();
+        assert!(svg.contains("<circle"), "{}", svg);
+        assert!(!svg.contains("NaN") && !svg.contains("inf"), "{}", svg);
+    }
 }
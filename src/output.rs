@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use resvg;
+use svg2pdf;
+use svg::Document;
+use tiny_skia::{Pixmap, Transform};
+use usvg::{FitTo, Options, Tree};
+
+/// Fonts available when rasterizing the map: the system fonts, plus the
+/// fonts found in the given files or directories.
+pub struct FontConfig<'a> {
+    /// Family used by the texts without `font-family` attribute.
+    pub default_family: Option<&'a str>,
+    pub paths: Vec<&'a str>,
+}
+
+/// Parses the document with `usvg`, making the given fonts available.
+fn load_tree(document: &Document, fonts: &FontConfig) -> Result<Tree, String> {
+    let mut opt = Options::default();
+    opt.fontdb.load_system_fonts();
+    for font_path in &fonts.paths {
+        if Path::new(font_path).is_dir() {
+            opt.fontdb.load_fonts_dir(font_path);
+        } else {
+            opt.fontdb.load_font_file(font_path).map_err(|err| err.to_string())?;
+        }
+    }
+    if let Some(family) = fonts.default_family {
+        opt.font_family = family.to_string();
+    }
+    Tree::from_str(&document.to_string(), &opt.to_ref()).map_err(|err| err.to_string())
+}
+
+/// Rasterizes the document and saves it as a PNG file, `zoom` being the
+/// ratio between the size of the image and the size of the SVG document.
+pub fn save_png(document: &Document,
+                path: &str,
+                zoom: f64,
+                fonts: &FontConfig)
+                -> Result<(), String> {
+    let tree = load_tree(document, fonts)?;
+    let size = tree.svg_node().size.to_screen_size();
+    let width = (size.width() as f64 * zoom).ceil() as u32;
+    let height = (size.height() as f64 * zoom).ceil() as u32;
+    let mut pixmap = Pixmap::new(width, height)
+        .ok_or_else(|| format!("Invalid image size: {}x{}", width, height))?;
+    resvg::render(&tree, FitTo::Zoom(zoom as f32), Transform::default(), pixmap.as_mut())
+        .ok_or_else(|| "Unable to rasterize the map!".to_string())?;
+    pixmap.save_png(path).map_err(|err| err.to_string())
+}
+
+/// Converts the document to a (vector) PDF file. The size of the page is the
+/// size of the map at the given resolution (in dots per inch).
+pub fn save_pdf(document: &Document,
+                path: &str,
+                dpi: f64,
+                fonts: &FontConfig)
+                -> Result<(), String> {
+    let tree = load_tree(document, fonts)?;
+    let mut options = svg2pdf::Options::default();
+    options.dpi = dpi;
+    let pdf = svg2pdf::convert_tree(&tree, options);
+    let mut file = File::create(path).map_err(|err| err.to_string())?;
+    file.write_all(&pdf).map_err(|err| err.to_string())
+}